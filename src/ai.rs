@@ -0,0 +1,367 @@
+// Built-in AI: tries every reachable placement of the current piece (and the
+// hold piece), scores the resulting boards, and plays the best one with the
+// same inputs a player would use.
+
+use std::time::Duration;
+
+use ggez::{Context, GameResult};
+use ggez::timer;
+
+use rand::Rng;
+
+use crate::game::{Game, Tets};
+use crate::input::Action;
+use crate::movegen::{self, Input, Placement};
+use crate::player::{Controller, Inputs};
+use crate::rules::Ruleset;
+use crate::tet::TetType;
+
+// How much each board feature counts towards a placement's score. Negative
+// weights are penalties.
+#[derive(Clone, Debug)]
+pub struct Weights {
+    // Sum of column heights
+    pub aggregate_height: f32,
+    // Empty cells with something above them
+    pub holes: f32,
+    // Sum of height differences between neighbouring columns
+    pub bumpiness: f32,
+    // Sum of well depths (columns lower than both neighbours)
+    pub wells: f32,
+    // Single, double, triple, tetris
+    pub line_clears: [f32; 4],
+    // T-spin single, double, triple
+    pub t_spin_clears: [f32; 3],
+    // Spots on the board shaped for a T-spin double
+    pub t_slots: f32,
+}
+
+impl Default for Weights {
+    fn default() -> Weights {
+        Weights {
+            aggregate_height: -0.51,
+            holes: -3.6,
+            bumpiness: -0.18,
+            wells: -0.3,
+            line_clears: [-1.0, -0.5, 0.5, 4.0],
+            t_spin_clears: [1.0, 5.0, 8.0],
+            t_slots: 1.5,
+        }
+    }
+}
+
+fn column_heights(tets: &Tets) -> Vec<i32> {
+    (0..tets.width())
+        .map(|col| tets.iter()
+            .position(|row| row[col].is_some())
+            .map_or(0, |row| (tets.height() - row) as i32))
+        .collect()
+}
+
+fn holes(tets: &Tets, heights: &[i32]) -> i32 {
+    let mut holes = 0;
+    for (col, &height) in heights.iter().enumerate() {
+        let top = tets.height() - height as usize;
+        holes += tets.iter().skip(top).filter(|row| row[col].is_none()).count() as i32;
+    }
+    holes
+}
+
+fn wells(heights: &[i32]) -> i32 {
+    let mut wells = 0;
+    for col in 0..heights.len() {
+        let left = if col == 0 { i32::MAX } else { heights[col - 1] };
+        let right = if col + 1 == heights.len() { i32::MAX } else { heights[col + 1] };
+        let depth = left.min(right) - heights[col];
+        if depth > 0 {
+            wells += depth;
+        }
+    }
+    wells
+}
+
+// Empty spots a T could spin into pointing down: three cells across with the
+// one below the middle also empty, the cells either side of that filled, and
+// an overhang on at least one side
+fn t_slots(tets: &Tets) -> i32 {
+    let filled = |row: i8, col: i8| {
+        tets.outside(row, col) || (row >= 0 && tets.at(row, col).is_some())
+    };
+    let mut slots = 0;
+    for row in 1..tets.height() as i8 - 1 {
+        for col in 1..tets.width() as i8 - 1 {
+            let open = !filled(row, col - 1) && !filled(row, col) && !filled(row, col + 1)
+                && !filled(row + 1, col);
+            let supported = filled(row + 1, col - 1) && filled(row + 1, col + 1);
+            let overhang = filled(row - 1, col - 1) || filled(row - 1, col + 1);
+            if open && supported && overhang {
+                slots += 1;
+            }
+        }
+    }
+    slots
+}
+
+// Score of a board on its own, ignoring how it got there
+pub fn evaluate(tets: &Tets, weights: &Weights) -> f32 {
+    let heights = column_heights(tets);
+    let aggregate_height: i32 = heights.iter().sum();
+    let bumpiness: i32 = heights.windows(2).map(|pair| (pair[0] - pair[1]).abs()).sum();
+    weights.aggregate_height * aggregate_height as f32
+        + weights.holes * holes(tets, &heights) as f32
+        + weights.bumpiness * bumpiness as f32
+        + weights.wells * wells(&heights) as f32
+        + weights.t_slots * t_slots(tets) as f32
+}
+
+fn clear_reward(lines: usize, t_spin: bool, weights: &Weights) -> f32 {
+    match (lines, t_spin) {
+        (0, _) => 0.0,
+        (1..=3, true) => weights.t_spin_clears[lines - 1],
+        (1..=4, _) => weights.line_clears[lines - 1],
+        _ => 0.0,
+    }
+}
+
+// Locks a placement into a copy of the board, returning the board and the
+// reward for any lines it cleared
+fn place(tets: &Tets, placement: &Placement, weights: &Weights) -> (Tets, f32) {
+    let mut after = tets.clone();
+    let lines = after.lock(&placement.tet);
+    (after, clear_reward(lines, placement.t_spin, weights))
+}
+
+// Best achievable score for a piece on this board, looking no further ahead
+fn best_score(tets: &Tets, tet_type: TetType, rules: &Ruleset, weights: &Weights) -> Option<f32> {
+    movegen::placements(tets, tet_type, rules)
+        .iter()
+        .map(|placement| {
+            let (after, reward) = place(tets, placement, weights);
+            reward + evaluate(&after, weights)
+        })
+        .fold(None, |best, score| Some(best.map_or(score, |best: f32| best.max(score))))
+}
+
+pub struct Plan {
+    // Swap with the hold piece before placing
+    pub hold: bool,
+    pub placement: Placement,
+    pub score: f32,
+}
+
+impl Plan {
+    pub fn inputs(&self) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        if self.hold {
+            inputs.push(Input::Tap(Action::Hold));
+        }
+        inputs.extend(self.placement.inputs.iter().cloned());
+        inputs
+    }
+}
+
+// Everything the AI knows when choosing a move
+pub struct Situation<'a> {
+    pub tets: &'a Tets,
+    pub current: TetType,
+    pub held: Option<TetType>,
+    pub queue: &'a [TetType],
+    pub can_hold: bool,
+    // The rules the pieces move under
+    pub rules: &'a Ruleset,
+}
+
+impl<'a> Situation<'a> {
+    pub fn of(game: &'a Game) -> Option<Situation<'a>> {
+        game.current_tet().map(|tet| Situation {
+            tets: game.tets(),
+            current: tet.tet_type,
+            held: game.held_tet(),
+            queue: game.queue(),
+            can_hold: game.can_hold(),
+            rules: game.rules(),
+        })
+    }
+}
+
+// Every candidate move, best first. With `lookahead`, each is also scored by
+// the best follow-up placement of the next piece.
+pub fn plans(situation: &Situation, weights: &Weights, lookahead: bool) -> Vec<Plan> {
+    // (use hold, piece to place, piece that comes next)
+    let mut options = vec![(false, situation.current, situation.queue.first().cloned())];
+    if situation.can_hold {
+        match situation.held {
+            Some(held) if held != situation.current => {
+                options.push((true, held, situation.queue.first().cloned()));
+            },
+            None => if let Some(&first) = situation.queue.first() {
+                options.push((true, first, situation.queue.get(1).cloned()));
+            },
+            _ => (),
+        }
+    }
+
+    let mut plans = Vec::new();
+    for (hold, tet_type, next) in options {
+        for placement in movegen::placements(situation.tets, tet_type, situation.rules) {
+            let (after, reward) = place(situation.tets, &placement, weights);
+            let future = match next {
+                Some(next) if lookahead => best_score(&after, next, situation.rules, weights),
+                _ => None,
+            };
+            let score = reward + future.unwrap_or_else(|| evaluate(&after, weights));
+            plans.push(Plan { hold, placement, score });
+        }
+    }
+    plans.sort_by(|a, b| b.score.total_cmp(&a.score));
+    plans
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+impl Difficulty {
+    pub fn from_name(name: &str) -> Option<Difficulty> {
+        match name {
+            "easy" => Some(Difficulty::Easy),
+            "medium" => Some(Difficulty::Medium),
+            "hard" => Some(Difficulty::Hard),
+            _ => None,
+        }
+    }
+
+    fn input_interval(&self) -> Duration {
+        match self {
+            Difficulty::Easy => Duration::from_millis(250),
+            Difficulty::Medium => Duration::from_millis(120),
+            Difficulty::Hard => Duration::from_millis(40),
+        }
+    }
+
+    // Chance of picking a worse move than the best one found
+    fn blunder_chance(&self) -> f64 {
+        match self {
+            Difficulty::Easy => 0.3,
+            Difficulty::Medium => 0.1,
+            Difficulty::Hard => 0.0,
+        }
+    }
+
+    fn lookahead(&self) -> bool {
+        matches!(self, Difficulty::Hard)
+    }
+}
+
+// Plays a game with the built-in AI
+pub struct AiController {
+    weights: Weights,
+    difficulty: Difficulty,
+    inputs: Inputs,
+    // Value of `game.placed()` the last plan was made for
+    planned_for: Option<usize>,
+    // Restart the game after topping out, for attract mode
+    attract: bool,
+    restart_timer: Duration,
+}
+
+impl AiController {
+    const RESTART_WAIT: Duration = Duration::from_secs(3);
+
+    pub fn new(difficulty: Difficulty, weights: Weights) -> AiController {
+        AiController {
+            weights,
+            difficulty,
+            inputs: Inputs::new(difficulty.input_interval()),
+            planned_for: None,
+            attract: false,
+            restart_timer: Self::RESTART_WAIT,
+        }
+    }
+
+    // An AI that plays forever, for demos
+    pub fn attract() -> AiController {
+        AiController {
+            attract: true,
+            ..AiController::new(Difficulty::Hard, Weights::default())
+        }
+    }
+
+    fn plan(&self, game: &Game) -> Vec<Input> {
+        let situation = match Situation::of(game) {
+            Some(situation) => situation,
+            None => return Vec::new(),
+        };
+        let mut plans = plans(&situation, &self.weights, self.difficulty.lookahead());
+        if plans.is_empty() {
+            return vec![Input::Tap(Action::HardDrop)];
+        }
+        let mut rng = rand::thread_rng();
+        let mut choice = 0;
+        while choice + 1 < plans.len().min(5) && rng.gen_bool(self.difficulty.blunder_chance()) {
+            choice += 1;
+        }
+        plans.swap_remove(choice).inputs()
+    }
+}
+
+impl Controller for AiController {
+    fn control(&mut self, ctx: &mut Context, game: &mut Game) -> GameResult {
+        if game.is_dead() {
+            self.inputs.clear(game);
+            self.planned_for = None;
+            if self.attract {
+                self.restart_timer = self.restart_timer
+                    .checked_sub(timer::delta(ctx))
+                    .unwrap_or_default();
+                if self.restart_timer == Duration::from_millis(0) {
+                    game.restart();
+                    self.restart_timer = Self::RESTART_WAIT;
+                }
+            }
+            return Ok(());
+        }
+
+        if self.inputs.is_empty() && game.current_tet().is_some()
+            && self.planned_for != Some(game.placed()) {
+            let inputs = self.plan(game);
+            self.inputs.set(inputs);
+            self.planned_for = Some(game.placed());
+        }
+        self.inputs.step(ctx, game);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::test_boards::board;
+
+    fn best(tets: &Tets, current: TetType, held: Option<TetType>, can_hold: bool) -> Plan {
+        let rules = Ruleset::default();
+        let situation = Situation { tets, current, held, queue: &[], can_hold, rules: &rules };
+        plans(&situation, &Weights::default(), false).swap_remove(0)
+    }
+
+    #[test]
+    fn clears_rather_than_making_a_hole() {
+        let tets = board(&["####...###"]);
+        let plan = best(&tets, TetType::T, None, false);
+        let mut after = tets.clone();
+        assert_eq!(after.lock(&plan.placement.tet), 1);
+        assert_eq!(holes(&after, &column_heights(&after)), 0);
+    }
+
+    #[test]
+    fn holds_when_that_scores_better() {
+        let tets = board(&["#########."; 4]);
+        let plan = best(&tets, TetType::S, Some(TetType::I), true);
+        assert!(plan.hold);
+        assert_eq!(tets.clone().lock(&plan.placement.tet), 4);
+        assert!(!best(&tets, TetType::S, Some(TetType::I), false).hold);
+    }
+}
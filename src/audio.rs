@@ -0,0 +1,287 @@
+// Sound effects and music. Both come from a sound pack: a directory under
+// /sounds with a file for each effect, named after it (move.wav,
+// t_spin.ogg, ...), and looping tracks in its music directory. Whatever a
+// pack leaves out is silent.
+
+use std::collections::HashMap;
+
+use ggez::Context;
+use ggez::audio::{SoundSource, Source};
+use ggez::filesystem;
+
+use crate::save;
+
+pub const SOUNDS_DIR: &str = "/sounds";
+pub const DEFAULT_PACK: &str = "default";
+// Formats looked for, in this order
+const EXTENSIONS: [&str; 3] = ["ogg", "wav", "flac"];
+// The track played when there's none for the mode
+const GAME_TRACK: &str = "game";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Sound {
+    Move,
+    Rotate,
+    // A turn that only fit by moving the piece
+    Kick,
+    SoftDrop,
+    HardDrop,
+    Lock,
+    Hold,
+    Single,
+    Double,
+    Triple,
+    Tetris,
+    TSpin,
+    // The clears in a row after the first
+    Combo(usize),
+    LevelUp,
+    TopOut,
+    // Each of the last seconds of a timed mode
+    Countdown,
+}
+
+impl Sound {
+    pub const ALL: [Sound; 16] = [
+        Sound::Move,
+        Sound::Rotate,
+        Sound::Kick,
+        Sound::SoftDrop,
+        Sound::HardDrop,
+        Sound::Lock,
+        Sound::Hold,
+        Sound::Single,
+        Sound::Double,
+        Sound::Triple,
+        Sound::Tetris,
+        Sound::TSpin,
+        Sound::Combo(0),
+        Sound::LevelUp,
+        Sound::TopOut,
+        Sound::Countdown,
+    ];
+
+    // The file name, less its extension
+    pub fn name(self) -> &'static str {
+        match self {
+            Sound::Move => "move",
+            Sound::Rotate => "rotate",
+            Sound::Kick => "kick",
+            Sound::SoftDrop => "soft_drop",
+            Sound::HardDrop => "hard_drop",
+            Sound::Lock => "lock",
+            Sound::Hold => "hold",
+            Sound::Single => "single",
+            Sound::Double => "double",
+            Sound::Triple => "triple",
+            Sound::Tetris => "tetris",
+            Sound::TSpin => "t_spin",
+            Sound::Combo(_) => "combo",
+            Sound::LevelUp => "level_up",
+            Sound::TopOut => "top_out",
+            Sound::Countdown => "countdown",
+        }
+    }
+
+    // Each step of a combo plays a little higher
+    fn pitch(self) -> f32 {
+        match self {
+            Sound::Combo(step) => 1.0 + 0.06 * step.min(12) as f32,
+            _ => 1.0,
+        }
+    }
+
+    // The sound for clearing lines
+    pub fn clear(lines: usize, t_spin: bool) -> Option<Sound> {
+        match (lines, t_spin) {
+            (_, true) => Some(Sound::TSpin),
+            (0, _) => None,
+            (1, _) => Some(Sound::Single),
+            (2, _) => Some(Sound::Double),
+            (3, _) => Some(Sound::Triple),
+            _ => Some(Sound::Tetris),
+        }
+    }
+}
+
+// Every pack installed, from the game's resources and the user data
+// directory alike
+pub fn packs(ctx: &mut Context) -> Vec<String> {
+    save::list_dirs(ctx, SOUNDS_DIR)
+}
+
+// The file for `name` in a pack directory, in whichever format it's in
+fn find(ctx: &mut Context, dir: &str, name: &str) -> Option<String> {
+    EXTENSIONS.iter()
+        .map(|extension| format!("{}/{}.{}", dir, name, extension))
+        .find(|path| filesystem::exists(ctx, path))
+}
+
+fn load(ctx: &mut Context, path: &str, volume: f32) -> Option<Source> {
+    match Source::new(ctx, path) {
+        Ok(mut source) => {
+            source.set_volume(volume);
+            Some(source)
+        },
+        Err(err) => {
+            eprintln!("{}: couldn't load: {}", path, err);
+            None
+        },
+    }
+}
+
+// A game's sound effects. The game cues them as things happen, without a
+// context to hand, and they're played on its next update.
+pub struct Effects {
+    pack: String,
+    volume: f32,
+    // The pack the sources are from, once loaded
+    loaded: Option<String>,
+    sources: HashMap<&'static str, Source>,
+    cued: Vec<Sound>,
+}
+
+impl Effects {
+    pub fn new() -> Effects {
+        Effects {
+            pack: DEFAULT_PACK.to_string(),
+            volume: 1.0,
+            loaded: None,
+            sources: HashMap::new(),
+            cued: Vec::new(),
+        }
+    }
+
+    pub fn set_pack(&mut self, pack: &str) {
+        self.pack = pack.to_string();
+    }
+
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume;
+        for source in self.sources.values_mut() {
+            source.set_volume(volume);
+        }
+    }
+
+    pub fn cue(&mut self, sound: Sound) {
+        self.cued.push(sound);
+    }
+
+    // Plays everything cued since last time, first loading the pack if it's
+    // changed. Nothing is loaded while muted.
+    pub fn play(&mut self, ctx: &mut Context) {
+        if self.volume <= 0.0 {
+            self.cued.clear();
+            return;
+        }
+        if self.loaded.as_ref() != Some(&self.pack) {
+            let dir = format!("{}/{}", SOUNDS_DIR, self.pack);
+            self.sources.clear();
+            for sound in Sound::ALL.iter() {
+                let source = find(ctx, &dir, sound.name())
+                    .and_then(|path| load(ctx, &path, self.volume));
+                if let Some(source) = source {
+                    self.sources.insert(sound.name(), source);
+                }
+            }
+            self.loaded = Some(self.pack.clone());
+        }
+        for sound in self.cued.drain(..) {
+            if let Some(source) = self.sources.get_mut(sound.name()) {
+                source.set_pitch(sound.pitch());
+                if let Err(err) = source.play() {
+                    eprintln!("{}: couldn't play: {}", sound.name(), err);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Effects {
+    fn default() -> Effects {
+        Effects::new()
+    }
+}
+
+// Background music for a mode, looping. A pack's music directory can have a
+// track named after the mode, or else one named "game", to play from the
+// start; tracks numbered for a level, like "game-10", take over from that
+// level on.
+pub struct Music {
+    volume: f32,
+    // Paths of the pack's tracks, by name
+    tracks: HashMap<String, String>,
+    // The track chosen last, and its source if it could be loaded
+    track: Option<String>,
+    source: Option<Source>,
+}
+
+impl Music {
+    pub fn new(ctx: &mut Context, pack: &str, volume: f32) -> Music {
+        let dir = format!("{}/{}/music", SOUNDS_DIR, pack);
+        let tracks = filesystem::read_dir(ctx, &dir)
+            .map(|entries| entries
+                .filter(|path| path.extension()
+                    .is_some_and(|extension| EXTENSIONS.iter().any(|&known| extension == known)))
+                .filter_map(|path| {
+                    let name = path.file_stem()?.to_string_lossy().into_owned();
+                    Some((name, path.to_string_lossy().into_owned()))
+                })
+                .collect())
+            .unwrap_or_default();
+        Music { volume, tracks, track: None, source: None }
+    }
+
+    // The track for a mode at a level, if the pack has one
+    fn choose(&self, mode: &str, level: usize) -> Option<String> {
+        [mode, GAME_TRACK].iter().find_map(|&base| {
+            let numbered = self.tracks.keys()
+                .filter_map(|name| {
+                    let from: usize = name.strip_prefix(base)?.strip_prefix('-')?.parse().ok()?;
+                    Some((from, name))
+                })
+                .filter(|&(from, _)| from <= level)
+                .max();
+            match numbered {
+                Some((_, name)) => Some(name.clone()),
+                None if self.tracks.contains_key(base) => Some(base.to_string()),
+                None => None,
+            }
+        })
+    }
+
+    // Keeps the right track going while the game is, and pauses it while
+    // it's not
+    pub fn update(&mut self, ctx: &mut Context, mode: &str, level: usize, playing: bool) {
+        if self.volume <= 0.0 {
+            return;
+        }
+        if !playing {
+            if let Some(source) = &self.source {
+                source.pause();
+            }
+            return;
+        }
+        let track = self.choose(mode, level);
+        if track == self.track {
+            if let Some(source) = self.source.as_ref().filter(|source| source.paused()) {
+                source.resume();
+            }
+            return;
+        }
+        // Dropping the old track stops it
+        self.source = track.as_ref()
+            .and_then(|track| load(ctx, &self.tracks[track], self.volume))
+            .and_then(|mut source| {
+                source.set_repeat(true);
+                match source.play() {
+                    Ok(()) => Some(source),
+                    Err(err) => {
+                        eprintln!("music: couldn't play: {}", err);
+                        None
+                    },
+                }
+            });
+        self.track = track;
+    }
+}
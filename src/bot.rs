@@ -0,0 +1,439 @@
+// Driver for external bots speaking the Tetris Bot Protocol (TBP): one JSON
+// message per line over the child process' stdin/stdout.
+// https://github.com/tetris-bot-protocol/tbp-spec
+
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ggez::{Context, GameError, GameResult};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{Block, Game, Tets};
+use crate::input::Action;
+use crate::movegen::{self, Input};
+use crate::player::{Controller, Inputs};
+use crate::tet::{Rot, Tet, TetType};
+
+type Point2 = ggez::nalgebra::Point2<i8>;
+
+// TBP boards are 40 rows tall, with row 0 at the bottom
+const BOARD_HEIGHT: usize = 40;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl From<Rot> for Orientation {
+    fn from(rot: Rot) -> Self {
+        match rot {
+            Rot::Zero => Orientation::North,
+            Rot::R    => Orientation::East,
+            Rot::Two  => Orientation::South,
+            Rot::L    => Orientation::West,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Location {
+    #[serde(rename = "type")]
+    pub tet_type: TetType,
+    pub orientation: Orientation,
+    pub x: i8,
+    pub y: i8,
+}
+
+impl Location {
+    // Cells covered by the piece, in board coordinates (row 0 at the top) on
+    // a board `height` rows high
+    pub fn cells(&self, height: usize) -> Vec<Point2> {
+        self.tet(height).cells()
+    }
+
+    // The piece there, on a board `height` rows high
+    pub fn tet(&self, height: usize) -> Tet {
+        let rot = match self.orientation {
+            Orientation::North => Rot::Zero,
+            Orientation::East  => Rot::R,
+            Orientation::South => Rot::Two,
+            Orientation::West  => Rot::L,
+        };
+        Tet::from_center(self.tet_type, rot, self.x, self.y, height)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Move {
+    pub location: Location,
+    pub spin: String,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum FrontendMessage {
+    Rules,
+    Start {
+        hold: Option<TetType>,
+        queue: Vec<TetType>,
+        combo: u32,
+        back_to_back: bool,
+        board: Vec<Vec<Option<Block>>>,
+    },
+    Suggest,
+    Play {
+        #[serde(rename = "move")]
+        mv: Move,
+    },
+    NewPiece {
+        piece: TetType,
+    },
+    Stop,
+    Quit,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum BotMessage {
+    Error {
+        reason: String,
+    },
+    Ready,
+    Info {
+        name: String,
+        version: String,
+        author: String,
+    },
+    Suggestion {
+        moves: Vec<Move>,
+    },
+}
+
+fn tbp_board(tets: &Tets) -> Vec<Vec<Option<Block>>> {
+    let mut board = vec![vec![None; tets.width()]; BOARD_HEIGHT];
+    for (y, row) in tets.iter().enumerate() {
+        board[tets.height() - 1 - y] = row.to_vec();
+    }
+    board
+}
+
+struct Bot {
+    // Taken when the bot is dropped, to be reaped off the UI thread
+    process: Option<Child>,
+    stdin: ChildStdin,
+    messages: Receiver<BotMessage>,
+}
+
+impl Bot {
+    // How long a bot gets to quit before it's killed
+    const QUIT_TIMEOUT: Duration = Duration::from_millis(500);
+
+    fn spawn(command: &[String]) -> GameResult<Bot> {
+        let (program, args) = command.split_first().ok_or_else(|| {
+            GameError::ConfigError("no bot command given".to_string())
+        })?;
+        let mut process = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let stdin = process.stdin.take().unwrap();
+        let stdout = process.stdout.take().unwrap();
+
+        // Read on a separate thread so a slow bot never stalls a frame
+        let (sender, messages) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                // Unknown message types are allowed by the protocol
+                if let Ok(message) = serde_json::from_str(&line) {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Bot { process: Some(process), stdin, messages })
+    }
+
+    fn send(&mut self, message: &FrontendMessage) -> GameResult {
+        let line = serde_json::to_string(message)
+            .map_err(|err| GameError::ResourceLoadError(err.to_string()))?;
+        writeln!(self.stdin, "{}", line)?;
+        self.stdin.flush()?;
+        Ok(())
+    }
+
+    fn recv(&self) -> GameResult<Option<BotMessage>> {
+        match self.messages.try_recv() {
+            Ok(message) => Ok(Some(message)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => {
+                Err(GameError::ResourceLoadError("bot process exited".to_string()))
+            },
+        }
+    }
+}
+
+// Asks the bot to quit, and leaves a thread to kill it if it doesn't, so
+// leaving a game never waits on the bot
+impl Drop for Bot {
+    fn drop(&mut self) {
+        let _ = self.send(&FrontendMessage::Quit);
+        let mut process = match self.process.take() {
+            Some(process) => process,
+            None => return,
+        };
+        thread::spawn(move || {
+            let deadline = Instant::now() + Self::QUIT_TIMEOUT;
+            while Instant::now() < deadline {
+                match process.try_wait() {
+                    Ok(None) => thread::sleep(Duration::from_millis(10)),
+                    _ => return,
+                }
+            }
+            let _ = process.kill();
+            let _ = process.wait();
+        });
+    }
+}
+
+enum BotState {
+    // Waiting for the bot to introduce itself
+    Starting,
+    // Waiting for the bot to accept the rules
+    Configuring,
+    Idle,
+    Thinking,
+    Moving,
+    // The game ended, waiting for it to be restarted
+    GameOver,
+    Stopped,
+}
+
+// Plays a game by asking an external bot where to put each piece
+pub struct TbpBot {
+    bot: Bot,
+    state: BotState,
+    // Current piece followed by the queue, as the bot last heard it
+    bot_queue: VecDeque<TetType>,
+    bot_hold: Option<TetType>,
+    // The board as the bot last heard it, with its own moves played
+    bot_tets: Tets,
+    // Value of `game.placed()` the last suggestion was requested for
+    planned_for: Option<usize>,
+    inputs: Inputs,
+}
+
+impl TbpBot {
+    // Delay between simulated key presses, so the bot can be watched
+    const INPUT_INTERVAL: Duration = Duration::from_millis(50);
+
+    pub fn new(command: &[String]) -> GameResult<TbpBot> {
+        Ok(TbpBot {
+            bot: Bot::spawn(command)?,
+            state: BotState::Starting,
+            bot_queue: VecDeque::new(),
+            bot_hold: None,
+            bot_tets: Tets::default(),
+            planned_for: None,
+            inputs: Inputs::new(Self::INPUT_INTERVAL),
+        })
+    }
+
+    fn start(&mut self, game: &Game) -> GameResult {
+        // TBP only knows the standard board and pieces
+        if !game.tets().is_standard() {
+            return Err(GameError::ConfigError("bots can only play on a 10x20 board".to_string()));
+        }
+        if !game.pieces().is_standard() {
+            return Err(GameError::ConfigError("bots can only play with tetrominoes".to_string()));
+        }
+        let current = match game.current_tet() {
+            Some(tet) => tet.tet_type,
+            None => return Ok(()),
+        };
+        self.bot_queue = std::iter::once(current)
+            .chain(game.queue().iter().cloned())
+            .collect();
+        self.bot_hold = game.held_tet();
+        self.bot_tets = game.tets().clone();
+        // TBP counts every clear in a row, the first included
+        let stats = game.stats();
+        self.bot.send(&FrontendMessage::Start {
+            hold: self.bot_hold,
+            queue: self.bot_queue.iter().cloned().collect(),
+            combo: stats.combo.map_or(0, |combo| combo as u32 + 1),
+            back_to_back: stats.b2b.is_some(),
+            board: tbp_board(game.tets()),
+        })?;
+        self.planned_for = None;
+        self.state = BotState::Idle;
+        Ok(())
+    }
+
+    // Tell the bot about pieces revealed since it last heard, restarting it
+    // if its idea of the board or queue has drifted from the game's, e.g.
+    // after topping out cleared the board
+    fn sync_queue(&mut self, game: &Game) -> GameResult {
+        let current = match game.current_tet() {
+            Some(tet) => tet.tet_type,
+            None => return Ok(()),
+        };
+        let actual: Vec<TetType> = std::iter::once(current)
+            .chain(game.queue().iter().cloned())
+            .collect();
+        let known = self.bot_queue.len();
+        let in_sync = actual.len() >= known
+            && self.bot_queue.iter().zip(actual.iter()).all(|(a, b)| a == b)
+            && self.bot_hold == game.held_tet()
+            && self.bot_tets.iter().eq(game.tets().iter());
+        if !in_sync {
+            self.bot.send(&FrontendMessage::Stop)?;
+            return self.start(game);
+        }
+        for &piece in &actual[known..] {
+            self.bot.send(&FrontendMessage::NewPiece { piece })?;
+            self.bot_queue.push_back(piece);
+        }
+        Ok(())
+    }
+
+    fn play(&mut self, mv: Move, game: &mut Game) -> GameResult {
+        let target = mv.location.cells(game.tets().height());
+        let placed_type = mv.location.tet_type;
+        let mut inputs = Vec::new();
+
+        // Work out which piece the move uses, mirroring what the bot will do
+        // to its own queue and hold
+        let front = self.bot_queue.pop_front();
+        let piece = if front == Some(placed_type) {
+            front
+        } else {
+            inputs.push(Input::Tap(Action::Hold));
+            match self.bot_hold.replace(front.unwrap_or(placed_type)) {
+                Some(held) => Some(held),
+                None => self.bot_queue.pop_front(),
+            }
+        };
+        if piece != Some(placed_type) {
+            game.set_status(Some(format!("Bot suggested a {} that isn't available", placed_type.name())));
+            self.bot.send(&FrontendMessage::Stop)?;
+            return self.start(game);
+        }
+
+        match movegen::find(game.tets(), placed_type, &target, game.rules()) {
+            Some(placement) => inputs.extend(placement.inputs),
+            None => {
+                game.set_status(Some(format!("Bot's {} can't get there, hard dropping", placed_type.name())));
+                inputs.push(Input::Tap(Action::HardDrop));
+            },
+        }
+
+        self.bot_tets.lock(&mv.location.tet(game.tets().height()));
+        self.bot.send(&FrontendMessage::Play { mv })?;
+        self.inputs.set(inputs);
+        self.state = BotState::Moving;
+        Ok(())
+    }
+
+    // Gives up on the bot, saying why beside its game
+    fn stop(&mut self, game: &mut Game, reason: String) {
+        self.inputs.clear(game);
+        game.set_status(Some(format!("Bot stopped: {}", reason)));
+        self.state = BotState::Stopped;
+    }
+
+    fn handle(&mut self, message: BotMessage, game: &mut Game) -> GameResult {
+        match (&self.state, message) {
+            (_, BotMessage::Error { reason }) => self.stop(game, format!("error: {}", reason)),
+            (BotState::Starting, BotMessage::Info { name, version, author }) => {
+                game.set_status(Some(format!("{} {} by {}", name, version, author)));
+                self.bot.send(&FrontendMessage::Rules)?;
+                self.state = BotState::Configuring;
+            },
+            (BotState::Configuring, BotMessage::Ready) => self.start(game)?,
+            (BotState::Thinking, BotMessage::Suggestion { moves }) => {
+                // TBP can't say hold is off, so moves that hold are passed
+                // over when it is
+                let current = self.bot_queue.front().copied();
+                let allow_hold = game.rules().allow.hold;
+                match moves.into_iter().find(|mv| allow_hold || Some(mv.location.tet_type) == current) {
+                    Some(mv) => self.play(mv, game)?,
+                    None if allow_hold => self.stop(game, "no moves suggested".to_string()),
+                    None => self.stop(game, "only moves using hold suggested".to_string()),
+                }
+            },
+            (_, message) => game.set_status(Some(format!("Bot sent an unexpected {:?}", message))),
+        }
+        Ok(())
+    }
+
+    fn step(&mut self, ctx: &mut Context, game: &mut Game) -> GameResult {
+        while let Some(message) = self.bot.recv()? {
+            self.handle(message, game)?;
+            if let BotState::Stopped = self.state {
+                return Ok(());
+            }
+        }
+
+        if game.is_dead() {
+            if let BotState::Idle | BotState::Thinking | BotState::Moving = self.state {
+                self.bot.send(&FrontendMessage::Stop)?;
+                self.inputs.clear(game);
+                self.state = BotState::GameOver;
+            }
+            return Ok(());
+        }
+
+        match self.state {
+            BotState::Idle if game.current_tet().is_some()
+                && self.planned_for != Some(game.placed()) => {
+                self.sync_queue(game)?;
+                self.bot.send(&FrontendMessage::Suggest)?;
+                self.planned_for = Some(game.placed());
+                self.state = BotState::Thinking;
+            },
+            BotState::Moving => {
+                self.inputs.step(ctx, game);
+                if self.inputs.is_empty() {
+                    self.state = BotState::Idle;
+                }
+            },
+            BotState::GameOver => self.start(game)?,
+            _ => (),
+        }
+        Ok(())
+    }
+}
+
+// A bot that crashes, or can't play this game, stops playing rather than
+// taking the whole program down with it
+impl Controller for TbpBot {
+    fn control(&mut self, ctx: &mut Context, game: &mut Game) -> GameResult {
+        if let BotState::Stopped = self.state {
+            return Ok(());
+        }
+        if let Err(err) = self.step(ctx, game) {
+            let reason = match err {
+                GameError::ConfigError(reason) | GameError::ResourceLoadError(reason) => reason,
+                err => err.to_string(),
+            };
+            self.stop(game, reason);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,451 @@
+// The board editor: cells are painted onto the board with the mouse, in any
+// piece's colour or as garbage, and the queue, hold, goal and move limit set
+// from the keyboard. What's made can be played straight away, exported as a
+// fumen (which the practice menu reads) or saved as a puzzle in the custom
+// pack.
+
+use ggez::{Context, GameError, GameResult};
+use ggez::event::{KeyCode, KeyMods, MouseButton};
+use ggez::graphics::{self, Color, DrawParam};
+
+use crate::fumen;
+use crate::game::{self, Block, Tets, SIDEBAR_WIDTH, TILE_SIZE};
+use crate::menu;
+use crate::pieces::PieceSet;
+use crate::puzzle::{Goal, Pack, Puzzle};
+use crate::scene::{Scene, Transition};
+use crate::settings::Settings;
+use crate::skin::Skin;
+use crate::tet::TetType;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+// Room right of the board for the queue and help
+const PANEL_WIDTH: f32 = 160.0;
+
+// The palette left of the board: a swatch per brush, top to bottom
+const PALETTE_X: f32 = 20.0;
+const PALETTE_Y: f32 = 40.0;
+const SWATCH_STEP: f32 = TILE_SIZE + 6.0;
+
+const HELP: [&str; 11] = [
+    "Mouse: paint, erase",
+    "1-8: colour",
+    "IJLOSTZ: add piece",
+    "Shift+piece: hold",
+    "Back: remove piece",
+    "Tab, Up/Down: goal",
+    "Left/Right: moves",
+    "Delete: clear board",
+    "P: play",
+    "F: export fumen",
+    "Enter: save puzzle",
+];
+
+// What painting puts in a cell: each piece's colour, then garbage
+fn brushes() -> Vec<Block> {
+    TetType::STANDARD.iter()
+        .map(|&tet_type| Block::Tet(tet_type))
+        .chain(Some(Block::Garbage))
+        .collect()
+}
+
+fn piece_key(keycode: KeyCode) -> Option<TetType> {
+    match keycode {
+        KeyCode::I => Some(TetType::I),
+        KeyCode::J => Some(TetType::J),
+        KeyCode::L => Some(TetType::L),
+        KeyCode::O => Some(TetType::O),
+        KeyCode::S => Some(TetType::S),
+        KeyCode::T => Some(TetType::T),
+        KeyCode::Z => Some(TetType::Z),
+        _ => None,
+    }
+}
+
+fn number_key(keycode: KeyCode) -> Option<usize> {
+    let keys = [
+        KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+        KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8,
+    ];
+    keys.iter().position(|&key| key == keycode)
+}
+
+pub struct Editor {
+    tets: Tets,
+    // Starting with the piece first in play
+    queue: Vec<TetType>,
+    held: Option<TetType>,
+    goal: Goal,
+    moves: Option<usize>,
+    // Given when first saved as a puzzle, unless editing one that has one
+    name: Option<String>,
+    brush: Block,
+    // What dragging with a button down paints
+    painting: Option<Option<Block>>,
+    message: Option<String>,
+    // The skin chosen in the settings, loaded when first drawn
+    skin: Option<Skin>,
+}
+
+impl Editor {
+    pub fn new() -> Editor {
+        Editor {
+            tets: Tets::default(),
+            queue: Vec::new(),
+            held: None,
+            goal: Goal::Lines(4),
+            moves: None,
+            name: None,
+            brush: Block::Garbage,
+            painting: None,
+            message: None,
+            skin: None,
+        }
+    }
+
+    pub fn editing(puzzle: Puzzle) -> Editor {
+        Editor {
+            tets: puzzle.start,
+            queue: puzzle.queue,
+            goal: puzzle.goal,
+            moves: puzzle.moves,
+            name: Some(puzzle.name),
+            ..Editor::new()
+        }
+    }
+
+    // The board cell under a point in the window
+    fn cell(&self, x: f32, y: f32) -> Option<(i8, i8)> {
+        let col = (x / TILE_SIZE - SIDEBAR_WIDTH).floor();
+        let row = (y / TILE_SIZE).floor();
+        if col < 0.0 || row < 0.0 || self.tets.outside(row as i8, col as i8) {
+            return None;
+        }
+        Some((row as i8, col as i8))
+    }
+
+    // The palette swatch under a point in the window
+    fn swatch(&self, x: f32, y: f32) -> Option<Block> {
+        let index = ((y - PALETTE_Y) / SWATCH_STEP).floor();
+        let inside = (PALETTE_X..PALETTE_X + TILE_SIZE).contains(&x)
+            && index >= 0.0 && y - PALETTE_Y - index * SWATCH_STEP < TILE_SIZE;
+        if !inside {
+            return None;
+        }
+        brushes().get(index as usize).copied()
+    }
+
+    fn paint(&mut self, x: f32, y: f32) {
+        if let (Some(block), Some((row, col))) = (self.painting, self.cell(x, y)) {
+            self.tets.set(row, col, block);
+        }
+    }
+
+    fn next_goal(&self) -> Goal {
+        match self.goal {
+            Goal::Lines(_) => Goal::TSpin(3),
+            Goal::TSpin(_) => Goal::NoHoles,
+            Goal::NoHoles => Goal::ClearGarbage,
+            Goal::ClearGarbage => Goal::Lines(4),
+        }
+    }
+
+    fn change_goal(&mut self, by: isize) {
+        self.goal = match self.goal {
+            Goal::Lines(lines) => Goal::Lines(lines.saturating_add_signed(by).clamp(1, self.tets.height())),
+            Goal::TSpin(lines) => Goal::TSpin(lines.saturating_add_signed(by).min(Goal::T_SPINS.len() - 1)),
+            goal => goal,
+        };
+    }
+
+    // No limit is the same as one move per piece, so limits stop short of
+    // the queue's length
+    fn change_moves(&mut self, by: isize) {
+        let moves = self.moves.unwrap_or(self.queue.len()).saturating_add_signed(by).max(1);
+        self.moves = if moves < self.queue.len() { Some(moves) } else { None };
+    }
+
+    // What's been made, as a puzzle. Puzzles start with an empty hold, so a
+    // held piece goes first.
+    fn puzzle(&self, name: String) -> Puzzle {
+        Puzzle {
+            name,
+            start: self.tets.clone(),
+            queue: self.held.iter().chain(self.queue.iter()).cloned().collect(),
+            goal: self.goal,
+            moves: self.moves,
+        }
+    }
+
+    fn save(&mut self, ctx: &mut Context) -> GameResult<String> {
+        let mut pack = Pack::custom(ctx)?;
+        let name = match &self.name {
+            Some(name) => name.clone(),
+            None => (pack.puzzles.len() + 1..)
+                .map(|n| format!("Puzzle {}", n))
+                .find(|name| pack.puzzles.iter().all(|puzzle| &puzzle.name != name))
+                .unwrap(),
+        };
+        let puzzle = self.puzzle(name.clone());
+        puzzle.check().map_err(GameError::ConfigError)?;
+        pack.put(puzzle);
+        pack.save(ctx)?;
+        self.name = Some(name.clone());
+        Ok(format!("Saved {:?} to the {} pack", name, pack.name))
+    }
+
+    // The board with the hold and queue as a quiz, which practice reads back
+    fn fumen(&self) -> GameResult<String> {
+        fumen::quiz(&self.tets, self.held, &self.queue)
+    }
+
+    fn export(&self, ctx: &mut Context) -> GameResult<String> {
+        game::export_fumen(ctx, &self.fumen()?)?;
+        Ok("Exported to fumen.txt".to_string())
+    }
+
+    fn report(&mut self, result: GameResult<String>) {
+        self.message = Some(match result {
+            Ok(message) => message,
+            Err(err) => err.to_string(),
+        });
+    }
+
+    fn draw_board(&self, ctx: &mut Context, skin: &Skin) -> GameResult {
+        let left = SIDEBAR_WIDTH * TILE_SIZE;
+        let (width, height) = (self.tets.width() as f32 * TILE_SIZE, self.tets.height() as f32 * TILE_SIZE);
+        skin.draw_board(ctx, [left, 0.0, width, height].into())?;
+        // A grid, to see where to paint
+        let mut mesh = graphics::MeshBuilder::new();
+        let grid = Color::from_rgb(40, 40, 40);
+        for col in 1..self.tets.width() {
+            let x = left + col as f32 * TILE_SIZE;
+            mesh.line(&[Point2f32::new(x, 0.0), Point2f32::new(x, height)], 1.0, grid)?;
+        }
+        for row in 1..self.tets.height() {
+            let y = row as f32 * TILE_SIZE;
+            mesh.line(&[Point2f32::new(left, y), Point2f32::new(left + width, y)], 1.0, grid)?;
+        }
+        if let Some(i) = brushes().iter().position(|&brush| brush == self.brush) {
+            let y = PALETTE_Y + i as f32 * SWATCH_STEP;
+            mesh.rectangle(
+                graphics::DrawMode::stroke(2.0),
+                [PALETTE_X - 3.0, y - 3.0, TILE_SIZE + 6.0, TILE_SIZE + 6.0].into(),
+                skin.frame(),
+            );
+        }
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())?;
+
+        for (y, row) in self.tets.iter().enumerate() {
+            for (x, block) in row.iter().enumerate() {
+                if let Some(block) = block {
+                    let dest = Point2f32::new(left + x as f32 * TILE_SIZE, y as f32 * TILE_SIZE);
+                    skin.draw_block(ctx, *block, dest, TILE_SIZE)?;
+                }
+            }
+        }
+
+        for (i, &brush) in brushes().iter().enumerate() {
+            let y = PALETTE_Y + i as f32 * SWATCH_STEP;
+            skin.draw_block(ctx, brush, Point2f32::new(PALETTE_X, y), TILE_SIZE)?;
+            let label = match brush {
+                Block::Tet(tet_type) => format!("{} {}", i + 1, tet_type.name()),
+                Block::Garbage => format!("{} Garbage", i + 1),
+            };
+            graphics::draw(
+                ctx,
+                &skin.text(label, graphics::DEFAULT_FONT_SCALE),
+                (Point2f32::new(PALETTE_X + TILE_SIZE + 8.0, y + 3.0), skin.text_color()),
+            )?;
+        }
+        Ok(())
+    }
+
+    // What's being made, the keys, and how the last save or export went
+    fn draw_panel(&self, ctx: &mut Context, skin: &Skin) -> GameResult {
+        let queue: String = self.queue.iter().map(|tet_type| tet_type.name()).collect();
+        let held = self.held.map_or("none", |tet_type| tet_type.name());
+        let moves = self.moves.map_or("all".to_string(), |moves| moves.to_string());
+        let lines = [
+            self.name.clone().unwrap_or_else(|| "New board".to_string()),
+            format!("Queue: {}", queue),
+            format!("Hold: {}", held),
+            format!("Goal: {}", self.goal.describe()),
+            format!("Moves: {}", moves),
+        ];
+        let x = (SIDEBAR_WIDTH + self.tets.width() as f32) * TILE_SIZE + 10.0;
+        let bounds = Point2f32::new(SIDEBAR_WIDTH * TILE_SIZE + PANEL_WIDTH - 20.0, 60.0);
+        let mut y = 10.0;
+        let message = self.message.iter().map(String::as_str);
+        let text = lines.iter().map(String::as_str).chain(Some("")).chain(HELP.iter().copied()).chain(Some(""));
+        for line in text.chain(message) {
+            let mut text = skin.text(line, graphics::DEFAULT_FONT_SCALE);
+            text.set_bounds(bounds, graphics::Align::Left);
+            graphics::draw(ctx, &text, (Point2f32::new(x, y), skin.text_color()))?;
+            y += text.height(ctx) as f32 + 4.0;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Editor {
+    fn default() -> Editor {
+        Editor::new()
+    }
+}
+
+impl Scene for Editor {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, keymod: KeyMods, _repeat: bool) -> Transition {
+        let shift = keymod.contains(KeyMods::SHIFT);
+        if let Some(tet_type) = piece_key(keycode) {
+            if shift {
+                self.held = Some(tet_type);
+            } else {
+                self.queue.push(tet_type);
+            }
+            return Transition::None;
+        }
+        if let Some(index) = number_key(keycode) {
+            self.brush = brushes()[index];
+            return Transition::None;
+        }
+        match keycode {
+            KeyCode::Escape => return Transition::Pop,
+            KeyCode::Back if shift => self.held = None,
+            KeyCode::Back => {
+                self.queue.pop();
+                self.change_moves(0);
+            },
+            KeyCode::Tab => self.goal = self.next_goal(),
+            KeyCode::Up => self.change_goal(1),
+            KeyCode::Down => self.change_goal(-1),
+            KeyCode::Left => self.change_moves(-1),
+            KeyCode::Right => self.change_moves(1),
+            KeyCode::Delete => self.tets = self.tets.empty(),
+            KeyCode::P => {
+                match menu::setup(ctx, self.tets.clone(), self.held, self.queue.clone()) {
+                    Ok(game) => return Transition::Push(Box::new(game)),
+                    Err(err) => self.message = Some(err.to_string()),
+                }
+            },
+            KeyCode::F => {
+                let result = self.export(ctx);
+                self.report(result);
+            },
+            KeyCode::Return => {
+                let result = self.save(ctx);
+                self.report(result);
+            },
+            _ => (),
+        }
+        Transition::None
+    }
+
+    fn mouse_button_down(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        if let Some(brush) = self.swatch(x, y) {
+            self.brush = brush;
+            return;
+        }
+        self.painting = match button {
+            MouseButton::Left => Some(Some(self.brush)),
+            MouseButton::Right => Some(None),
+            _ => return,
+        };
+        self.paint(x, y);
+    }
+
+    fn mouse_button_up(&mut self, _ctx: &mut Context, _button: MouseButton, _x: f32, _y: f32) {
+        self.painting = None;
+    }
+
+    fn mouse_motion(&mut self, _ctx: &mut Context, x: f32, y: f32) {
+        self.paint(x, y);
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        if self.skin.is_none() {
+            let name = Settings::load(ctx).skin;
+            self.skin = Some(Skin::load(ctx, &name, &PieceSet::standard())?);
+        }
+        if let Some(skin) = &self.skin {
+            self.draw_board(ctx, skin)?;
+            self.draw_panel(ctx, skin)?;
+        }
+        Ok(())
+    }
+
+    fn window_size(&self) -> (f32, f32) {
+        (game::WINDOW_WIDTH + PANEL_WIDTH, game::WINDOW_HEIGHT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::test_boards::rows;
+
+    // A T-spin double setup, painted as it would be with the mouse
+    fn painted() -> Editor {
+        let mut editor = Editor::new();
+        for col in (0..10).filter(|&col| col != 1) {
+            editor.tets.set(19, col, Some(Block::Garbage));
+        }
+        for col in (0..10).filter(|&col| col > 2) {
+            editor.tets.set(18, col, Some(Block::Tet(TetType::L)));
+        }
+        editor.tets.set(17, 3, Some(Block::Tet(TetType::J)));
+        editor.queue = vec![TetType::T, TetType::I];
+        editor.held = Some(TetType::O);
+        editor.goal = Goal::TSpin(2);
+        editor
+    }
+
+    #[test]
+    fn export_puzzle() {
+        let editor = painted();
+        let mut pack = Pack { name: "Custom".to_string(), path: "custom.toml".to_string(), puzzles: Vec::new() };
+        pack.put(editor.puzzle("TSD".to_string()));
+        let read = Pack::parse(&pack.path, &pack.data().unwrap()).unwrap();
+        assert_eq!(read.name, "Custom");
+        assert_eq!(read.puzzles.len(), 1);
+
+        let puzzle = &read.puzzles[0];
+        assert_eq!(puzzle.name, "TSD");
+        assert_eq!(rows(&puzzle.start), rows(&editor.tets));
+        assert_eq!(puzzle.queue, vec![TetType::O, TetType::T, TetType::I]);
+        assert_eq!(puzzle.goal, Goal::TSpin(2));
+        assert_eq!(puzzle.moves, None);
+
+        // Opening it again gives back what was saved
+        let reopened = Editor::editing(puzzle.clone());
+        assert_eq!(reopened.puzzle("TSD".to_string()).queue, puzzle.queue);
+        assert_eq!(reopened.name.as_deref(), Some("TSD"));
+    }
+
+    #[test]
+    fn export_fumen() {
+        let editor = painted();
+        let pages = fumen::decode(&editor.fumen().unwrap()).unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(rows(&pages[0].tets), rows(&editor.tets));
+        let quiz = pages[0].comment.as_deref().and_then(fumen::parse_quiz);
+        assert_eq!(quiz, Some((Some(TetType::O), vec![TetType::T, TetType::I])));
+    }
+
+    #[test]
+    fn move_limits_stop_short_of_the_queue() {
+        let mut editor = painted();
+        editor.change_moves(-1);
+        assert_eq!(editor.moves, Some(1));
+        editor.change_moves(-1);
+        assert_eq!(editor.moves, Some(1));
+        editor.change_moves(1);
+        assert_eq!(editor.moves, None);
+    }
+}
@@ -0,0 +1,320 @@
+// Things drawn over the board for a moment when something happens: rows
+// flashing and shattering as they clear, streaks behind hard drops, a flash
+// as a piece locks, the screen shaking on big clears, and callouts naming
+// them. Each can be turned off.
+
+use std::time::Duration;
+
+use ggez::{Context, GameResult};
+use ggez::graphics::{self, Color, DrawParam};
+
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::TILE_SIZE;
+use crate::skin;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+type Vector2f32 = ggez::nalgebra::Vector2<f32>;
+type Point2 = ggez::nalgebra::Point2<i8>;
+
+const CLEAR_FLASH_TIME: f32 = 0.25;
+const LOCK_FLASH_TIME: f32 = 0.15;
+const STREAK_TIME: f32 = 0.2;
+const SHAKE_TIME: f32 = 0.3;
+const CALLOUT_TIME: f32 = 1.2;
+// How far callouts drift up as they fade, in pixels
+const CALLOUT_RISE: f32 = 30.0;
+// Particles per cleared block, and how fast they fall, in pixels per second
+// squared
+const SHARDS: usize = 3;
+const GRAVITY: f32 = 600.0;
+const SHARD_SIZE: f32 = 4.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Effect {
+    ClearFlash,
+    Shatter,
+    DropStreaks,
+    LockFlash,
+    Shake,
+    Callouts,
+}
+
+impl Effect {
+    pub const ALL: [Effect; 6] = [
+        Effect::ClearFlash,
+        Effect::Shatter,
+        Effect::DropStreaks,
+        Effect::LockFlash,
+        Effect::Shake,
+        Effect::Callouts,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Effect::ClearFlash => "Line clear flash",
+            Effect::Shatter => "Shatter particles",
+            Effect::DropStreaks => "Hard drop streaks",
+            Effect::LockFlash => "Lock flash",
+            Effect::Shake => "Screen shake",
+            Effect::Callouts => "Callouts",
+        }
+    }
+}
+
+// Cells lit up white, fading
+struct Flash {
+    // Top left corners of the cells, in pixels from the board's corner
+    cells: Vec<Point2f32>,
+    width: f32,
+    left: f32,
+    total: f32,
+}
+
+struct Shard {
+    pos: Point2f32,
+    velocity: Vector2f32,
+    color: Color,
+    left: f32,
+    total: f32,
+}
+
+// A column of fading colour from where a piece was dropped to where it
+// landed
+struct Streak {
+    x: f32,
+    top: f32,
+    bottom: f32,
+    color: Color,
+    left: f32,
+}
+
+struct Callout {
+    text: String,
+    left: f32,
+}
+
+pub struct Animations {
+    off: Vec<Effect>,
+    flashes: Vec<Flash>,
+    shards: Vec<Shard>,
+    streaks: Vec<Streak>,
+    callouts: Vec<Callout>,
+    // Time left shaking, and how hard
+    shake: f32,
+    shake_strength: f32,
+}
+
+// Board cells to pixels from the board's corner
+fn corner(cell: Point2) -> Point2f32 {
+    Point2f32::new(TILE_SIZE * cell.x as f32, TILE_SIZE * cell.y as f32)
+}
+
+impl Animations {
+    pub fn new() -> Animations {
+        Animations {
+            off: Vec::new(),
+            flashes: Vec::new(),
+            shards: Vec::new(),
+            streaks: Vec::new(),
+            callouts: Vec::new(),
+            shake: 0.0,
+            shake_strength: 0.0,
+        }
+    }
+
+    pub fn set_off(&mut self, off: Vec<Effect>) {
+        self.off = off;
+    }
+
+    fn on(&self, effect: Effect) -> bool {
+        !self.off.contains(&effect)
+    }
+
+    // Stops everything, e.g. on restarting
+    pub fn clear(&mut self) {
+        self.flashes.clear();
+        self.shards.clear();
+        self.streaks.clear();
+        self.callouts.clear();
+        self.shake = 0.0;
+    }
+
+    // Rows about to be cleared, given as the row and the colour of each of
+    // its blocks
+    pub fn clear_rows(&mut self, rows: &[(i8, Vec<Color>)]) {
+        if self.on(Effect::ClearFlash) {
+            for (row, colors) in rows {
+                self.flashes.push(Flash {
+                    cells: vec![corner(Point2::new(0, *row))],
+                    width: TILE_SIZE * colors.len() as f32,
+                    left: CLEAR_FLASH_TIME,
+                    total: CLEAR_FLASH_TIME,
+                });
+            }
+        }
+        if self.on(Effect::Shatter) {
+            let mut rng = rand::thread_rng();
+            for (row, colors) in rows {
+                for (col, &color) in colors.iter().enumerate() {
+                    let center = corner(Point2::new(col as i8, *row)) + Vector2f32::new(TILE_SIZE / 2.0, TILE_SIZE / 2.0);
+                    for _ in 0..SHARDS {
+                        let life = rng.gen_range(0.5, 0.9);
+                        self.shards.push(Shard {
+                            pos: center,
+                            velocity: Vector2f32::new(rng.gen_range(-120.0, 120.0), rng.gen_range(-220.0, -40.0)),
+                            color,
+                            left: life,
+                            total: life,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn lock(&mut self, cells: &[Point2]) {
+        if self.on(Effect::LockFlash) {
+            self.flashes.push(Flash {
+                cells: cells.iter().map(|&cell| corner(cell)).collect(),
+                width: TILE_SIZE,
+                left: LOCK_FLASH_TIME,
+                total: LOCK_FLASH_TIME,
+            });
+        }
+    }
+
+    // A piece hard dropped, from its cells before the drop to how far it
+    // fell
+    pub fn hard_drop(&mut self, cells: &[Point2], rows: i8, color: Color) {
+        if !self.on(Effect::DropStreaks) || rows == 0 {
+            return;
+        }
+        // The top of the piece in each column
+        let mut columns: Vec<(i8, i8)> = Vec::new();
+        for cell in cells {
+            match columns.iter_mut().find(|(col, _)| *col == cell.x) {
+                Some((_, top)) => *top = (*top).min(cell.y),
+                None => columns.push((cell.x, cell.y)),
+            }
+        }
+        for (col, top) in columns {
+            // Hidden rows above the board are left out
+            let top = top.max(0);
+            self.streaks.push(Streak {
+                x: TILE_SIZE * col as f32 + 3.0,
+                top: TILE_SIZE * top as f32,
+                bottom: TILE_SIZE * (top + rows).max(0) as f32,
+                color,
+                left: STREAK_TIME,
+            });
+        }
+    }
+
+    pub fn shake(&mut self, strength: f32) {
+        if self.on(Effect::Shake) {
+            self.shake = SHAKE_TIME;
+            self.shake_strength = strength;
+        }
+    }
+
+    pub fn callout(&mut self, text: String) {
+        if self.on(Effect::Callouts) {
+            self.callouts.push(Callout { text, left: CALLOUT_TIME });
+        }
+    }
+
+    pub fn update(&mut self, delta: Duration) {
+        let delta = delta.as_secs_f32();
+        for flash in self.flashes.iter_mut() {
+            flash.left -= delta;
+        }
+        self.flashes.retain(|flash| flash.left > 0.0);
+        for shard in self.shards.iter_mut() {
+            shard.velocity.y += GRAVITY * delta;
+            shard.pos += shard.velocity * delta;
+            shard.left -= delta;
+        }
+        self.shards.retain(|shard| shard.left > 0.0);
+        for streak in self.streaks.iter_mut() {
+            streak.left -= delta;
+        }
+        self.streaks.retain(|streak| streak.left > 0.0);
+        for callout in self.callouts.iter_mut() {
+            callout.left -= delta;
+        }
+        self.callouts.retain(|callout| callout.left > 0.0);
+        self.shake = (self.shake - delta).max(0.0);
+    }
+
+    // How far to move everything while shaking, dying down as it ends
+    pub fn shake_offset(&self) -> Option<Vector2f32> {
+        if self.shake <= 0.0 {
+            return None;
+        }
+        let mut rng = rand::thread_rng();
+        let strength = self.shake_strength * self.shake / SHAKE_TIME;
+        Some(Vector2f32::new(rng.gen_range(-strength, strength), rng.gen_range(-strength, strength)))
+    }
+
+    // Draws over a board whose top left corner is at `origin`, with
+    // callouts centered on `center`
+    pub fn draw(&self, ctx: &mut Context, origin: Point2f32, center: f32) -> GameResult {
+        let offset = origin.coords;
+        let mut mesh = graphics::MeshBuilder::new();
+        let mut empty = true;
+        for streak in &self.streaks {
+            let mut color = streak.color;
+            color.a = 0.4 * streak.left / STREAK_TIME;
+            mesh.rectangle(
+                graphics::DrawMode::fill(),
+                [offset.x + streak.x, offset.y + streak.top, TILE_SIZE - 6.0, streak.bottom - streak.top].into(),
+                color,
+            );
+            empty = false;
+        }
+        for flash in &self.flashes {
+            let color = Color::new(1.0, 1.0, 1.0, 0.7 * flash.left / flash.total);
+            for cell in &flash.cells {
+                mesh.rectangle(
+                    graphics::DrawMode::fill(),
+                    [offset.x + cell.x, offset.y + cell.y, flash.width, TILE_SIZE].into(),
+                    color,
+                );
+                empty = false;
+            }
+        }
+        for shard in &self.shards {
+            let mut color = shard.color;
+            color.a = shard.left / shard.total;
+            mesh.rectangle(
+                graphics::DrawMode::fill(),
+                [offset.x + shard.pos.x, offset.y + shard.pos.y, SHARD_SIZE, SHARD_SIZE].into(),
+                color,
+            );
+            empty = false;
+        }
+        if !empty {
+            let mesh = mesh.build(ctx)?;
+            graphics::draw(ctx, &mesh, DrawParam::default())?;
+        }
+
+        // Newest at the bottom
+        for (i, callout) in self.callouts.iter().enumerate() {
+            let age = 1.0 - callout.left / CALLOUT_TIME;
+            let text = skin::text(callout.text.as_str(), 20.0);
+            let x = center - text.width(ctx) as f32 / 2.0;
+            let y = offset.y + 100.0 + 24.0 * i as f32 - CALLOUT_RISE * age;
+            graphics::draw(ctx, &text, (Point2f32::new(x, y), Color::new(1.0, 0.9, 0.4, 1.0 - age)))?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Animations {
+    fn default() -> Animations {
+        Animations::new()
+    }
+}
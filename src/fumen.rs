@@ -0,0 +1,553 @@
+// Fumen (v115), the format the community uses to share boards and setups:
+// pages of a 23 row field plus a garbage row, each with an optional piece and
+// comment, packed into base64-style text.
+
+use ggez::{GameError, GameResult};
+
+use crate::game::{self, Block, Tets};
+use crate::tet::{Rot, Tet, TetType};
+
+const TABLE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const PREFIX: &str = "v115@";
+
+const WIDTH: usize = 10;
+// 23 rows of field and the garbage row under them
+const FIELD_ROWS: usize = 24;
+const FIELD_CELLS: usize = WIDTH * FIELD_ROWS;
+// Field rows above the top of our board
+const HIDDEN_ROWS: usize = FIELD_ROWS - 1 - game::STANDARD_HEIGHT;
+
+// Field diff meaning "same as the last page"
+const UNCHANGED: u32 = 8 * FIELD_CELLS as u32 + FIELD_CELLS as u32 - 1;
+// Comments are stored as `escape`d text, four characters to five digits
+const COMMENT_BASE: u32 = 96;
+
+type Field = [u8; FIELD_CELLS];
+
+pub struct Page {
+    // The board, not counting the piece
+    pub tets: Tets,
+    pub piece: Option<Tet>,
+    pub comment: Option<String>,
+}
+
+fn invalid(reason: &str) -> GameError {
+    GameError::ConfigError(format!("invalid fumen: {}", reason))
+}
+
+fn block_value(block: Option<Block>) -> u8 {
+    match block {
+        None => 0,
+        Some(Block::Tet(tet_type)) => piece_value(tet_type),
+        Some(Block::Garbage) => 8,
+    }
+}
+
+fn piece_value(tet_type: TetType) -> u8 {
+    match tet_type {
+        TetType::I => 1,
+        TetType::L => 2,
+        TetType::O => 3,
+        TetType::Z => 4,
+        TetType::T => 5,
+        TetType::J => 6,
+        TetType::S => 7,
+        // Fumen only has the tetrominoes; blocks of other pieces are shown
+        // as garbage
+        _ => 8,
+    }
+}
+
+fn value_piece(value: u8) -> Option<TetType> {
+    match value {
+        1 => Some(TetType::I),
+        2 => Some(TetType::L),
+        3 => Some(TetType::O),
+        4 => Some(TetType::Z),
+        5 => Some(TetType::T),
+        6 => Some(TetType::J),
+        7 => Some(TetType::S),
+        _ => None,
+    }
+}
+
+fn value_block(value: u8) -> Option<Block> {
+    match value {
+        0 => None,
+        8 => Some(Block::Garbage),
+        _ => value_piece(value).map(Block::Tet),
+    }
+}
+
+fn rot_value(rot: Rot) -> u32 {
+    match rot {
+        Rot::Two => 0,
+        Rot::R => 1,
+        Rot::Zero => 2,
+        Rot::L => 3,
+    }
+}
+
+fn value_rot(value: u32) -> Rot {
+    match value {
+        0 => Rot::Two,
+        1 => Rot::R,
+        2 => Rot::Zero,
+        _ => Rot::L,
+    }
+}
+
+// Fumen places a few pieces by a different cell than the SRS center; this is
+// what to add to a fumen position to get the center
+fn center_adjustment(tet_type: TetType, rot: Rot) -> (i8, i8) {
+    match (tet_type, rot) {
+        (TetType::O, Rot::L) => (1, -1),
+        (TetType::O, Rot::Two) => (1, 0),
+        (TetType::O, Rot::Zero) => (0, -1),
+        (TetType::I, Rot::Two) => (1, 0),
+        (TetType::I, Rot::L) => (0, -1),
+        (TetType::S, Rot::Zero) => (0, -1),
+        (TetType::S, Rot::R) => (-1, 0),
+        (TetType::Z, Rot::Zero) => (0, -1),
+        (TetType::Z, Rot::L) => (1, 0),
+        _ => (0, 0),
+    }
+}
+
+// Field index of a board cell; rows above the board are negative
+fn field_index(row: i8, col: i8) -> Option<usize> {
+    let row = row + HIDDEN_ROWS as i8;
+    if row < 0 || row >= FIELD_ROWS as i8 - 1 || col < 0 || col >= WIDTH as i8 {
+        None
+    } else {
+        Some(row as usize * WIDTH + col as usize)
+    }
+}
+
+fn to_field(tets: &Tets) -> Field {
+    let mut field = [0; FIELD_CELLS];
+    for (row, blocks) in tets.iter().enumerate() {
+        for (col, block) in blocks.iter().enumerate() {
+            field[(row + HIDDEN_ROWS) * WIDTH + col] = block_value(*block);
+        }
+    }
+    field
+}
+
+fn to_tets(field: &Field) -> GameResult<Tets> {
+    if field[..HIDDEN_ROWS * WIDTH].iter().any(|&value| value != 0) {
+        return Err(invalid("board is taller than 20 rows"));
+    }
+    let mut tets = Tets::default();
+    for row in 0..game::STANDARD_HEIGHT {
+        for col in 0..WIDTH {
+            let value = field[(row + HIDDEN_ROWS) * WIDTH + col];
+            tets.set(row as i8, col as i8, value_block(value));
+        }
+    }
+    Ok(tets)
+}
+
+// What happens to the field after a page's piece locks
+fn lock(field: &mut Field, piece: &Option<Tet>, rise: bool, mirror: bool) {
+    if let Some(tet) = piece {
+        for cell in tet.cells().iter() {
+            if let Some(index) = field_index(cell.y, cell.x) {
+                field[index] = piece_value(tet.tet_type);
+            }
+        }
+    }
+
+    let rows = FIELD_ROWS - 1;
+    let mut kept: Vec<[u8; WIDTH]> = Vec::with_capacity(rows);
+    for row in 0..rows {
+        let mut cells = [0; WIDTH];
+        cells.copy_from_slice(&field[row * WIDTH..(row + 1) * WIDTH]);
+        if cells.contains(&0) {
+            kept.push(cells);
+        }
+    }
+    while kept.len() < rows {
+        kept.insert(0, [0; WIDTH]);
+    }
+
+    if rise {
+        let mut garbage = [0; WIDTH];
+        garbage.copy_from_slice(&field[rows * WIDTH..]);
+        kept.remove(0);
+        kept.push(garbage);
+        for value in field[rows * WIDTH..].iter_mut() {
+            *value = 0;
+        }
+    }
+    if mirror {
+        for cells in kept.iter_mut() {
+            cells.reverse();
+        }
+    }
+    for (row, cells) in kept.iter().enumerate() {
+        field[row * WIDTH..(row + 1) * WIDTH].copy_from_slice(cells);
+    }
+}
+
+struct Reader {
+    digits: Vec<u32>,
+    pos: usize,
+}
+
+impl Reader {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.digits.len()
+    }
+
+    // The next `count` digits as one little-endian number
+    fn poll(&mut self, count: usize) -> GameResult<u32> {
+        let digits = self.digits.get(self.pos..self.pos + count)
+            .ok_or_else(|| invalid("data ends too soon"))?;
+        self.pos += count;
+        Ok(digits.iter().rev().fold(0, |value, &digit| value * 64 + digit))
+    }
+}
+
+fn push(out: &mut String, mut value: u32, count: usize) {
+    for _ in 0..count {
+        out.push(TABLE[(value % 64) as usize] as char);
+        value /= 64;
+    }
+}
+
+// Like JavaScript's `escape`, which is how fumen stores comments
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for unit in text.encode_utf16() {
+        match unit {
+            0x30..=0x39 | 0x41..=0x5a | 0x61..=0x7a => escaped.push(unit as u8 as char),
+            _ if unit < 0x80 && b"@*_+-./".contains(&(unit as u8)) => {
+                escaped.push(unit as u8 as char);
+            },
+            0..=0xff => escaped.push_str(&format!("%{:02X}", unit)),
+            _ => escaped.push_str(&format!("%u{:04X}", unit)),
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut units = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let code = if c != '%' {
+            None
+        } else if rest[1..].starts_with('u') {
+            rest.get(2..6).and_then(|hex| u16::from_str_radix(hex, 16).ok()).map(|code| (code, 6))
+        } else {
+            rest.get(1..3).and_then(|hex| u16::from_str_radix(hex, 16).ok()).map(|code| (code, 3))
+        };
+        match code {
+            Some((code, len)) => {
+                units.push(code);
+                rest = &rest[len..];
+            },
+            None => {
+                let mut buf = [0; 2];
+                units.extend_from_slice(c.encode_utf16(&mut buf));
+                rest = &rest[c.len_utf8()..];
+            },
+        }
+    }
+    String::from_utf16_lossy(&units)
+}
+
+fn read_comment(reader: &mut Reader) -> GameResult<String> {
+    let len = reader.poll(2)? as usize;
+    let mut escaped = String::with_capacity(len);
+    while escaped.len() < len {
+        let mut value = reader.poll(5)?;
+        for _ in 0..4 {
+            if escaped.len() < len {
+                escaped.push((value % COMMENT_BASE + 32) as u8 as char);
+            }
+            value /= COMMENT_BASE;
+        }
+    }
+    Ok(unescape(&escaped))
+}
+
+fn write_comment(out: &mut String, comment: &str) {
+    // Only the first 4095 characters fit
+    let escaped: Vec<u8> = escape(comment).bytes().take(4095).collect();
+    push(out, escaped.len() as u32, 2);
+    for chunk in escaped.chunks(4) {
+        let value = chunk.iter().rev()
+            .fold(0, |value, &c| value * COMMENT_BASE + (c as u32 - 32));
+        push(out, value, 5);
+    }
+}
+
+// Reads every page of a fumen. Accepts the bare data or a whole fumen URL.
+pub fn decode(data: &str) -> GameResult<Vec<Page>> {
+    let start = data.find(PREFIX).ok_or_else(|| invalid("only v115 data is supported"))?;
+    let digits = data[start + PREFIX.len()..]
+        .chars()
+        .filter(|&c| c != '?' && !c.is_whitespace())
+        .map(|c| {
+            TABLE.iter().position(|&digit| digit as char == c)
+                .map(|value| value as u32)
+                .ok_or_else(|| invalid(&format!("unexpected character {:?}", c)))
+        })
+        .collect::<GameResult<Vec<u32>>>()?;
+    let mut reader = Reader { digits, pos: 0 };
+
+    let mut pages = Vec::new();
+    let mut field: Field = [0; FIELD_CELLS];
+    let mut repeats = 0;
+    let mut comment = String::new();
+    while !reader.is_empty() {
+        if repeats > 0 {
+            repeats -= 1;
+        } else {
+            let mut index = 0;
+            while index < FIELD_CELLS {
+                let value = reader.poll(2)?;
+                if value == UNCHANGED {
+                    repeats = reader.poll(1)?;
+                }
+                let diff = (value / FIELD_CELLS as u32) as i32 - 8;
+                let count = (value % FIELD_CELLS as u32) as usize + 1;
+                if index + count > FIELD_CELLS {
+                    return Err(invalid("field runs past the end"));
+                }
+                for cell in field[index..index + count].iter_mut() {
+                    let changed = *cell as i32 + diff;
+                    if !(0..=8).contains(&changed) {
+                        return Err(invalid("bad field cell"));
+                    }
+                    *cell = changed as u8;
+                }
+                index += count;
+            }
+        }
+
+        let mut action = reader.poll(3)?;
+        let piece_type = (action % 8) as u8;
+        action /= 8;
+        let rot = value_rot(action % 4);
+        action /= 4;
+        let position = (action % FIELD_CELLS as u32) as usize;
+        action /= FIELD_CELLS as u32;
+        let mut flag = || {
+            let set = action % 2 == 1;
+            action /= 2;
+            set
+        };
+        let rise = flag();
+        let mirror = flag();
+        let _colorize = flag();
+        let has_comment = flag();
+        let lock_piece = !flag();
+
+        if has_comment {
+            comment = read_comment(&mut reader)?;
+        }
+
+        let piece = match value_piece(piece_type) {
+            Some(tet_type) => {
+                let (dx, dy) = center_adjustment(tet_type, rot);
+                let x = (position % WIDTH) as i8 + dx;
+                let y = (FIELD_ROWS - 2) as i8 - (position / WIDTH) as i8 + dy;
+                let tet = Tet::from_center(tet_type, rot, x, y, game::STANDARD_HEIGHT);
+                if tet.cells().iter().any(|cell| field_index(cell.y, cell.x).is_none()) {
+                    return Err(invalid("piece is off the field"));
+                }
+                Some(tet)
+            },
+            None => None,
+        };
+
+        pages.push(Page {
+            tets: to_tets(&field)?,
+            piece: piece.clone(),
+            comment: if comment.is_empty() { None } else { Some(comment.clone()) },
+        });
+
+        if lock_piece {
+            lock(&mut field, &piece, rise, mirror);
+        }
+    }
+    if pages.is_empty() {
+        return Err(invalid("no pages"));
+    }
+    Ok(pages)
+}
+
+// Writes pages as one fumen, each page's piece locking before the next
+pub fn encode(pages: &[Page]) -> String {
+    let mut out = PREFIX.to_string();
+    let mut prev: Field = [0; FIELD_CELLS];
+    let mut comment = String::new();
+    for (i, page) in pages.iter().enumerate() {
+        let mut field = to_field(&page.tets);
+        // Keep the garbage row we can't show
+        field[(FIELD_ROWS - 1) * WIDTH..].copy_from_slice(&prev[(FIELD_ROWS - 1) * WIDTH..]);
+
+        let diffs: Vec<u32> = field.iter().zip(prev.iter())
+            .map(|(&now, &before)| (now as i32 - before as i32 + 8) as u32)
+            .collect();
+        if diffs.iter().all(|&diff| diff == 8) {
+            push(&mut out, UNCHANGED, 2);
+            push(&mut out, 0, 1);
+        } else {
+            let mut index = 0;
+            while index < FIELD_CELLS {
+                let diff = diffs[index];
+                let count = diffs[index..].iter().take_while(|&&d| d == diff).count();
+                push(&mut out, diff * FIELD_CELLS as u32 + count as u32 - 1, 2);
+                index += count;
+            }
+        }
+
+        let page_comment = page.comment.clone().unwrap_or_default();
+        let comment_changed = page_comment != comment;
+        let (piece_type, rot, position) = match &page.piece {
+            Some(tet) => {
+                let (dx, dy) = center_adjustment(tet.tet_type, tet.rot());
+                let (x, y) = tet.center(game::STANDARD_HEIGHT);
+                let (x, y) = ((x - dx) as i32, (y - dy) as i32);
+                let position = (FIELD_ROWS as i32 - 2 - y) * WIDTH as i32 + x;
+                (piece_value(tet.tet_type) as u32, rot_value(tet.rot()), position.max(0) as u32)
+            },
+            None => (0, 0, 0),
+        };
+        let mut action = 0;
+        // Lock (stored inverted), comment, colorize, mirror, rise
+        for &flag in [false, comment_changed, i == 0, false, false].iter() {
+            action = action * 2 + flag as u32;
+        }
+        action = ((action * FIELD_CELLS as u32 + position) * 4 + rot) * 8 + piece_type;
+        push(&mut out, action, 3);
+
+        if comment_changed {
+            write_comment(&mut out, &page_comment);
+            comment = page_comment;
+        }
+
+        lock(&mut field, &page.piece, false, false);
+        prev = field;
+    }
+    out
+}
+
+// Fumen only holds boards the standard size, and tetrominoes
+fn check<'a>(tets: &Tets, mut pieces: impl Iterator<Item = &'a Tet>) -> GameResult {
+    if !tets.is_standard() {
+        return Err(GameError::ConfigError("fumen only holds 10x20 boards".to_string()));
+    }
+    if !pieces.all(|tet| tet.tet_type.is_standard()) {
+        return Err(GameError::ConfigError("fumen only holds tetrominoes".to_string()));
+    }
+    Ok(())
+}
+
+// The board and the piece in play, as a single page
+pub fn snapshot(tets: &Tets, piece: Option<&Tet>) -> GameResult<String> {
+    check(tets, piece.into_iter())?;
+    Ok(encode(&[Page { tets: tets.clone(), piece: piece.cloned(), comment: None }]))
+}
+
+// A page for every piece placed, starting from `start`
+pub fn replay(start: &Tets, pieces: &[Tet]) -> GameResult<String> {
+    check(start, pieces.iter())?;
+    let mut tets = start.clone();
+    let mut pages = Vec::new();
+    for tet in pieces {
+        pages.push(Page { tets: tets.clone(), piece: Some(tet.clone()), comment: None });
+        tets.lock(tet);
+    }
+    pages.push(Page { tets, piece: None, comment: None });
+    Ok(encode(&pages))
+}
+
+// Fumen's quiz comment, "#Q=[hold](current)next...", is how setups with a
+// queue are shared. The queue here starts with the current piece.
+const QUIZ_PREFIX: &str = "#Q=";
+
+// The board, with the hold and queue as a quiz
+pub fn quiz(tets: &Tets, held: Option<TetType>, queue: &[TetType]) -> GameResult<String> {
+    check(tets, None.into_iter())?;
+    if !held.iter().chain(queue).all(|tet_type| tet_type.is_standard()) {
+        return Err(GameError::ConfigError("fumen only holds tetrominoes".to_string()));
+    }
+    let name = |tet_type: Option<&TetType>| tet_type.map_or("", |tet_type| tet_type.name());
+    let next: String = queue.iter().skip(1).map(|tet_type| tet_type.name()).collect();
+    let comment = format!("{}[{}]({}){}", QUIZ_PREFIX, name(held.as_ref()), name(queue.first()), next);
+    Ok(encode(&[Page { tets: tets.clone(), piece: None, comment: Some(comment) }]))
+}
+
+// The held piece and queue of a quiz comment. Anything after the first
+// quiz, e.g. more of them separated by ';', is ignored.
+pub fn parse_quiz(comment: &str) -> Option<(Option<TetType>, Vec<TetType>)> {
+    let rest = comment.strip_prefix(QUIZ_PREFIX)?.strip_prefix('[')?;
+    let (held, rest) = rest.split_once("](")?;
+    let (current, next) = rest.split_once(')')?;
+    let next = next.split(|c: char| c == ';' || c.is_whitespace()).next().unwrap_or("");
+    let piece = |name: char| TetType::named(&name.to_string()).filter(|tet_type| tet_type.is_standard());
+    let held = match held.chars().next() {
+        Some(name) if held.chars().count() == 1 => Some(piece(name)?),
+        Some(_) => return None,
+        None => None,
+    };
+    let queue = current.chars().chain(next.chars()).map(piece).collect::<Option<Vec<_>>>()?;
+    Some((held, queue))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::test_boards::rows;
+
+    #[test]
+    fn empty_board() {
+        assert_eq!(snapshot(&Tets::default(), None).unwrap(), "v115@vhAAgH");
+        let pages = decode("v115@vhAAgH").unwrap();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(rows(&pages[0].tets), rows(&Tets::default()));
+        assert!(pages[0].piece.is_none());
+        assert!(pages[0].comment.is_none());
+    }
+
+    #[test]
+    fn replay_round_trip() {
+        let mut tets = Tets::default();
+        for col in (0..10).filter(|&col| col != 4) {
+            tets.set(19, col, Some(Block::Garbage));
+        }
+        tets.set(18, 9, Some(Block::Tet(TetType::L)));
+        let pieces = [
+            Tet::from_center(TetType::I, Rot::R, 0, 2, game::STANDARD_HEIGHT),
+            Tet::from_center(TetType::T, Rot::Two, 4, 1, game::STANDARD_HEIGHT),
+        ];
+        let data = replay(&tets, &pieces).unwrap();
+        let pages = decode(&data).unwrap();
+        assert_eq!(pages.len(), pieces.len() + 1);
+        for (page, piece) in pages.iter().zip(pieces.iter()) {
+            assert_eq!(rows(&page.tets), rows(&tets));
+            let decoded = page.piece.as_ref().unwrap();
+            assert_eq!(decoded.tet_type, piece.tet_type);
+            assert_eq!(decoded.rot(), piece.rot());
+            assert_eq!(decoded.cells(), piece.cells());
+            tets.lock(piece);
+        }
+        assert_eq!(rows(&pages[pieces.len()].tets), rows(&tets));
+        assert_eq!(encode(&pages), data);
+    }
+
+    #[test]
+    fn quiz_round_trip() {
+        let queue = [TetType::T, TetType::S, TetType::Z];
+        let data = quiz(&Tets::default(), Some(TetType::I), &queue).unwrap();
+        let pages = decode(&data).unwrap();
+        let comment = pages[0].comment.as_deref().unwrap();
+        assert_eq!(comment, "#Q=[I](T)SZ");
+        assert_eq!(parse_quiz(comment), Some((Some(TetType::I), queue.to_vec())));
+    }
+}
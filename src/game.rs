@@ -1,64 +1,180 @@
-use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::io::Write;
 use std::time::Duration;
 
-use ggez::{Context, GameResult};
-use ggez::event::{self, KeyCode, KeyMods};
-use ggez::graphics::{self, Color, DrawParam, Text};
+use ggez::{Context, GameError, GameResult};
+use ggez::filesystem;
+use ggez::graphics::{self, Color, DrawParam};
 use ggez::timer;
 
-use lazy_static::lazy_static;
 
-use crate::tet::{Tet, TetType, RotationDir};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::audio::{Effects, Sound};
+use crate::effects::{Animations, Effect};
+use crate::input::Action;
+use crate::movegen;
+use crate::pieces::PieceSet;
+use crate::rules::{Ruleset, TopOut};
+use crate::skin::Skin;
+use crate::stats::{Metric, Stats};
+use crate::tet::{Rot, Tet, TetType, RotationDir};
 
 type Point2f32 = ggez::nalgebra::Point2<f32>;
 type Point2 = ggez::nalgebra::Point2<i8>;
+type Matrix4 = ggez::nalgebra::Matrix4<f32>;
 
-const TILE_SIZE: f32 = 20.0;
-pub const TILES_WIDE: usize = 10;
-pub const TILES_HIGH: usize = 20;
-const SIDEBAR_WIDTH: f32 = 6.0;
-pub const WINDOW_WIDTH: f32 = (TILES_WIDE as f32 + SIDEBAR_WIDTH * 2.0) * TILE_SIZE;
-pub const WINDOW_HEIGHT: f32 = TILES_HIGH as f32 * TILE_SIZE;
+pub const TILE_SIZE: f32 = 20.0;
+// The usual board; others are set up per game
+pub const STANDARD_WIDTH: usize = 10;
+pub const STANDARD_HEIGHT: usize = 20;
+pub const SIDEBAR_WIDTH: f32 = 6.0;
+// The window around a standard board, which menus use too
+pub const WINDOW_WIDTH: f32 = (STANDARD_WIDTH as f32 + SIDEBAR_WIDTH * 2.0) * TILE_SIZE;
+pub const WINDOW_HEIGHT: f32 = STANDARD_HEIGHT as f32 * TILE_SIZE;
+
+// What fills a cell of the board
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Block {
+    Tet(TetType),
+    // Cells that didn't come from a piece, e.g. imported or rising garbage
+    Garbage,
+}
+
+impl Block {
+    pub fn color(self) -> Color {
+        match self {
+            Block::Tet(tet_type) => {
+                let [r, g, b] = tet_type.shape().color;
+                Color::from_rgb(r, g, b)
+            },
+            Block::Garbage => Color::from_rgb(130, 130, 130),
+        }
+    }
+}
 
-struct Assets {
-    block_sprites: HashMap<TetType, graphics::Image>,
-    preview_sprite: graphics::Image,
+// Blocks are written as the piece letter, or "G" for garbage, like TBP
+impl Serialize for Block {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Block::Tet(tet_type) => tet_type.serialize(serializer),
+            Block::Garbage => serializer.serialize_str("G"),
+        }
+    }
 }
 
-impl Assets {
-    fn load(ctx: &mut Context) -> GameResult<Assets> {
-        let mut block_sprites = HashMap::new();
-        block_sprites.insert(TetType::I, graphics::Image::new(ctx, "/i.png")?);
-        block_sprites.insert(TetType::J, graphics::Image::new(ctx, "/j.png")?);
-        block_sprites.insert(TetType::L, graphics::Image::new(ctx, "/l.png")?);
-        block_sprites.insert(TetType::O, graphics::Image::new(ctx, "/o.png")?);
-        block_sprites.insert(TetType::S, graphics::Image::new(ctx, "/s.png")?);
-        block_sprites.insert(TetType::T, graphics::Image::new(ctx, "/t.png")?);
-        block_sprites.insert(TetType::Z, graphics::Image::new(ctx, "/z.png")?);
-        let preview_sprite = graphics::Image::new(ctx, "/preview.png")?;
-        Ok(Assets { block_sprites, preview_sprite })
+impl<'de> Deserialize<'de> for Block {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        if name == "G" {
+            return Ok(Block::Garbage);
+        }
+        let tet_type = TetType::deserialize(serde::de::value::StrDeserializer::<D::Error>::new(&name))?;
+        Ok(Block::Tet(tet_type))
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "SavedTets", try_from = "SavedTets")]
 pub struct Tets {
-    tets: [[Option<TetType>; TILES_WIDE]; TILES_HIGH]
+    width: usize,
+    height: usize,
+    tets: Vec<Vec<Option<Block>>>,
 }
 
+// How a board is saved: just its rows, which give its size
+type SavedTets = Vec<Vec<Option<Block>>>;
+
+impl From<Tets> for SavedTets {
+    fn from(tets: Tets) -> SavedTets {
+        tets.tets
+    }
+}
+
+impl TryFrom<SavedTets> for Tets {
+    type Error = String;
+
+    fn try_from(rows: SavedTets) -> Result<Tets, String> {
+        let width = rows.first().map_or(0, Vec::len);
+        if rows.iter().any(|row| row.len() != width) {
+            return Err("board rows differ in length".to_string());
+        }
+        Tets::check_size(width, rows.len())?;
+        Ok(Tets { width, height: rows.len(), tets: rows })
+    }
+}
+
+// An empty board of the standard size
 impl Default for Tets {
     fn default() -> Tets {
-        Tets { tets: [[None; TILES_WIDE]; TILES_HIGH] }
+        Tets::new(STANDARD_WIDTH, STANDARD_HEIGHT)
     }
 }
 
 impl Tets {
-    pub fn at(&self, row: i8, col: i8) -> &Option<TetType> {
+    // Sizes a board can be: room for every piece, within the i8 that
+    // positions are kept in
+    pub const MIN_SIZE: usize = 4;
+    pub const MAX_SIZE: usize = 100;
+
+    pub fn check_size(width: usize, height: usize) -> Result<(), String> {
+        let sizes = Self::MIN_SIZE..=Self::MAX_SIZE;
+        if !sizes.contains(&width) || !sizes.contains(&height) {
+            return Err(format!(
+                "board must be {} to {} cells each way, not {}x{}",
+                Self::MIN_SIZE, Self::MAX_SIZE, width, height,
+            ));
+        }
+        Ok(())
+    }
+
+    // Every piece has to have room to turn on the board
+    pub fn check_pieces(width: usize, height: usize, pieces: &PieceSet) -> Result<(), String> {
+        let size = pieces.box_size();
+        if size > width || size > height {
+            return Err(format!("pieces need a board at least {0}x{0}, not {1}x{2}", size, width, height));
+        }
+        Ok(())
+    }
+
+    pub fn new(width: usize, height: usize) -> Tets {
+        Tets { width, height, tets: vec![vec![None; width]; height] }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // An empty board the same size as this one
+    pub fn empty(&self) -> Tets {
+        Tets::new(self.width, self.height)
+    }
+
+    pub fn is_standard(&self) -> bool {
+        self.width == STANDARD_WIDTH && self.height == STANDARD_HEIGHT
+    }
+
+    // Whether a cell is off the sides or bottom of the board. Rows above the
+    // top are open.
+    pub fn outside(&self, row: i8, col: i8) -> bool {
+        col < 0 || col as usize >= self.width || row >= self.height as i8
+    }
+
+    pub fn at(&self, row: i8, col: i8) -> &Option<Block> {
         self.tets.get(row as usize)
             .map_or(&None, |row| row.get(col as usize)
             .map_or(&None, |block| block))
     }
 
-    fn set(&mut self, row: i8, col: i8, val: TetType) {
-        self.tets[row as usize][col as usize] = Some(val);
+    pub fn set(&mut self, row: i8, col: i8, val: Option<Block>) {
+        self.tets[row as usize][col as usize] = val;
     }
 
     fn row_full(&self, row: i8) -> bool {
@@ -66,17 +182,44 @@ impl Tets {
     }
 
     fn clear(&mut self, row: i8) {
-        self.tets[row as usize] = [None; TILES_WIDE];
-        for row in (0..row as usize).rev() {
-            for col in (0..TILES_WIDE).rev() {
-                self.tets[row + 1][col] = self.tets[row][col];
-            }
-        }
+        self.tets.remove(row as usize);
+        self.tets.insert(0, vec![None; self.width]);
     }
 
-    fn iter(&self) -> std::slice::Iter<[Option<TetType>; TILES_WIDE]> {
+    pub fn iter(&self) -> std::slice::Iter<'_, Vec<Option<Block>>> {
         self.tets.iter()
     }
+
+    // The rows the piece would complete, with the colour of each of their
+    // blocks
+    pub fn filled_by(&self, tet: &Tet) -> Vec<(i8, Vec<Color>)> {
+        let mut filled = self.clone();
+        for cell in tet.cells().iter().filter(|cell| cell.y >= 0) {
+            filled.set(cell.y, cell.x, Some(Block::Tet(tet.tet_type)));
+        }
+        (0..self.height as i8)
+            .filter(|&row| filled.row_full(row))
+            .map(|row| (row, filled.tets[row as usize].iter().flatten().map(|block| block.color()).collect()))
+            .collect()
+    }
+
+    // Adds the piece's blocks to the board and clears any rows it completes,
+    // returning how many were cleared. Blocks above the board are lost.
+    pub fn lock(&mut self, tet: &Tet) -> usize {
+        for cell in tet.cells().iter() {
+            if cell.y >= 0 {
+                self.set(cell.y, cell.x, Some(Block::Tet(tet.tet_type)));
+            }
+        }
+        let mut clears = 0;
+        for row in 0..self.height {
+            if self.row_full(row as i8) {
+                self.clear(row as i8);
+                clears += 1;
+            }
+        }
+        clears
+    }
 }
 
 #[derive(Debug)]
@@ -91,114 +234,648 @@ enum Moving {
     None,
 }
 
-lazy_static! {
-    static ref NORMAL_INTERVALS: Vec<Duration> = (0..21).map(|level|
-        Duration::from_millis(
-            ((0.8 - ((level - 1) as f32 * 0.007)).powi(level - 1) * 1000.0) as u64
-        )
-    ).collect();
+// A piece as it was locked into the board
+pub struct Lock {
+    pub tet: Tet,
+    pub lines: usize,
+    pub t_spin: bool,
+    // Movement keys pressed for the piece, and the fewest that would have
+    // done, not counting the hard drop
+    pub presses: usize,
+    pub optimal: Option<usize>,
+}
+
+impl Lock {
+    pub fn finesse_fault(&self) -> bool {
+        self.optimal.is_some_and(|optimal| self.presses > optimal)
+    }
+}
+
+// The fewest movement keys that get a piece from spawn to where `tet` is,
+// moving the way the rules let it
+fn optimal_presses(tets: &Tets, tet: &Tet, rules: &Ruleset) -> Option<usize> {
+    movegen::shortest(tets, tet.tet_type, &tet.cells(), rules).map(|placement| placement.presses())
+}
+
+// Everything needed to carry on with a game later. Keys being held and
+// things only shown on screen are left out.
+#[derive(Serialize, Deserialize)]
+pub struct Snapshot {
+    score: usize,
+    lines: usize,
+    tets: Tets,
+    current_tet: Option<Tet>,
+    queue: Vec<TetType>,
+    seed: u64,
+    batches: usize,
+    elapsed: Duration,
+    held_tet: Option<TetType>,
+    already_held: bool,
+    placed: usize,
+    start_tets: Tets,
+    history: Vec<Tet>,
+    finesse_faults: usize,
+    assisted: bool,
+    fall_timer: Duration,
+    spawn_timer: Duration,
+    start_level: Option<usize>,
+    stats: Stats,
+}
+
+impl Snapshot {
+    // Whether this can be carried on in a game with this board, these pieces
+    // and these rules. Files can be edited, or saved by a game set up
+    // differently.
+    pub fn check(&self, width: usize, height: usize, pieces: &PieceSet, rules: &Ruleset) -> Result<(), String> {
+        let boards = Some(&self.tets).into_iter()
+            .chain(Some(&self.start_tets));
+        for tets in boards {
+            if tets.width() != width || tets.height() != height {
+                return Err(format!(
+                    "board is {}x{}, not {}x{}", tets.width(), tets.height(), width, height,
+                ));
+            }
+        }
+        if let Some(tet) = &self.current_tet {
+            let blocked = tet.cells().iter()
+                .any(|cell| self.tets.outside(cell.y, cell.x) || self.tets.at(cell.y, cell.x).is_some());
+            if blocked {
+                return Err("the piece in play is off the board or in blocks".to_string());
+            }
+        }
+        let dealt = self.current_tet.iter().map(|tet| tet.tet_type)
+            .chain(self.queue.iter().copied())
+            .chain(self.held_tet)
+            .chain(self.history.iter().map(|tet| tet.tet_type));
+        for tet_type in dealt {
+            if !pieces.pieces().contains(&tet_type) {
+                return Err(format!("{} isn't in the piece set", tet_type.name()));
+            }
+        }
+        if self.held_tet.is_some() && !rules.allow.hold {
+            return Err(format!("{} rules have no hold", rules.name));
+        }
+        let levels = &rules.levels;
+        let highest = levels.highest_start.unwrap_or(levels.first);
+        match self.start_level {
+            Some(level) if level < levels.first || level > highest => {
+                Err(format!("start level {} isn't allowed", level))
+            },
+            _ => Ok(()),
+        }
+    }
+}
+
+// The game as it was at some point during a piece, to go back to. The piece
+// starts over from the top.
+pub struct Checkpoint {
+    score: usize,
+    lines: usize,
+    tets: Tets,
+    current: TetType,
+    queue: Vec<TetType>,
+    rng: StdRng,
+    recent: Vec<TetType>,
+    batches: usize,
+    held_tet: Option<TetType>,
+    already_held: bool,
+    placed: usize,
+    finesse_faults: usize,
+    stats: Stats,
 }
 
 enum GameState {
     Playing,
     Dead,
-    // Paused,
+    // Ended by reaching the goal of a mode
+    Finished,
+    Paused,
 }
 
 pub struct Game {
     state: GameState,
-    assets: Assets,
+    // What the queue is dealt from
+    pieces: PieceSet,
+    rules: Ruleset,
+    // Lately dealt pieces, for randomizers that avoid repeats. Rebuilt from
+    // the seed, like the queue.
+    recent: Vec<TetType>,
     score: usize,
     lines: usize,
+    // The level the game began at, which some rulesets let the player pick
+    start_level: usize,
     tets: Tets,
     current_tet: Tet,
     has_tet: bool,
-    next_batch: [TetType; 7],
-    next_tet: usize, // TODO: iterator?
+    // The piece's last move was a turn, which a T-spin needs
+    rotated_last: bool,
+    // Upcoming pieces, topped up a batch at a time
+    queue: Vec<TetType>,
+    // Pieces come from a seeded generator so a game can be identified (and
+    // resumed) by its seed and how many batches it has used
+    seed: u64,
+    rng: StdRng,
+    batches: usize,
+    // Time spent playing
+    elapsed: Duration,
     held_tet: Option<TetType>,
     already_held: bool,
+    placed: usize,
+    last_lock: Option<Lock>,
+    // The board the game started from and every piece locked since, for
+    // replays
+    start_tets: Tets,
+    history: Vec<Tet>,
+    presses: usize,
+    finesse_faults: usize,
+    stats: Stats,
+    // What's shown at the bottom of the sidebar while playing
+    hud: Vec<Metric>,
+    effects: Effects,
+    animations: Animations,
+    // A line about whoever is playing, e.g. a bot's name or why it stopped
+    status: Option<String>,
+    // Hints or other help were used at some point this game
+    assisted: bool,
     fall_timer: Duration,
     fall_mode: FallMode,
     spawn_timer: Duration,
     move_timer: Duration,
     moving: Moving,
+    // Auto-shift delay and repeat interval
+    move_wait: Duration,
+    move_interval: Duration,
 }
 
 impl Game {
-    const NORMAL_INTERVAL: Duration = Duration::from_secs(1);
-    // TODO: maybe this should shorten depending on the level, or have a total
-    // limit even with resets (piece has to stop eventually)
-    const LOCK_DELAY: Duration = Duration::from_millis(500);
-    const SOFT_DROP_INTERVAL: Duration = Duration::from_millis(100);
-    // TODO: maybe there is only a wait at the end of a normal/soft drop
-    const SPAWN_INTERVAL: Duration = Duration::from_millis(0);
-    const MOVE_WAIT: Duration = Duration::from_millis(300);
-    const MOVE_INTERVAL: Duration = Duration::from_millis(70);
-
-    pub fn new(ctx: &mut Context) -> GameResult<Self> {
-        let next_batch = TetType::batch();
-
-        let game = Self {
+    pub const MOVE_WAIT: Duration = Duration::from_millis(300);
+    pub const MOVE_INTERVAL: Duration = Duration::from_millis(70);
+
+    pub fn new() -> GameResult<Self> {
+        Self::with_board(STANDARD_WIDTH, STANDARD_HEIGHT)
+    }
+
+    // A game on a board that isn't empty, of that board's size
+    pub fn with_tets(tets: Tets) -> GameResult<Self> {
+        let mut game = Self::with_board(tets.width(), tets.height())?;
+        game.set_tets(tets);
+        Ok(game)
+    }
+
+    pub fn with_board(width: usize, height: usize) -> GameResult<Self> {
+        Self::with_pieces(width, height, PieceSet::standard())
+    }
+
+    pub fn with_pieces(width: usize, height: usize, pieces: PieceSet) -> GameResult<Self> {
+        Self::with_rules(width, height, pieces, Ruleset::default())
+    }
+
+    pub fn with_rules(
+        width: usize,
+        height: usize,
+        pieces: PieceSet,
+        rules: Ruleset,
+    ) -> GameResult<Self> {
+        Tets::check_size(width, height).map_err(GameError::ConfigError)?;
+        Tets::check_pieces(width, height, &pieces).map_err(GameError::ConfigError)?;
+        let seed = rand::random();
+        // Stands in until the first piece is dealt, if there is one
+        let placeholder = pieces.pieces()[0];
+        let start_level = rules.levels.first;
+        let fall_timer = rules.gravity(start_level);
+        let spawn_timer = rules.spawn_delay();
+
+        let mut game = Self {
             state: GameState::Playing,
-            assets: Assets::load(ctx)?,
+            pieces,
+            rules,
+            recent: Vec::new(),
             score: 0,
             lines: 0,
-            tets: Tets::default(),
-            current_tet: Tet::new(next_batch[0], Point2::new(3, 0)),
-            has_tet: true,
-            next_batch,
-            next_tet: 1,
+            start_level,
+            tets: Tets::new(width, height),
+            current_tet: Tet::spawn(placeholder, width),
+            has_tet: false,
+            rotated_last: false,
+            queue: Vec::new(),
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+            batches: 0,
+            elapsed: Duration::from_secs(0),
             held_tet: None,
             already_held: false,
-            fall_timer: Self::NORMAL_INTERVAL,
+            placed: 0,
+            last_lock: None,
+            start_tets: Tets::new(width, height),
+            history: Vec::new(),
+            presses: 0,
+            finesse_faults: 0,
+            stats: Stats::default(),
+            hud: Metric::defaults(),
+            effects: Effects::new(),
+            animations: Animations::new(),
+            status: None,
+            assisted: false,
+            fall_timer,
             fall_mode: FallMode::Normal,
-            spawn_timer: Self::SPAWN_INTERVAL,
+            spawn_timer,
             move_timer: Self::MOVE_WAIT,
             moving: Moving::None,
+            move_wait: Self::MOVE_WAIT,
+            move_interval: Self::MOVE_INTERVAL,
         };
+        game.set_handling(Self::MOVE_WAIT, Self::MOVE_INTERVAL);
+        game.fill_queue();
+        game.deal_first();
 
         Ok(game)
     }
 
-    fn restart(&mut self) {
+    pub fn restart(&mut self) {
         self.state = GameState::Playing;
 
         self.score = 0;
         self.lines = 0;
 
-        self.tets = Tets::default();
-        self.next_batch = TetType::batch();
-        self.current_tet = Tet::new(self.next_batch[0], Point2::new(3, 0));
-        self.has_tet = true;
-        self.next_tet = 1;
+        self.tets = self.tets.empty();
+        self.seed = rand::random();
+        self.rng = StdRng::seed_from_u64(self.seed);
+        self.batches = 0;
+        self.recent.clear();
+        self.elapsed = Duration::from_secs(0);
+        self.queue.clear();
+        self.fill_queue();
+        self.deal_first();
 
         self.held_tet = None;
         self.already_held = false;
+        self.placed = 0;
+        self.last_lock = None;
+        self.start_tets = self.tets.empty();
+        self.history.clear();
+        self.presses = 0;
+        self.finesse_faults = 0;
+        self.stats = Stats::default();
+        self.animations.clear();
+        self.assisted = false;
+
+        // Nothing held down or counting carries over from the last game
+        self.fall_mode = FallMode::Normal;
+        self.fall_timer = self.fall_interval();
+        self.spawn_timer = self.rules.spawn_delay();
+        self.move_timer = self.move_wait;
+        self.moving = Moving::None;
     }
 
-    fn new_tet(&mut self) {
-        for block in self.current_tet.blocks.iter() {
-            self.tets.set(
-                self.current_tet.pos.y + block.y,
-                self.current_tet.pos.x + block.x,
-                self.current_tet.tet_type
-            );
+    // The first piece of a game, if the randomizer deals any
+    fn deal_first(&mut self) {
+        self.has_tet = false;
+        if let Some(first) = self.next_piece() {
+            self.current_tet = Tet::spawn(first, self.tets.width());
+            self.has_tet = true;
         }
-        let mut clears = 0;
-        for row in 0..TILES_HIGH {
-            if self.tets.row_full(row as i8) {
-                self.tets.clear(row as i8);
-                clears += 1;
+        self.rotated_last = false;
+    }
+
+    // The game's state, if it's still going
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        if self.is_over() {
+            return None;
+        }
+        Some(Snapshot {
+            score: self.score,
+            lines: self.lines,
+            tets: self.tets.clone(),
+            current_tet: self.current_tet().cloned(),
+            queue: self.queue.clone(),
+            seed: self.seed,
+            batches: self.batches,
+            elapsed: self.elapsed,
+            held_tet: self.held_tet,
+            already_held: self.already_held,
+            placed: self.placed,
+            start_tets: self.start_tets.clone(),
+            history: self.history.clone(),
+            finesse_faults: self.finesse_faults,
+            assisted: self.assisted,
+            fall_timer: self.fall_timer,
+            spawn_timer: self.spawn_timer,
+            start_level: Some(self.start_level),
+            stats: self.stats.clone(),
+        })
+    }
+
+    // Carries on from a snapshot, with no keys held
+    pub fn resume(&mut self, snapshot: Snapshot) {
+        self.state = GameState::Playing;
+        self.score = snapshot.score;
+        self.lines = snapshot.lines;
+        self.start_level = snapshot.start_level.unwrap_or(self.rules.levels.first);
+        self.tets = snapshot.tets;
+        self.has_tet = snapshot.current_tet.is_some();
+        if let Some(tet) = snapshot.current_tet {
+            self.current_tet = tet;
+        }
+        self.seed = snapshot.seed;
+        self.rng = StdRng::seed_from_u64(snapshot.seed);
+        self.recent.clear();
+        for _ in 0..snapshot.batches {
+            self.rules.batch(&self.pieces, &mut self.recent, &mut self.rng);
+        }
+        self.batches = snapshot.batches;
+        self.elapsed = snapshot.elapsed;
+        self.queue = snapshot.queue;
+        self.fill_queue();
+        self.held_tet = snapshot.held_tet;
+        self.already_held = snapshot.already_held;
+        self.placed = snapshot.placed;
+        self.last_lock = None;
+        self.start_tets = snapshot.start_tets;
+        self.history = snapshot.history;
+        self.rotated_last = false;
+        self.presses = 0;
+        self.finesse_faults = snapshot.finesse_faults;
+        self.stats = snapshot.stats;
+        self.animations.clear();
+        self.assisted = snapshot.assisted;
+        self.fall_timer = snapshot.fall_timer;
+        self.fall_mode = FallMode::Normal;
+        self.spawn_timer = snapshot.spawn_timer;
+        self.move_timer = self.move_wait;
+        self.moving = Moving::None;
+    }
+
+    pub fn tets(&self) -> &Tets {
+        &self.tets
+    }
+
+    // None while waiting for the next piece to spawn
+    pub fn current_tet(&self) -> Option<&Tet> {
+        if self.has_tet { Some(&self.current_tet) } else { None }
+    }
+
+    pub fn pieces(&self) -> &PieceSet {
+        &self.pieces
+    }
+
+    pub fn rules(&self) -> &Ruleset {
+        &self.rules
+    }
+
+    // Begins at a later level, as far as the ruleset allows. The piece in
+    // play starts falling again at the new level's speed.
+    pub fn set_start_level(&mut self, level: usize) {
+        let levels = &self.rules.levels;
+        self.start_level = level.clamp(levels.first, levels.highest_start.unwrap_or(levels.first));
+        self.fall_timer = self.fall_interval();
+    }
+
+    // Upcoming pieces, in the order they will be spawned
+    pub fn queue(&self) -> &[TetType] {
+        &self.queue
+    }
+
+    // Makes these the next pieces, ahead of the random ones
+    pub fn set_queue(&mut self, pieces: &[TetType]) {
+        self.queue = pieces.to_vec();
+        self.fill_queue();
+    }
+
+    fn fill_queue(&mut self) {
+        while self.queue.len() < 7 {
+            let batch = self.rules.batch(&self.pieces, &mut self.recent, &mut self.rng);
+            // A randomizer that deals nothing leaves the queue to run out
+            if batch.is_empty() {
+                break;
             }
+            self.queue.extend(batch);
+            self.batches += 1;
+        }
+    }
+
+    fn next_piece(&mut self) -> Option<TetType> {
+        if self.queue.is_empty() {
+            return None;
         }
+        let tet_type = self.queue.remove(0);
+        self.fill_queue();
+        Some(tet_type)
+    }
+
+    pub fn score(&self) -> usize {
+        self.score
+    }
+
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn held_tet(&self) -> Option<TetType> {
+        self.held_tet
+    }
+
+    // Hold can be used once per piece, where the rules have it at all.
+    // Holding with nothing held takes the next piece, if there is one.
+    pub fn can_hold(&self) -> bool {
+        self.rules.allow.hold && !self.already_held
+            && (self.held_tet.is_some() || !self.queue.is_empty())
+    }
+
+    // Puts a piece in the hold, e.g. to set up a drill
+    pub fn set_held(&mut self, held: Option<TetType>) {
+        self.held_tet = held;
+    }
+
+    // Number of pieces locked into the board since the game started
+    pub fn placed(&self) -> usize {
+        self.placed
+    }
+
+    pub fn last_lock(&self) -> Option<&Lock> {
+        self.last_lock.as_ref()
+    }
+
+    // The starting board and the pieces locked onto it, in order
+    pub fn replay(&self) -> (&Tets, &[Tet]) {
+        (&self.start_tets, &self.history)
+    }
+
+    pub fn finesse_faults(&self) -> usize {
+        self.finesse_faults
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    pub fn set_hud(&mut self, hud: Vec<Metric>) {
+        self.hud = hud;
+    }
+
+    // Visual effects the player has turned off
+    pub fn set_effects_off(&mut self, off: Vec<Effect>) {
+        self.animations.set_off(off);
+    }
+
+    pub fn set_sounds(&mut self, pack: &str, volume: f32) {
+        self.effects.set_pack(pack);
+        self.effects.set_volume(volume);
+    }
+
+    // Plays a sound on the next update, for things the game itself doesn't
+    // know about
+    pub fn cue(&mut self, sound: Sound) {
+        self.effects.cue(sound);
+    }
+
+    pub fn set_status(&mut self, status: Option<String>) {
+        self.status = status;
+    }
+
+    pub fn assisted(&self) -> bool {
+        self.assisted
+    }
+
+    // Marks the game as helped along, e.g. by hints or a perfect clear
+    // search
+    pub fn assist(&mut self) {
+        self.assisted = true;
+    }
+
+    // Replaces the board, e.g. to set up a drill
+    pub fn set_tets(&mut self, tets: Tets) {
+        self.start_tets = tets.clone();
+        self.history.clear();
+        self.tets = tets;
+    }
+
+    // Replaces the current piece with a new one at the top of the board
+    pub fn spawn(&mut self, tet_type: TetType) {
+        self.spawn_tet(tet_type);
+        self.already_held = false;
+        self.fall_timer = self.fall_interval();
+    }
+
+    pub fn is_dead(&self) -> bool {
+        matches!(self.state, GameState::Dead)
+    }
+
+    // Dead, or finished
+    pub fn is_over(&self) -> bool {
+        matches!(self.state, GameState::Dead | GameState::Finished)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        matches!(self.state, GameState::Paused)
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.state = match self.state {
+            GameState::Playing => GameState::Paused,
+            GameState::Paused => GameState::Playing,
+            _ => return,
+        };
+        // Keys let go while paused never got their release
+        self.moving = Moving::None;
+        self.fall_mode = FallMode::Normal;
+    }
+
+    // Ends the game without topping out
+    pub fn finish(&mut self) {
+        self.state = GameState::Finished;
+        self.has_tet = false;
+    }
+
+    // How long holding left or right waits before repeating, and how often
+    // it repeats, unless the ruleset fixes them
+    pub fn set_handling(&mut self, move_wait: Duration, move_interval: Duration) {
+        self.move_wait = self.rules.das().unwrap_or(move_wait);
+        self.move_interval = self.rules.arr().unwrap_or(move_interval);
+    }
+
+    // Shows a board and piece without playing, e.g. for replays
+    pub fn show(&mut self, tets: Tets, piece: Option<Tet>) {
+        self.tets = tets;
+        self.has_tet = piece.is_some();
+        if let Some(tet) = piece {
+            self.current_tet = tet;
+        }
+    }
+
+    fn new_tet(&mut self) {
+        let cells = self.current_tet.cells();
+        let optimal = optimal_presses(&self.tets, &self.current_tet, &self.rules);
+        let t_spin = movegen::is_t_spin(&self.current_tet, self.rotated_last, &self.tets);
+        self.animations.clear_rows(&self.tets.filled_by(&self.current_tet));
+        self.animations.lock(&cells);
+        let clears = self.tets.lock(&self.current_tet);
+        let lock = Lock {
+            tet: self.current_tet.clone(),
+            lines: clears,
+            t_spin,
+            presses: self.presses,
+            optimal,
+        };
+        if lock.finesse_fault() {
+            self.finesse_faults += 1;
+        }
+        let perfect_clear = clears > 0 && self.tets.iter().flatten().all(Option::is_none);
+        self.stats.record(&lock, perfect_clear);
+        self.effects.cue(Sound::Lock);
+        if let Some(sound) = Sound::clear(clears, t_spin) {
+            self.effects.cue(sound);
+        }
+        if let Some(combo) = self.stats.combo.filter(|&combo| combo > 0) {
+            self.effects.cue(Sound::Combo(combo));
+        }
+        self.call_out(&lock, perfect_clear);
+        self.last_lock = Some(lock);
+        self.history.push(self.current_tet.clone());
         if clears > 0 {
+            let level = self.level();
             self.add_score(clears);
             // Add to lines after score, so score is calculated on pre-clear
             // level
             self.lines += clears;
+            if self.level() > level {
+                self.effects.cue(Sound::LevelUp);
+            }
         }
+        self.placed += 1;
         self.has_tet = false;
-        self.spawn_timer = Self::SPAWN_INTERVAL;
+        self.spawn_timer = self.rules.spawn_delay();
+    }
+
+    // Names and shakes for the clears worth making a fuss over
+    fn call_out(&mut self, lock: &Lock, perfect_clear: bool) {
+        let name = match (lock.t_spin, lock.lines) {
+            (true, lines) => Some(format!("T-SPIN{}", ["", " SINGLE", " DOUBLE", " TRIPLE"][lines.min(3)])),
+            (false, lines) if lines >= 4 => Some("TETRIS".to_string()),
+            _ => None,
+        };
+        if let Some(name) = name {
+            self.animations.callout(name);
+        }
+        if lock.lines >= 4 || (lock.t_spin && lock.lines > 0) {
+            self.animations.shake(2.0 + lock.lines as f32);
+        }
+        if self.stats.b2b.is_some_and(|b2b| b2b > 0) && lock.lines > 0 {
+            self.animations.callout("B2B".to_string());
+        }
+        if let Some(combo) = self.stats.combo.filter(|&combo| combo > 0) {
+            self.animations.callout(format!("COMBO {}", combo));
+        }
+        if perfect_clear {
+            self.animations.callout("PERFECT CLEAR".to_string());
+        }
     }
 
     fn preview_tet(&self) -> Tet {
@@ -208,44 +885,192 @@ impl Game {
     }
 
     fn spawn_tet(&mut self, tet_type: TetType) {
-        self.current_tet = Tet::new(tet_type, Point2::new(3, 0));
-        for block in self.current_tet.blocks.iter() {
-            if self.tets.at(
+        self.current_tet = Tet::spawn(tet_type, self.tets.width());
+        self.rotated_last = false;
+        self.presses = 0;
+        let blocked = self.current_tet.blocks().iter().any(|block| {
+            self.tets.at(
                 self.current_tet.pos.y + block.y,
                 self.current_tet.pos.x + block.x
-            ).is_some() {
-                self.state = GameState::Dead;
-                self.has_tet = false;
+            ).is_some()
+        });
+        if blocked {
+            self.effects.cue(Sound::TopOut);
+            match self.rules.top_out {
+                TopOut::End => self.state = GameState::Dead,
+                TopOut::Clear => self.tets = self.tets.empty(),
             }
         }
         self.has_tet = true;
     }
 
+    // Where the game is now, to go back to with `restore`
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            score: self.score,
+            lines: self.lines,
+            tets: self.tets.clone(),
+            current: self.current_tet.tet_type,
+            queue: self.queue.clone(),
+            rng: self.rng.clone(),
+            recent: self.recent.clone(),
+            batches: self.batches,
+            held_tet: self.held_tet,
+            already_held: self.already_held,
+            placed: self.placed,
+            finesse_faults: self.finesse_faults,
+            stats: self.stats.clone(),
+        }
+    }
+
+    // Goes back to a checkpoint, with its piece at the top again. Does
+    // nothing while no piece is in play.
+    pub fn restore(&mut self, checkpoint: Checkpoint) {
+        if !self.has_tet {
+            return;
+        }
+        self.score = checkpoint.score;
+        self.lines = checkpoint.lines;
+        self.tets = checkpoint.tets;
+        self.queue = checkpoint.queue;
+        self.rng = checkpoint.rng;
+        self.recent = checkpoint.recent;
+        self.batches = checkpoint.batches;
+        self.held_tet = checkpoint.held_tet;
+        self.already_held = checkpoint.already_held;
+        self.placed = checkpoint.placed;
+        self.finesse_faults = checkpoint.finesse_faults;
+        self.stats = checkpoint.stats;
+        self.animations.clear();
+        self.history.truncate(self.placed);
+        self.last_lock = None;
+        self.spawn_tet(checkpoint.current);
+        self.fall_timer = self.fall_interval();
+    }
+
     fn hard_drop(&mut self) {
-        while self.current_tet.fall(&self.tets) { self.score += 2; }
+        self.effects.cue(Sound::HardDrop);
+        let cells = self.current_tet.cells();
+        let mut rows = 0;
+        while self.current_tet.fall(&self.tets) {
+            self.score += self.rules.scoring.hard_drop;
+            self.rotated_last = false;
+            rows += 1;
+        }
+        let color = Block::Tet(self.current_tet.tet_type).color();
+        self.animations.hard_drop(&cells, rows, color);
         self.new_tet();
         self.fall_timer = self.fall_interval();
     }
 
+    pub fn press(&mut self, action: Action) {
+        if self.is_over() || self.is_paused() {
+            return;
+        }
+        // Shifting the way the piece is already shifting is the same press
+        if let (Action::Left, Moving::Left) | (Action::Right, Moving::Right) = (action, &self.moving) {
+            return;
+        }
+        if self.has_tet && action.is_movement() {
+            self.presses += 1;
+        }
+        if self.has_tet {
+            self.stats.keys += 1;
+        }
+        match action {
+            Action::Left if self.has_tet => {
+                self.moving = Moving::Left;
+                self.move_timer = self.move_wait;
+                let moved = self.current_tet.move_left(&self.tets);
+                self.moved(moved);
+            },
+            Action::Right if self.has_tet => {
+                self.moving = Moving::Right;
+                self.move_timer = self.move_wait;
+                let moved = self.current_tet.move_right(&self.tets);
+                self.moved(moved);
+            },
+            Action::RotateCw if self.has_tet => self.rotate(Some(RotationDir::Clockwise)),
+            Action::RotateCcw if self.has_tet => self.rotate(Some(RotationDir::CounterClockwise)),
+            Action::Rotate180 if self.has_tet && self.rules.allow.rotate_180 => self.rotate(None),
+            Action::HardDrop if self.has_tet && self.rules.allow.hard_drop => self.hard_drop(),
+            Action::SoftDrop => {
+                if self.has_tet {
+                    self.effects.cue(Sound::SoftDrop);
+                }
+                self.fall_mode = FallMode::SoftDrop;
+                self.fall_timer = Duration::from_secs(0);
+            },
+            Action::Hold if self.has_tet && self.can_hold() => {
+                self.effects.cue(Sound::Hold);
+                self.already_held = true;
+                if let Some(held_tet) = self.held_tet {
+                    self.held_tet = Some(self.current_tet.tet_type);
+                    self.spawn_tet(held_tet);
+                } else if let Some(tet_type) = self.next_piece() {
+                    self.held_tet = Some(self.current_tet.tet_type);
+                    self.spawn_tet(tet_type);
+                }
+            }
+            _ => ()
+        }
+    }
+
+    // A quarter turn, or a half turn with no direction, kicking as the
+    // ruleset says
+    fn rotate(&mut self, dir: Option<RotationDir>) {
+        let pos = self.current_tet.pos;
+        let rotated = movegen::rotate(&mut self.current_tet, dir, &self.rules, &self.tets);
+        if rotated && self.current_tet.at_bottom(&self.tets) {
+            self.fall_timer = self.rules.lock_delay();
+        }
+        if rotated {
+            self.rotated_last = true;
+            self.effects.cue(if self.current_tet.pos == pos { Sound::Rotate } else { Sound::Kick });
+        }
+    }
+
+    // After a shift sideways
+    fn moved(&mut self, moved: bool) {
+        if moved {
+            self.effects.cue(Sound::Move);
+            self.rotated_last = false;
+            if self.current_tet.at_bottom(&self.tets) {
+                self.fall_timer = self.rules.lock_delay();
+            }
+        }
+    }
+
+    pub fn release(&mut self, action: Action) {
+        match action {
+            Action::SoftDrop => self.fall_mode = FallMode::Normal,
+            Action::Left => {
+                if let Moving::Left = self.moving {
+                    self.moving = Moving::None;
+                }
+            },
+            Action::Right => {
+                if let Moving::Right = self.moving {
+                    self.moving = Moving::None;
+                }
+            },
+            _ => ()
+        }
+    }
+
     fn fall_interval(&self) -> Duration {
         match self.fall_mode {
-            FallMode::Normal => NORMAL_INTERVALS[self.level()],
-            FallMode::SoftDrop => Self::SOFT_DROP_INTERVAL,
+            FallMode::Normal => self.rules.gravity(self.level()),
+            FallMode::SoftDrop => self.rules.soft_drop(),
         }
     }
 
-    fn level(&self) -> usize {
-        std::cmp::min(1 + self.lines / 10, 20)
+    pub fn level(&self) -> usize {
+        self.rules.level(self.lines, self.start_level)
     }
 
     fn add_score(&mut self, line_clears: usize) {
-        self.score += match line_clears {
-            1 => self.level() * 100,
-            2 => self.level() * 300,
-            3 => self.level() * 500,
-            4 => self.level() * 800,
-            _ => 0, // Should never happen
-        };
+        self.score += self.rules.clear_score(line_clears, self.level());
     }
 }
 
@@ -262,30 +1087,36 @@ fn decrement(lhs: Duration, rhs: Duration) -> TimerState {
     }
 }
 
-impl event::EventHandler for Game {
-    fn update(&mut self, ctx: &mut Context) -> GameResult {
+impl Game {
+    pub fn update(&mut self, ctx: &mut Context) -> GameResult {
         const DESIRED_FPS: u32 = 60;
 
         while ggez::timer::check_update_time(ctx, DESIRED_FPS) {
             // TODO: update logic in here
         }
+        self.effects.play(ctx);
 
-        if let GameState::Dead = self.state {
+        if !self.is_paused() {
+            self.animations.update(ggez::timer::delta(ctx));
+        }
+        if self.is_over() || self.is_paused() {
             return Ok(())
         }
+        self.elapsed += ggez::timer::delta(ctx);
 
         if self.has_tet {
             match decrement(self.fall_timer, ggez::timer::delta(ctx)) {
                 TimerState::Ticking(time) => self.fall_timer = time,
                 TimerState::Done => {
                     if let FallMode::SoftDrop = self.fall_mode {
-                        self.score += 1;
+                        self.score += self.rules.scoring.soft_drop;
                     }
                     if !self.current_tet.fall(&self.tets) {
                         self.new_tet();
                     } else {
+                        self.rotated_last = false;
                         self.fall_timer = if self.current_tet.at_bottom(&self.tets) {
-                            Self::LOCK_DELAY
+                            self.rules.lock_delay()
                         } else {
                             self.fall_interval()
                         }
@@ -295,14 +1126,24 @@ impl event::EventHandler for Game {
         } else {
             self.spawn_timer = match decrement(self.spawn_timer, ggez::timer::delta(ctx)) {
                 TimerState::Ticking(time) => time,
-                TimerState::Done => {
-                    self.spawn_tet(self.next_batch[self.next_tet]);
-                    self.next_tet = (self.next_tet + 1) % 7;
-                    if self.next_tet == 0 {
-                        self.next_batch = TetType::batch();
-                    }
-                    self.already_held = false;
-                    Self::SPAWN_INTERVAL
+                TimerState::Done => match self.next_piece() {
+                    Some(tet_type) => {
+                        self.spawn_tet(tet_type);
+                        self.already_held = false;
+                        self.rules.spawn_delay()
+                    },
+                    // Out of pieces, bar the one held
+                    None => match self.held_tet.take() {
+                        Some(held_tet) => {
+                            self.spawn_tet(held_tet);
+                            self.already_held = true;
+                            self.rules.spawn_delay()
+                        },
+                        None => {
+                            self.finish();
+                            return Ok(());
+                        },
+                    },
                 },
             };
         }
@@ -318,10 +1159,8 @@ impl event::EventHandler for Game {
                             Moving::Right => self.current_tet.move_right(&self.tets),
                             _ => false,
                         };
-                        if actually_moved && self.current_tet.at_bottom(&self.tets) {
-                            self.fall_timer = Self::LOCK_DELAY;
-                        }
-                        Self::MOVE_INTERVAL
+                        self.moved(actually_moved);
+                        self.move_interval
                     },
                 };
             },
@@ -329,106 +1168,72 @@ impl event::EventHandler for Game {
 
         Ok(())
     }
+}
 
-    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, repeat: bool) {
-        if let KeyCode::Escape = keycode {
-            event::quit(ctx);
-        }
-        if let GameState::Dead = self.state {
-            if let KeyCode::R = keycode {
-                self.restart();
-            }
-            return;
-        }
-        match keycode {
-            KeyCode::Left if self.has_tet && !repeat => {
-                if let Moving::Left = self.moving {
-                    return
-                }
-                self.moving = Moving::Left;
-                self.move_timer = Self::MOVE_WAIT;
-                let moved = self.current_tet.move_left(&self.tets);
-                if moved && self.current_tet.at_bottom(&self.tets) {
-                    self.fall_timer = Self::LOCK_DELAY;
-                }
-            },
-            KeyCode::Right if self.has_tet && !repeat => {
-                if let Moving::Right = self.moving {
-                    return
-                }
-                self.moving = Moving::Right;
-                self.move_timer = Self::MOVE_WAIT;
-                let moved = self.current_tet.move_right(&self.tets);
-                if moved && self.current_tet.at_bottom(&self.tets) {
-                    self.fall_timer = Self::LOCK_DELAY;
-                }
-            },
-            KeyCode::X | KeyCode::Up if self.has_tet => {
-                let rotated = self.current_tet.rotate(RotationDir::Clockwise, &self.tets);
-                if rotated && self.current_tet.at_bottom(&self.tets) {
-                    self.fall_timer = Self::LOCK_DELAY;
-                }
-            },
-            KeyCode::Z if self.has_tet => {
-                let rotated = self.current_tet.rotate(RotationDir::CounterClockwise, &self.tets);
-                if rotated && self.current_tet.at_bottom(&self.tets) {
-                    self.fall_timer = Self::LOCK_DELAY;
-                }
-            }
-            KeyCode::Space if self.has_tet => self.hard_drop(),
-            KeyCode::Down => {
-                if !repeat {
-                    self.fall_mode = FallMode::SoftDrop;
-                    self.fall_timer = Duration::from_secs(0);
-                }
-            },
-            KeyCode::LShift | KeyCode::RShift if !self.already_held => {
-                self.already_held = true;
-                if let Some(held_tet) = self.held_tet {
-                    self.held_tet = Some(self.current_tet.tet_type);
-                    self.spawn_tet(held_tet);
-                } else {
-                    self.held_tet = Some(self.current_tet.tet_type);
-                    self.spawn_tet(self.next_batch[self.next_tet]);
-                    self.next_tet = (self.next_tet + 1) % 7;
-                    if self.next_tet == 0 {
-                        self.next_batch = TetType::batch();
-                    }
-                }
-            }
-            _ => ()
-        }
+impl Game {
+    // The window around this game's board, never smaller than the menus so
+    // high score tables still fit
+    pub fn window_size(&self) -> (f32, f32) {
+        (
+            ((self.tets.width() as f32 + SIDEBAR_WIDTH * 2.0) * TILE_SIZE).max(WINDOW_WIDTH),
+            (self.tets.height() as f32 * TILE_SIZE).max(WINDOW_HEIGHT),
+        )
     }
 
-    fn key_up_event(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymods: KeyMods) {
-        match keycode {
-            KeyCode::Down => self.fall_mode = FallMode::Normal,
-            KeyCode::Left => {
-                if let Moving::Left = self.moving {
-                    self.moving = Moving::None;
-                }
-            },
-            KeyCode::Right => {
-                if let Moving::Right = self.moving {
-                    self.moving = Moving::None;
-                }
-            },
-            _ => ()
+    // Middle of the board, for centering text over it
+    pub fn board_center(&self) -> f32 {
+        (SIDEBAR_WIDTH + self.tets.width() as f32 / 2.0) * TILE_SIZE
+    }
+
+    // Left edge of the sidebar right of the board
+    pub fn sidebar_x(&self) -> f32 {
+        (SIDEBAR_WIDTH + self.tets.width() as f32) * TILE_SIZE
+    }
+
+    // A piece in the hold or next box, whose top left is at `corner`
+    fn draw_piece_box(&self, ctx: &mut Context, skin: &Skin, tet_type: TetType, corner: Point2f32) -> GameResult {
+        let (offset, scale) = tet_type.preview();
+        for block in tet_type.blocks(Rot::Zero).iter() {
+            skin.draw_block(
+                ctx,
+                Block::Tet(tet_type),
+                Point2f32::new(
+                    corner.x + TILE_SIZE * scale * (block.x as f32 + offset.x),
+                    corner.y + TILE_SIZE * scale * (block.y as f32 + offset.y)
+                ),
+                TILE_SIZE * scale,
+            )?;
         }
+        Ok(())
     }
 
-    fn draw(&mut self, ctx: &mut Context) -> GameResult {
-        graphics::clear(ctx, Color::from_rgb(80, 80, 80));
+    // Draws everything but the background, so several games can share a
+    // window
+    pub fn draw_game(&self, ctx: &mut Context, skin: &Skin) -> GameResult {
+        let offset = match self.animations.shake_offset() {
+            Some(offset) => offset,
+            None => return self.draw_contents(ctx, skin),
+        };
+        let shaken = Matrix4::from(graphics::transform(ctx)) * Matrix4::new_translation(&offset.push(0.0));
+        graphics::push_transform(ctx, Some(shaken));
+        graphics::apply_transformations(ctx)?;
+        let drawn = self.draw_contents(ctx, skin);
+        graphics::pop_transform(ctx);
+        graphics::apply_transformations(ctx)?;
+        drawn
+    }
 
-        let play_area = graphics::Mesh::new_rectangle(
-            ctx,
-            graphics::DrawMode::fill(),
-            [
-                SIDEBAR_WIDTH * TILE_SIZE, 0.0,
-                TILES_WIDE as f32 * TILE_SIZE, TILES_HIGH as f32 * TILE_SIZE
-            ].into(),
-            graphics::BLACK,
-        )?;
+    // A line under "Paused" or the final score, e.g. which key carries on
+    pub fn draw_prompt(&self, ctx: &mut Context, skin: &Skin, prompt: &str) -> GameResult {
+        let size = if self.is_over() { 32.0 } else { 24.0 };
+        let text = skin.text(prompt, size);
+        let offset = text.width(ctx) as f32 / 2.0;
+        graphics::draw(ctx, &text, (Point2f32::new(self.board_center() - offset, 210.0), skin.text_color()))
+    }
+
+    fn draw_contents(&self, ctx: &mut Context, skin: &Skin) -> GameResult {
+        let (board_width, board_height) = (self.tets.width() as f32, self.tets.height() as f32);
+        let (_, window_height) = self.window_size();
         let hold_area = graphics::Mesh::new_rectangle(
             ctx,
             graphics::DrawMode::stroke(2.0),
@@ -436,150 +1241,357 @@ impl event::EventHandler for Game {
                 20.0, 40.0,
                 TILE_SIZE * 4.0, TILE_SIZE * 4.0
             ].into(),
-            graphics::WHITE,
+            skin.frame(),
         )?;
         if let Some(held_tet) = self.held_tet {
-            let offset = held_tet.center_4x4();
-            for block in held_tet.blocks().iter() {
-                graphics::draw(
-                    ctx,
-                    &self.assets.block_sprites[&held_tet],
-                    DrawParam::default()
-                        .dest(Point2f32::new(
-                            20.0 + TILE_SIZE * (block.x as f32 + offset.x),
-                            40.0 + TILE_SIZE * (block.y as f32 + offset.y)
-                        ))
-                )?;
-            }
+            self.draw_piece_box(ctx, skin, held_tet, Point2f32::new(20.0, 40.0))?;
         }
+        let allow = self.rules.allow;
         let next_area = graphics::Mesh::new_rectangle(
             ctx,
             graphics::DrawMode::stroke(2.0),
             [
-                (SIDEBAR_WIDTH + TILES_WIDE as f32) * TILE_SIZE + 20.0, 40.0,
+                self.sidebar_x() + 20.0, 40.0,
                 TILE_SIZE * 4.0, TILE_SIZE * 4.0
             ].into(),
-            graphics::WHITE,
+            skin.frame(),
         )?;
-        let offset = self.next_batch[self.next_tet].center_4x4();
-        for block in self.next_batch[self.next_tet].blocks().iter() {
-            graphics::draw(
-                ctx,
-                &self.assets.block_sprites[&self.next_batch[self.next_tet]],
-                DrawParam::default()
-                    .dest(Point2f32::new(
-                        20.0 + TILE_SIZE * (SIDEBAR_WIDTH + TILES_WIDE as f32 + block.x as f32 + offset.x),
-                        40.0 + TILE_SIZE * (block.y as f32 + offset.y)
-                    ))
-            )?;
+        if let Some(&next) = self.queue.first() {
+            self.draw_piece_box(ctx, skin, next, Point2f32::new(self.sidebar_x() + 20.0, 40.0))?;
         }
 
-        graphics::draw(ctx, &play_area, DrawParam::default())?;
-        graphics::draw(ctx, &hold_area, DrawParam::default())?;
+        skin.draw_board(ctx, [
+            SIDEBAR_WIDTH * TILE_SIZE, 0.0,
+            board_width * TILE_SIZE, board_height * TILE_SIZE
+        ].into())?;
+        if allow.hold {
+            graphics::draw(ctx, &hold_area, DrawParam::default())?;
+        }
         graphics::draw(ctx, &next_area, DrawParam::default())?;
 
         for (y, row) in self.tets.iter().enumerate() {
             for (x, block) in row.iter().enumerate() {
+                let dest = Point2f32::new(
+                    SIDEBAR_WIDTH * TILE_SIZE + TILE_SIZE * x as f32,
+                    TILE_SIZE * y as f32,
+                );
                 if let Some(block) = block {
-                    graphics::draw(
-                        ctx,
-                        &self.assets.block_sprites[&block],
-                        DrawParam::default()
-                            .dest(Point2f32::new(
-                                SIDEBAR_WIDTH * TILE_SIZE + TILE_SIZE * x as f32,
-                                TILE_SIZE * y as f32,
-                            ))
-                    )?;
+                    skin.draw_block(ctx, *block, dest, TILE_SIZE)?;
                 }
             }
         }
-        if self.has_tet {
+        if self.has_tet && allow.ghost {
             let preview_tet = self.preview_tet();
-            for block in preview_tet.blocks.iter() {
-                graphics::draw(
+            for block in preview_tet.blocks().iter() {
+                skin.draw_ghost(
                     ctx,
-                    &self.assets.preview_sprite,
-                    DrawParam::default()
-                        .dest(Point2f32::new(
-                            SIDEBAR_WIDTH * TILE_SIZE + TILE_SIZE * (preview_tet.pos.x + block.x) as f32,
-                            TILE_SIZE * (preview_tet.pos.y + block.y) as f32,
-                        ))
+                    preview_tet.tet_type,
+                    Point2f32::new(
+                        SIDEBAR_WIDTH * TILE_SIZE + TILE_SIZE * (preview_tet.pos.x + block.x) as f32,
+                        TILE_SIZE * (preview_tet.pos.y + block.y) as f32,
+                    ),
                 )?;
             }
-            for block in self.current_tet.blocks.iter() {
-                graphics::draw(
+        }
+        if self.has_tet {
+            for block in self.current_tet.blocks().iter() {
+                skin.draw_block(
                     ctx,
-                    &self.assets.block_sprites[&self.current_tet.tet_type],
-                    DrawParam::default()
-                        .dest(Point2f32::new(
-                            SIDEBAR_WIDTH * TILE_SIZE + TILE_SIZE * (self.current_tet.pos.x + block.x) as f32,
-                            TILE_SIZE * (self.current_tet.pos.y + block.y) as f32,
-                        ))
+                    Block::Tet(self.current_tet.tet_type),
+                    Point2f32::new(
+                        SIDEBAR_WIDTH * TILE_SIZE + TILE_SIZE * (self.current_tet.pos.x + block.x) as f32,
+                        TILE_SIZE * (self.current_tet.pos.y + block.y) as f32,
+                    ),
+                    TILE_SIZE,
                 )?;
             }
         }
 
-        if let GameState::Dead = self.state {
+        self.animations.draw(ctx, Point2f32::new(SIDEBAR_WIDTH * TILE_SIZE, 0.0), self.board_center())?;
+
+        if self.is_over() || self.is_paused() {
             let overlay = graphics::Mesh::new_rectangle(
                 ctx,
                 graphics::DrawMode::fill(),
                 [
                     SIDEBAR_WIDTH * TILE_SIZE, 0.0,
-                    TILES_WIDE as f32 * TILE_SIZE, TILES_HIGH as f32 * TILE_SIZE
+                    board_width * TILE_SIZE, board_height * TILE_SIZE
                 ].into(),
                 Color::from_rgba(10, 10, 10, 230),
             )?;
             graphics::draw(ctx, &overlay, DrawParam::default())?;
-            let mut score_display = Text::new(format!("Final Score: {}", self.score));
-            score_display.set_font(graphics::Font::default(), graphics::Scale::uniform(32.0));
+        }
+
+        let center = self.board_center();
+        if self.is_paused() {
+            let paused_display = skin.text("Paused", 32.0);
+            let offset = paused_display.width(ctx) as f32 / 2.0;
+            graphics::draw(ctx, &paused_display, (Point2f32::new(center - offset, 170.0), skin.text_color()))?;
+        }
+
+        if self.is_over() {
+            let score_display = skin.text(if self.assisted {
+                format!("Final Score: {}*", self.score)
+            } else {
+                format!("Final Score: {}", self.score)
+            }, 32.0);
             let offset = score_display.width(ctx) as f32 / 2.0;
             graphics::draw(
                 ctx,
                 &score_display,
-                (Point2f32::new((SIDEBAR_WIDTH + TILES_WIDE as f32 / 2.0) * TILE_SIZE - offset, 170.0),
-                graphics::WHITE),
+                (Point2f32::new(center - offset, 170.0),
+                skin.text_color()),
             )?;
-            let mut restart_display = Text::new("Press R to Restart");
-            restart_display.set_font(graphics::Font::default(), graphics::Scale::uniform(32.0));
-            let offset = restart_display.width(ctx) as f32 / 2.0;
+            let mut breakdown = skin.text(self.stats.breakdown(self.elapsed).join("\n"), 14.0);
+            breakdown.set_bounds(Point2f32::new(board_width * TILE_SIZE, f32::INFINITY), graphics::Align::Center);
             graphics::draw(
                 ctx,
-                &restart_display,
-                (Point2f32::new((SIDEBAR_WIDTH + TILES_WIDE as f32 / 2.0) * TILE_SIZE - offset, 210.0),
-                graphics::WHITE),
+                &breakdown,
+                (Point2f32::new(SIDEBAR_WIDTH * TILE_SIZE, 255.0), skin.text_color()),
             )?;
+        } else {
+            // Counted up from the bottom, leaving the space under the next
+            // box to the modes
+            for (i, metric) in self.hud.iter().rev().enumerate() {
+                graphics::draw(
+                    ctx,
+                    &skin.text(metric.show(&self.stats, self.elapsed), graphics::DEFAULT_FONT_SCALE),
+                    (Point2f32::new(self.sidebar_x() + 10.0, window_height - 25.0 * (i + 1) as f32), skin.text_color()),
+                )?;
+            }
         }
 
         let fps = timer::fps(ctx);
-        let fps_display = Text::new(format!("FPS: {:.0}", fps));
+        let fps_display = skin.text(format!("FPS: {:.0}", fps), graphics::DEFAULT_FONT_SCALE);
         graphics::draw(
             ctx,
             &fps_display,
-            (Point2f32::new(10.0, 10.0), graphics::WHITE),
+            (Point2f32::new(10.0, 10.0), skin.text_color()),
         )?;
 
-        let lines_display = Text::new(format!("Lines: {}", self.lines));
+        let lines_display = skin.text(format!("Lines: {}", self.lines), graphics::DEFAULT_FONT_SCALE);
         graphics::draw(
             ctx,
             &lines_display,
-            (Point2f32::new(10.0, WINDOW_HEIGHT - 25.0), graphics::WHITE),
+            (Point2f32::new(10.0, window_height - 25.0), skin.text_color()),
         )?;
 
-        let level_display = Text::new(format!("Level: {}", self.level()));
+        let level_display = skin.text(format!("Level: {}", self.level()), graphics::DEFAULT_FONT_SCALE);
         graphics::draw(
             ctx,
             &level_display,
-            (Point2f32::new(10.0, WINDOW_HEIGHT - 50.0), graphics::WHITE),
+            (Point2f32::new(10.0, window_height - 50.0), skin.text_color()),
         )?;
 
-        let score_display = Text::new(format!("Score: {}", self.score));
+        let score_display = skin.text(format!("Score: {}", self.score), graphics::DEFAULT_FONT_SCALE);
         graphics::draw(
             ctx,
             &score_display,
-            (Point2f32::new(10.0, WINDOW_HEIGHT - 75.0), graphics::WHITE),
+            (Point2f32::new(10.0, window_height - 75.0), skin.text_color()),
+        )?;
+
+        let finesse_display = skin.text(format!("Finesse: {}", self.finesse_faults), graphics::DEFAULT_FONT_SCALE);
+        graphics::draw(
+            ctx,
+            &finesse_display,
+            (Point2f32::new(10.0, window_height - 100.0), skin.text_color()),
         )?;
 
-        graphics::present(ctx)?;
+        if let Some(status) = &self.status {
+            let mut status_display = skin.text(status.as_str(), graphics::DEFAULT_FONT_SCALE);
+            status_display.set_bounds(Point2f32::new(SIDEBAR_WIDTH * TILE_SIZE - 20.0, f32::INFINITY), graphics::Align::Left);
+            graphics::draw(
+                ctx,
+                &status_display,
+                (Point2f32::new(10.0, 130.0), skin.text_color()),
+            )?;
+        }
+
+        if self.assisted {
+            let assisted_display = skin.text("* Assisted", graphics::DEFAULT_FONT_SCALE);
+            graphics::draw(
+                ctx,
+                &assisted_display,
+                (Point2f32::new(10.0, window_height - 125.0), skin.text_color()),
+            )?;
+        }
+
         Ok(())
     }
 }
+
+// Saves a fumen as fumen.txt in the user data directory
+pub fn export_fumen(ctx: &mut Context, data: &str) -> GameResult {
+    let mut file = filesystem::create(ctx, "/fumen.txt")?;
+    file.write_all(data.as_bytes())?;
+    Ok(())
+}
+
+// Outlines board cells, for marking placements on top of the playfield
+pub fn draw_outline(ctx: &mut Context, cells: &[Point2], color: Color) -> GameResult {
+    for cell in cells {
+        let outline = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::stroke(2.0),
+            [
+                SIDEBAR_WIDTH * TILE_SIZE + TILE_SIZE * cell.x as f32 + 1.0,
+                TILE_SIZE * cell.y as f32 + 1.0,
+                TILE_SIZE - 2.0, TILE_SIZE - 2.0
+            ].into(),
+            color,
+        )?;
+        graphics::draw(ctx, &outline, DrawParam::default())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod test_boards {
+    use super::{Block, Tets};
+
+    // A board with these rows at the bottom, `#` for a filled cell
+    pub(crate) fn board(rows: &[&str]) -> Tets {
+        let mut tets = Tets::default();
+        let top = tets.height() - rows.len();
+        for (i, row) in rows.iter().enumerate() {
+            for (col, mark) in row.chars().enumerate() {
+                if mark == '#' {
+                    tets.set((top + i) as i8, col as i8, Some(Block::Garbage));
+                }
+            }
+        }
+        tets
+    }
+
+    // Every cell of the board, top row first, to compare boards with
+    pub(crate) fn rows(tets: &Tets) -> Vec<Vec<Option<Block>>> {
+        tets.iter().map(|row| row.to_vec()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::movegen::Input;
+    use crate::rules::Randomizer;
+
+    // Locks a T moved from spawn on an empty board with these keys, counting
+    // presses the way `Game::press` does
+    fn lock_after(inputs: &[Input]) -> Lock {
+        let (tets, rules) = (Tets::default(), Ruleset::default());
+        let mut tet = Tet::spawn(TetType::T, tets.width());
+        for &input in inputs {
+            movegen::apply(&mut tet, input, &rules, &tets);
+        }
+        movegen::apply(&mut tet, Input::Hold(Action::SoftDrop), &rules, &tets);
+        let optimal = optimal_presses(&tets, &tet, &rules);
+        Lock { tet, lines: 0, t_spin: false, presses: inputs.len(), optimal }
+    }
+
+    #[test]
+    fn finesse_faults() {
+        let tapped = lock_after(&[Input::Tap(Action::Left), Input::Tap(Action::Left), Input::Tap(Action::Left)]);
+        assert!(tapped.finesse_fault());
+        assert!(!lock_after(&[Input::Hold(Action::Left)]).finesse_fault());
+
+        let turned = [Input::Tap(Action::RotateCcw), Input::Tap(Action::RotateCcw), Input::Tap(Action::RotateCcw)];
+        assert!(lock_after(&turned).finesse_fault());
+        assert!(!lock_after(&[Input::Tap(Action::RotateCw)]).finesse_fault());
+    }
+
+    // Soft dropping, or a shift's key repeating while held, isn't another
+    // press
+    #[test]
+    fn soft_drop_then_hard_drop() {
+        let mut game = Game::new().unwrap();
+        game.spawn(TetType::T);
+        game.press(Action::Left);
+        game.press(Action::Left);
+        game.release(Action::Left);
+        game.press(Action::SoftDrop);
+        game.release(Action::SoftDrop);
+        game.press(Action::HardDrop);
+        let lock = game.last_lock().unwrap();
+        assert_eq!(lock.presses, 1);
+        assert!(!lock.finesse_fault());
+        assert_eq!(game.stats().keys, 3);
+    }
+
+    #[test]
+    fn restart_lets_go_of_keys() {
+        let mut game = Game::new().unwrap();
+        game.press(Action::Left);
+        game.press(Action::SoftDrop);
+        game.restart();
+        assert!(matches!(game.moving, Moving::None));
+        assert!(matches!(game.fall_mode, FallMode::Normal));
+        assert_eq!(game.fall_timer, game.fall_interval());
+
+        // Shifting again is a new press
+        let x = game.current_tet().unwrap().pos.x;
+        game.press(Action::Left);
+        assert_eq!(game.current_tet().unwrap().pos.x, x - 1);
+    }
+
+    fn snapshot(tets: Tets, current_tet: Option<Tet>) -> Snapshot {
+        Snapshot {
+            score: 0,
+            lines: 0,
+            start_tets: tets.clone(),
+            tets,
+            current_tet,
+            queue: vec![TetType::I, TetType::O],
+            seed: 0,
+            batches: 1,
+            elapsed: Duration::from_secs(0),
+            held_tet: None,
+            already_held: false,
+            placed: 0,
+            history: Vec::new(),
+            finesse_faults: 0,
+            assisted: false,
+            fall_timer: Duration::from_secs(0),
+            spawn_timer: Duration::from_secs(0),
+            start_level: None,
+            stats: Stats::default(),
+        }
+    }
+
+    #[test]
+    fn snapshot_checks() {
+        let (pieces, rules) = (PieceSet::standard(), Ruleset::default());
+        let spawned = Some(Tet::spawn(TetType::T, STANDARD_WIDTH));
+        assert!(snapshot(Tets::default(), spawned.clone()).check(10, 20, &pieces, &rules).is_ok());
+        assert!(snapshot(Tets::default(), spawned.clone()).check(12, 20, &pieces, &rules).is_err());
+
+        let mut blocked = Tets::default();
+        blocked.set(1, 4, Some(Block::Garbage));
+        assert!(snapshot(blocked, spawned.clone()).check(10, 20, &pieces, &rules).is_err());
+
+        let mut held = snapshot(Tets::default(), spawned);
+        held.held_tet = PieceSet::trominoes().pieces().first().copied();
+        assert!(held.check(10, 20, &pieces, &rules).is_err());
+    }
+
+    #[test]
+    fn only_the_queue_given() {
+        let rules = Ruleset { randomizer: Randomizer::None, ..Ruleset::default() };
+        let mut game = Game::with_rules(STANDARD_WIDTH, STANDARD_HEIGHT, PieceSet::standard(), rules).unwrap();
+        assert!(game.current_tet().is_none());
+        game.spawn(TetType::T);
+        assert!(!game.can_hold());
+
+        game.set_queue(&[TetType::O]);
+        game.press(Action::Hold);
+        assert_eq!(game.held_tet(), Some(TetType::T));
+        assert_eq!(game.current_tet().map(|tet| tet.tet_type), Some(TetType::O));
+        assert!(game.queue().is_empty());
+    }
+
+    #[test]
+    fn pieces_fit_the_board() {
+        assert!(Tets::check_pieces(4, 4, &PieceSet::standard()).is_ok());
+        assert!(Tets::check_pieces(4, 20, &PieceSet::pentominoes()).is_err());
+        assert!(Tets::check_pieces(5, 5, &PieceSet::pentominoes()).is_ok());
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::BTreeMap;
+
+use ggez::event::{Button, KeyCode};
+
+use serde::{Deserialize, Serialize};
+
+// Everything a player (or bot) can ask the engine to do with the current
+// piece
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Left,
+    Right,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
+    SoftDrop,
+    HardDrop,
+    Hold,
+}
+
+impl Action {
+    // Actions that start something on press and stop it on release, rather
+    // than happening once per (possibly repeated) press
+    pub fn is_held(&self) -> bool {
+        matches!(self, Action::Left | Action::Right | Action::SoftDrop)
+    }
+
+    // Keys that steer the piece, which are what finesse counts. Soft drops
+    // aren't, as one before the drop to the floor changes nothing.
+    pub fn is_movement(&self) -> bool {
+        matches!(
+            self,
+            Action::Left | Action::Right | Action::RotateCw | Action::RotateCcw | Action::Rotate180
+        )
+    }
+}
+
+// What a player can bind keys to: the piece actions, and controlling the
+// game itself
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Control {
+    Left,
+    Right,
+    SoftDrop,
+    HardDrop,
+    RotateCw,
+    RotateCcw,
+    Rotate180,
+    Hold,
+    Pause,
+    Restart,
+    // Taking back a placement and putting it back, where the rules allow
+    Undo,
+    Redo,
+    // Steps through the hint overlays
+    Hints,
+    // Shows or hides a perfect clear, while practicing a setup
+    PerfectClear,
+}
+
+impl Control {
+    pub const ALL: [Control; 14] = [
+        Control::Left,
+        Control::Right,
+        Control::SoftDrop,
+        Control::HardDrop,
+        Control::RotateCw,
+        Control::RotateCcw,
+        Control::Rotate180,
+        Control::Hold,
+        Control::Pause,
+        Control::Restart,
+        Control::Undo,
+        Control::Redo,
+        Control::Hints,
+        Control::PerfectClear,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Control::Left => "Move left",
+            Control::Right => "Move right",
+            Control::SoftDrop => "Soft drop",
+            Control::HardDrop => "Hard drop",
+            Control::RotateCw => "Rotate CW",
+            Control::RotateCcw => "Rotate CCW",
+            Control::Rotate180 => "Rotate 180",
+            Control::Hold => "Hold",
+            Control::Pause => "Pause",
+            Control::Restart => "Restart",
+            Control::Undo => "Undo",
+            Control::Redo => "Redo",
+            Control::Hints => "Cycle hints",
+            Control::PerfectClear => "Perfect clear",
+        }
+    }
+
+    pub fn action(&self) -> Option<Action> {
+        match self {
+            Control::Left => Some(Action::Left),
+            Control::Right => Some(Action::Right),
+            Control::SoftDrop => Some(Action::SoftDrop),
+            Control::HardDrop => Some(Action::HardDrop),
+            Control::RotateCw => Some(Action::RotateCw),
+            Control::RotateCcw => Some(Action::RotateCcw),
+            Control::Rotate180 => Some(Action::Rotate180),
+            Control::Hold => Some(Action::Hold),
+            Control::Pause | Control::Restart | Control::Undo | Control::Redo | Control::Hints
+                | Control::PerfectClear => None,
+        }
+    }
+}
+
+// Something a control can be bound to: a key or a gamepad button
+pub trait Bindable: Copy + PartialEq + std::fmt::Debug + 'static {
+    // Everything that can be bound, which is also how inputs are looked up
+    // by name
+    const ALL: &'static [Self];
+
+    fn defaults(control: Control) -> Vec<Self>;
+
+    // Inputs with a fixed meaning everywhere, which can't be bound
+    fn is_reserved(self) -> bool;
+
+    fn name(self) -> String {
+        format!("{:?}", self)
+    }
+
+    fn named(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|input| input.name() == name)
+    }
+
+    fn is_bindable(self) -> bool {
+        Self::ALL.contains(&self) && !self.is_reserved()
+    }
+}
+
+impl Bindable for KeyCode {
+    const ALL: &'static [KeyCode] = &[
+        KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+        KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N,
+        KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R, KeyCode::S, KeyCode::T, KeyCode::U,
+        KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+        KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+        KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+        KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3, KeyCode::Numpad4,
+        KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7, KeyCode::Numpad8, KeyCode::Numpad9,
+        KeyCode::Add, KeyCode::Subtract, KeyCode::Multiply, KeyCode::Divide, KeyCode::Decimal,
+        KeyCode::NumpadEnter,
+        KeyCode::Left, KeyCode::Right, KeyCode::Up, KeyCode::Down,
+        KeyCode::Space, KeyCode::Return, KeyCode::Tab, KeyCode::Back,
+        KeyCode::LShift, KeyCode::RShift, KeyCode::LControl, KeyCode::RControl,
+        KeyCode::LAlt, KeyCode::RAlt,
+        KeyCode::Insert, KeyCode::Delete, KeyCode::Home, KeyCode::End,
+        KeyCode::PageUp, KeyCode::PageDown,
+        KeyCode::Comma, KeyCode::Period, KeyCode::Slash, KeyCode::Backslash,
+        KeyCode::Semicolon, KeyCode::Apostrophe, KeyCode::LBracket, KeyCode::RBracket,
+        KeyCode::Minus, KeyCode::Equals, KeyCode::Grave,
+        KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+        KeyCode::Escape,
+    ];
+
+    fn defaults(control: Control) -> Vec<KeyCode> {
+        match control {
+            Control::Left => vec![KeyCode::Left],
+            Control::Right => vec![KeyCode::Right],
+            Control::SoftDrop => vec![KeyCode::Down],
+            Control::HardDrop => vec![KeyCode::Space],
+            Control::RotateCw => vec![KeyCode::X, KeyCode::Up],
+            Control::RotateCcw => vec![KeyCode::Z],
+            Control::Rotate180 => vec![KeyCode::A],
+            Control::Hold => vec![KeyCode::C, KeyCode::LShift, KeyCode::RShift],
+            Control::Pause => vec![KeyCode::P],
+            Control::Restart => vec![KeyCode::R],
+            Control::Undo => vec![KeyCode::Back],
+            Control::Redo => vec![KeyCode::Return],
+            Control::Hints => vec![KeyCode::H],
+            Control::PerfectClear => vec![KeyCode::F],
+        }
+    }
+
+    // Leaving a screen and exporting fumen
+    fn is_reserved(self) -> bool {
+        matches!(self, KeyCode::Escape | KeyCode::F5 | KeyCode::F6)
+    }
+}
+
+impl Bindable for Button {
+    const ALL: &'static [Button] = &[
+        Button::South, Button::East, Button::North, Button::West, Button::C, Button::Z,
+        Button::LeftTrigger, Button::LeftTrigger2, Button::RightTrigger, Button::RightTrigger2,
+        Button::Select, Button::Start, Button::Mode,
+        Button::LeftThumb, Button::RightThumb,
+        Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight,
+    ];
+
+    fn defaults(control: Control) -> Vec<Button> {
+        match control {
+            Control::Left => vec![Button::DPadLeft],
+            Control::Right => vec![Button::DPadRight],
+            Control::SoftDrop => vec![Button::DPadDown],
+            Control::HardDrop => vec![Button::DPadUp],
+            Control::RotateCw => vec![Button::East],
+            Control::RotateCcw => vec![Button::South],
+            Control::Rotate180 => vec![Button::North],
+            Control::Hold => vec![Button::LeftTrigger, Button::RightTrigger],
+            Control::Pause => vec![Button::Start],
+            Control::Restart => vec![Button::Select],
+            Control::Undo => vec![Button::LeftTrigger2],
+            Control::Redo => vec![Button::RightTrigger2],
+            Control::Hints | Control::PerfectClear => Vec::new(),
+        }
+    }
+
+    // The guide button leaves a screen, like Escape
+    fn is_reserved(self) -> bool {
+        matches!(self, Button::Mode)
+    }
+}
+
+// The keys or buttons bound to each control. Stored by name, so the file can
+// be edited by hand.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "SavedBindings", from = "SavedBindings", bound = "I: Bindable")]
+pub struct Bindings<I: Bindable> {
+    bindings: BTreeMap<Control, Vec<I>>,
+}
+
+pub type KeyMap = Bindings<KeyCode>;
+pub type ButtonMap = Bindings<Button>;
+
+type SavedBindings = BTreeMap<Control, Vec<String>>;
+
+impl<I: Bindable> Default for Bindings<I> {
+    fn default() -> Bindings<I> {
+        Bindings {
+            bindings: Control::ALL.iter()
+                .map(|&control| (control, I::defaults(control)))
+                .collect(),
+        }
+    }
+}
+
+impl<I: Bindable> Bindings<I> {
+    // Inputs each control can have at once
+    pub const MAX_INPUTS: usize = 3;
+
+    pub fn control(&self, input: I) -> Option<Control> {
+        self.bindings.iter()
+            .find(|(_, inputs)| inputs.contains(&input))
+            .map(|(&control, _)| control)
+    }
+
+    pub fn action(&self, input: I) -> Option<Action> {
+        self.control(input).and_then(|control| control.action())
+    }
+
+    pub fn inputs(&self, control: Control) -> &[I] {
+        self.bindings.get(&control).map_or(&[], Vec::as_slice)
+    }
+
+    // The inputs bound to a control, for showing to the player
+    pub fn describe(&self, control: Control) -> String {
+        let inputs = self.inputs(control);
+        if inputs.is_empty() {
+            return "none".to_string();
+        }
+        inputs.iter().map(|input| input.name()).collect::<Vec<_>>().join(", ")
+    }
+
+    // The first input bound to a control, for prompts
+    pub fn prompt(&self, control: Control) -> String {
+        self.inputs(control).first().map_or("?".to_string(), |input| input.name())
+    }
+
+    // Adds an input to a control, replacing its oldest if it has too many.
+    // Checking the input isn't reserved or already taken is up to the caller.
+    pub fn bind(&mut self, control: Control, input: I) {
+        let inputs = self.bindings.entry(control).or_default();
+        if inputs.len() >= Self::MAX_INPUTS {
+            inputs.remove(0);
+        }
+        inputs.push(input);
+    }
+
+    pub fn clear(&mut self, control: Control) {
+        self.bindings.insert(control, Vec::new());
+    }
+}
+
+impl<I: Bindable> From<Bindings<I>> for SavedBindings {
+    fn from(bindings: Bindings<I>) -> SavedBindings {
+        bindings.bindings.into_iter()
+            .map(|(control, inputs)| (control, inputs.into_iter().map(I::name).collect()))
+            .collect()
+    }
+}
+
+// Controls missing from the file keep their defaults, and inputs that can't
+// be bound are dropped
+impl<I: Bindable> From<SavedBindings> for Bindings<I> {
+    fn from(saved: SavedBindings) -> Bindings<I> {
+        let mut bindings = Bindings::default();
+        for (control, names) in saved {
+            let bound = names.iter()
+                .filter_map(|name| I::named(name))
+                .filter(|&input| input.is_bindable())
+                .take(Self::MAX_INPUTS)
+                .collect();
+            bindings.bindings.insert(control, bound);
+        }
+        bindings
+    }
+}
@@ -1,4 +1,32 @@
 #![allow(dead_code)]
 
+pub mod ai;
+pub mod audio;
+pub mod bot;
+pub mod editor;
+pub mod effects;
+pub mod fumen;
 pub mod game;
-mod tet;
+pub mod input;
+pub mod menu;
+pub mod movegen;
+pub mod openers;
+pub mod pc;
+pub mod person;
+pub mod pieces;
+pub mod player;
+pub mod practice;
+pub mod profile;
+pub mod puzzle;
+pub mod records;
+pub mod rules;
+pub mod save;
+pub mod scene;
+pub mod scores;
+pub mod session;
+pub mod settings;
+pub mod skin;
+pub mod stats;
+pub mod tet;
+pub mod trainer;
+pub mod versus;
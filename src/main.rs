@@ -1,14 +1,32 @@
+use std::env;
 use std::path;
 
-use ggez::{ContextBuilder, GameResult};
+use ggez::{Context, ContextBuilder, GameError, GameResult};
 use ggez::conf::{WindowMode, WindowSetup};
 use ggez::event;
 
+use tetris::ai::{AiController, Difficulty, Weights};
+use tetris::bot::TbpBot;
 use tetris::game::{self, Game};
+use tetris::menu::{self, TitleScreen};
+use tetris::player::{AutoPlayer, Controller};
+use tetris::scene::{Scene, SceneStack};
+use tetris::trainer::FinesseTrainer;
+use tetris::versus::Versus;
 
+// Usage:
+//   tetris                        title screen
+//   tetris --finesse              finesse trainer
+//   tetris --fumen <data>         practice a setup shared as fumen
+//   tetris --demo                 watch the built-in AI play
+//   tetris --cpu <difficulty>     race the built-in AI (easy, medium, hard)
+//   tetris --bot <command...>     watch a TBP bot play
+//   tetris --versus <command...>  race a TBP bot
 fn main() -> GameResult {
     let resource_dir = path::PathBuf::from("./res");
+    let args: Vec<String> = env::args().skip(1).collect();
 
+    // The scene stack resizes the window to fit the first scene
     let (ref mut ctx, ref mut event_loop) = ContextBuilder::new("tetris", "peter")
         .window_setup(WindowSetup::default()
             .title("tetris"))
@@ -17,6 +35,41 @@ fn main() -> GameResult {
         .add_resource_path(resource_dir)
         .build()?;
 
-    let mut game = Game::new(ctx)?;
-    event::run(ctx, event_loop, &mut game)
+    let scene = first_scene(ctx, &args)?;
+    let mut stack = SceneStack::new(ctx, scene)?;
+    event::run(ctx, event_loop, &mut stack)
+}
+
+fn first_scene(ctx: &mut Context, args: &[String]) -> GameResult<Box<dyn Scene>> {
+    let auto_player = |ctx: &mut Context, controller: Box<dyn Controller>| -> GameResult<AutoPlayer> {
+        AutoPlayer::new(ctx, Game::new()?, controller)
+    };
+    let difficulty = || -> GameResult<Difficulty> {
+        let name = args.get(1).map_or("medium", String::as_str);
+        Difficulty::from_name(name).ok_or_else(|| {
+            GameError::ConfigError(format!("unknown difficulty {:?}", name))
+        })
+    };
+
+    Ok(match args.first().map(String::as_str) {
+        Some("--finesse") => Box::new(FinesseTrainer::new(ctx)?),
+        Some("--fumen") => {
+            let data = args.get(1).ok_or_else(|| {
+                GameError::ConfigError("no fumen given".to_string())
+            })?;
+            Box::new(menu::practice(ctx, data)?)
+        },
+        Some("--demo") => Box::new(auto_player(ctx, Box::new(AiController::attract()))?),
+        Some("--cpu") => {
+            let cpu = AiController::new(difficulty()?, Weights::default());
+            let opponent = auto_player(ctx, Box::new(cpu))?;
+            Box::new(Versus::new(ctx, opponent)?)
+        },
+        Some("--bot") => Box::new(auto_player(ctx, Box::new(TbpBot::new(&args[1..])?))?),
+        Some("--versus") => {
+            let opponent = auto_player(ctx, Box::new(TbpBot::new(&args[1..])?))?;
+            Box::new(Versus::new(ctx, opponent)?)
+        },
+        _ => Box::new(TitleScreen::new()),
+    })
 }
@@ -0,0 +1,329 @@
+// The title screen and the menus leading from it to each mode.
+
+use std::io::Read;
+
+use ggez::{Context, GameResult};
+use ggez::event::{KeyCode, KeyMods};
+use ggez::filesystem;
+
+use crate::ai::{AiController, Difficulty, Weights};
+use crate::editor::Editor;
+use crate::fumen;
+use crate::game::{Game, Tets};
+use crate::openers::OpenerSelect;
+use crate::player::AutoPlayer;
+use crate::practice::Practice;
+use crate::profile::StatsScreen;
+use crate::puzzle::PackSelect;
+use crate::records::{RecordsScreen, ReplaysScreen};
+use crate::rules::Levels;
+use crate::scene::{self, Menu, Scene, Transition};
+use crate::session::{Mode, Session};
+use crate::settings::SettingsScreen;
+use crate::tet::TetType;
+use crate::trainer::FinesseTrainer;
+use crate::versus::{Versus, VersusLobby};
+
+// Where practice setups are read from; the same file F5 and F6 export to
+const PRACTICE_PATH: &str = "/fumen.txt";
+
+// Shows a scene, or keeps why it couldn't be made to show under the menu
+pub fn open<S: Scene + 'static>(scene: GameResult<S>, error: &mut Option<String>) -> Transition {
+    match scene {
+        Ok(scene) => {
+            *error = None;
+            Transition::Push(Box::new(scene))
+        },
+        Err(err) => {
+            *error = Some(err.to_string());
+            Transition::None
+        },
+    }
+}
+
+pub struct TitleScreen {
+    menu: Menu,
+}
+
+impl TitleScreen {
+    pub fn new() -> TitleScreen {
+        TitleScreen {
+            menu: Menu::new("Tetris", &["Play", "Records", "Replays", "Statistics", "Settings", "Quit"]),
+        }
+    }
+}
+
+impl Default for TitleScreen {
+    fn default() -> TitleScreen {
+        TitleScreen::new()
+    }
+}
+
+impl Scene for TitleScreen {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if let KeyCode::Escape = keycode {
+            return Transition::Quit;
+        }
+        match self.menu.key_down(keycode) {
+            Some(0) => Transition::Push(Box::new(ModeSelect::new())),
+            Some(1) => Transition::Push(Box::new(RecordsScreen::new(ctx))),
+            Some(2) => Transition::Push(Box::new(ReplaysScreen::new(ctx))),
+            Some(3) => Transition::Push(Box::new(StatsScreen::new(ctx))),
+            Some(4) => Transition::Push(Box::new(SettingsScreen::new(ctx))),
+            Some(_) => Transition::Quit,
+            None => Transition::None,
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)
+    }
+}
+
+pub struct ModeSelect {
+    menu: Menu,
+    // Why the last mode couldn't be started
+    error: Option<String>,
+}
+
+impl ModeSelect {
+    pub fn new() -> ModeSelect {
+        ModeSelect {
+            menu: Menu::new("Play", &[
+                "Marathon",
+                "Sprint: 40 lines",
+                "Ultra: 2 minutes",
+                "Dig: clear garbage",
+                "4-Wide: 4x20 board",
+                "Big Board: 20x20 board",
+                "Tall: 10x40 board",
+                "Pentomino: 5-block pieces",
+                "Tromino: 3-block pieces",
+                "Custom pieces: pieces.json",
+                "NES: classic rules",
+                "TGM-ish: arcade rules",
+                "House rules: rules.toml",
+                "Zen: no game over, undo",
+                "Versus CPU",
+                "Local versus",
+                "Practice",
+                "Puzzles",
+            ]),
+            error: None,
+        }
+    }
+}
+
+impl Default for ModeSelect {
+    fn default() -> ModeSelect {
+        ModeSelect::new()
+    }
+}
+
+impl Scene for ModeSelect {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if let KeyCode::Escape = keycode {
+            return Transition::Pop;
+        }
+        match self.menu.key_down(keycode) {
+            Some(i) if i < Mode::ALL.len() => {
+                let mode = Mode::ALL[i];
+                // Rulesets that let the player pick a start level ask first
+                match mode.rules(ctx).map(|rules| rules.levels) {
+                    Ok(Levels { first, highest_start: Some(highest), .. }) => {
+                        Transition::Push(Box::new(LevelSelect::new(mode, first, highest)))
+                    },
+                    _ => open(Session::new(ctx, mode), &mut self.error),
+                }
+            },
+            // The rest come after the modes
+            Some(i) => match i - Mode::ALL.len() {
+                0 => Transition::Push(Box::new(VersusSelect::new())),
+                1 => Transition::Push(Box::new(VersusLobby::new())),
+                2 => Transition::Push(Box::new(PracticeSelect::new())),
+                _ => Transition::Push(Box::new(PackSelect::new(ctx))),
+            },
+            None => Transition::None,
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)?;
+        scene::draw_errors(ctx, self.error.as_slice())
+    }
+}
+
+// Picks the level a mode's game starts at
+pub struct LevelSelect {
+    menu: Menu,
+    mode: Mode,
+    first: usize,
+    error: Option<String>,
+}
+
+impl LevelSelect {
+    pub fn new(mode: Mode, first: usize, highest: usize) -> LevelSelect {
+        let levels = (first..=highest).map(|level| format!("Level {}", level)).collect();
+        LevelSelect {
+            menu: Menu::with_items(mode.title(), levels),
+            mode,
+            first,
+            error: None,
+        }
+    }
+}
+
+impl Scene for LevelSelect {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if let KeyCode::Escape = keycode {
+            return Transition::Pop;
+        }
+        match self.menu.key_down(keycode) {
+            Some(i) => open(Session::starting_at(ctx, self.mode, self.first + i), &mut self.error),
+            None => Transition::None,
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)?;
+        scene::draw_errors(ctx, self.error.as_slice())
+    }
+}
+
+pub struct VersusSelect {
+    menu: Menu,
+    error: Option<String>,
+}
+
+impl VersusSelect {
+    const DIFFICULTIES: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Medium, Difficulty::Hard];
+
+    pub fn new() -> VersusSelect {
+        VersusSelect { menu: Menu::new("Versus CPU", &["Easy", "Medium", "Hard"]), error: None }
+    }
+}
+
+impl Default for VersusSelect {
+    fn default() -> VersusSelect {
+        VersusSelect::new()
+    }
+}
+
+impl Scene for VersusSelect {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if let KeyCode::Escape = keycode {
+            return Transition::Pop;
+        }
+        match self.menu.key_down(keycode) {
+            Some(i) => {
+                let cpu = AiController::new(Self::DIFFICULTIES[i], Weights::default());
+                open(Game::new()
+                    .and_then(|game| AutoPlayer::new(ctx, game, Box::new(cpu)))
+                    .and_then(|opponent| Versus::new(ctx, opponent)), &mut self.error)
+            },
+            None => Transition::None,
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)?;
+        scene::draw_errors(ctx, self.error.as_slice())
+    }
+}
+
+pub struct PracticeSelect {
+    menu: Menu,
+    // Why the last setup couldn't be loaded
+    error: Option<String>,
+}
+
+impl PracticeSelect {
+    pub fn new() -> PracticeSelect {
+        PracticeSelect {
+            menu: Menu::new("Practice", &["Finesse trainer", "Setup from fumen.txt", "Openers", "Board editor"]),
+            error: None,
+        }
+    }
+}
+
+impl Default for PracticeSelect {
+    fn default() -> PracticeSelect {
+        PracticeSelect::new()
+    }
+}
+
+// A game starting from the first page of a fumen, with the pieces it places
+// coming first, or the hold and queue of its quiz if it has one
+pub fn practice(ctx: &mut Context, data: &str) -> GameResult<Practice> {
+    let pages = fumen::decode(data)?;
+    let quiz = pages[0].comment.as_deref().and_then(fumen::parse_quiz);
+    let (held, pieces) = quiz.unwrap_or_else(|| {
+        let pieces = pages.iter()
+            .filter_map(|page| page.piece.as_ref().map(|tet| tet.tet_type))
+            .collect();
+        (None, pieces)
+    });
+    setup(ctx, pages[0].tets.clone(), held, pieces)
+}
+
+// A game starting from a board, with a piece held and the given pieces first
+pub fn setup(ctx: &mut Context, tets: Tets, held: Option<TetType>, pieces: Vec<TetType>) -> GameResult<Practice> {
+    let game = Game::with_board(tets.width(), tets.height())?;
+    Practice::new(ctx, game, tets, held, pieces)
+}
+
+fn practice_from_file(ctx: &mut Context) -> GameResult<Practice> {
+    let mut data = String::new();
+    filesystem::open(ctx, PRACTICE_PATH)?.read_to_string(&mut data)?;
+    practice(ctx, &data)
+}
+
+impl Scene for PracticeSelect {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if let KeyCode::Escape = keycode {
+            return Transition::Pop;
+        }
+        let scene: GameResult<Box<dyn Scene>> = match self.menu.key_down(keycode) {
+            Some(0) => FinesseTrainer::new(ctx).map(|trainer| Box::new(trainer) as Box<dyn Scene>),
+            Some(1) => practice_from_file(ctx).map(|practice| Box::new(practice) as Box<dyn Scene>),
+            Some(2) => OpenerSelect::new(ctx).map(|select| Box::new(select) as Box<dyn Scene>),
+            Some(_) => Ok(Box::new(Editor::new()) as Box<dyn Scene>),
+            None => return Transition::None,
+        };
+        match scene {
+            Ok(scene) => {
+                self.error = None;
+                Transition::Push(scene)
+            },
+            Err(err) => {
+                self.error = Some(err.to_string());
+                Transition::None
+            },
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)?;
+        scene::draw_errors(ctx, self.error.as_slice())
+    }
+}
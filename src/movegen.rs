@@ -0,0 +1,315 @@
+// Finds every place a piece can lock, by searching the moves a player could
+// make with the real movement and rotation (kick) code, under the ruleset's
+// kicks and allowed actions. Used by bots, the AI, hints and finesse checking.
+
+use std::collections::HashSet;
+
+use crate::game::Tets;
+use crate::input::Action;
+use crate::rules::Ruleset;
+use crate::tet::{Rot, RotationDir, Tet, TetType};
+
+type Point2 = ggez::nalgebra::Point2<i8>;
+
+// One key press
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Input {
+    // Press and release straight away
+    Tap(Action),
+    // Keep the key down until the piece stops moving: auto-shift to the wall
+    // for `Left`/`Right`, or all the way down for `SoftDrop`
+    Hold(Action),
+}
+
+// Moves tried at each step of the search, in order of preference when two
+// paths are the same length. Half turns are only tried where the rules allow
+// them.
+const MOVES: [Input; 8] = [
+    Input::Tap(Action::Left),
+    Input::Tap(Action::Right),
+    Input::Tap(Action::RotateCw),
+    Input::Tap(Action::RotateCcw),
+    Input::Tap(Action::Rotate180),
+    Input::Hold(Action::Left),
+    Input::Hold(Action::Right),
+    Input::Hold(Action::SoftDrop),
+];
+
+#[derive(Clone)]
+pub struct Placement {
+    // The piece where it locks
+    pub tet: Tet,
+    // Shortest key sequence from spawn, ending with a hard drop, or with
+    // soft dropping to the floor where the rules have no hard drop
+    pub inputs: Vec<Input>,
+    pub t_spin: bool,
+}
+
+impl Placement {
+    // Keys that steer the piece, as finesse counts them: everything but
+    // dropping
+    pub fn presses(&self) -> usize {
+        self.inputs.iter()
+            .filter(|input| match input {
+                Input::Tap(action) | Input::Hold(action) => action.is_movement(),
+            })
+            .count()
+    }
+
+    // Board cells the piece covers, sorted so placements can be compared
+    pub fn cells(&self) -> Vec<Point2> {
+        sorted_cells(&self.tet)
+    }
+}
+
+fn sorted_cells(tet: &Tet) -> Vec<Point2> {
+    let mut cells = tet.cells();
+    cells.sort_by_key(|cell| (cell.y, cell.x));
+    cells
+}
+
+// A position the search got to: the piece, whether it got there by
+// rotating, and the position and input it came from
+struct Reached {
+    tet: Tet,
+    rotated: bool,
+    from: Option<(usize, Input)>,
+}
+
+// Search state: a piece position, and whether it got there by rotating (which
+// matters for T-spins)
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+struct State {
+    x: i8,
+    y: i8,
+    rot: Rot,
+    rotated: bool,
+}
+
+fn state(tet: &Tet, rotated: bool) -> State {
+    State { x: tet.pos.x, y: tet.pos.y, rot: tet.rot(), rotated }
+}
+
+// Like `Tets::at`, but treating the walls and floor as filled
+fn filled(tets: &Tets, row: i8, col: i8) -> bool {
+    row < 0 || tets.outside(row, col) || tets.at(row, col).is_some()
+}
+
+// 3-corner rule: a T that locks right after rotating with at least three of
+// the four corners around its center filled
+pub fn is_t_spin(tet: &Tet, rotated: bool, tets: &Tets) -> bool {
+    if tet.tet_type != TetType::T || !rotated {
+        return false;
+    }
+    let (x, y) = (tet.pos.x + 1, tet.pos.y + 1);
+    let corners = [(y - 1, x - 1), (y - 1, x + 1), (y + 1, x - 1), (y + 1, x + 1)];
+    corners.iter().filter(|&&(row, col)| filled(tets, row, col)).count() >= 3
+}
+
+// A quarter turn, or a half turn with no direction, kicking as the ruleset
+// says. Half turns the ruleset doesn't allow don't happen.
+pub fn rotate(tet: &mut Tet, dir: Option<RotationDir>, rules: &Ruleset, tets: &Tets) -> bool {
+    if dir.is_none() && !rules.allow.rotate_180 {
+        return false;
+    }
+    match (rules.rotation.kicks(), dir) {
+        (Some(kicks), _) => tet.rotate_with(dir, kicks, tets),
+        (None, Some(dir)) => tet.rotate(dir, tets),
+        (None, None) => tet.rotate_180(tets),
+    }
+}
+
+fn step(tet: &mut Tet, action: Action, rules: &Ruleset, tets: &Tets) -> bool {
+    match action {
+        Action::Left => tet.move_left(tets),
+        Action::Right => tet.move_right(tets),
+        Action::RotateCw => rotate(tet, Some(RotationDir::Clockwise), rules, tets),
+        Action::RotateCcw => rotate(tet, Some(RotationDir::CounterClockwise), rules, tets),
+        Action::Rotate180 => rotate(tet, None, rules, tets),
+        Action::SoftDrop => tet.fall(tets),
+        _ => false,
+    }
+}
+
+// Applies an input to the piece, returning whether it moved
+pub fn apply(tet: &mut Tet, input: Input, rules: &Ruleset, tets: &Tets) -> bool {
+    match input {
+        Input::Tap(action) => step(tet, action, rules, tets),
+        Input::Hold(action) => {
+            let mut moved = false;
+            while step(tet, action, rules, tets) {
+                moved = true;
+            }
+            moved
+        },
+    }
+}
+
+fn overlaps(tet: &Tet, tets: &Tets) -> bool {
+    tet.cells().iter().any(|cell| cell.y >= 0 && filled(tets, cell.y, cell.x))
+}
+
+// Every distinct placement of `tet_type` reachable from spawn on this board,
+// each with the fewest key presses that get it there. Rotations covering the
+// same cells (as S, Z and I can) count as one placement; a T-spin is kept
+// apart from a plain drop into the same cells.
+pub fn placements(tets: &Tets, tet_type: TetType, rules: &Ruleset) -> Vec<Placement> {
+    let spawn = Tet::spawn(tet_type, tets.width());
+    if overlaps(&spawn, tets) {
+        return Vec::new();
+    }
+
+    // Every position reached, in order. Paths are only pieced together for
+    // the placements found.
+    let mut reached = vec![Reached { tet: spawn.clone(), rotated: false, from: None }];
+    let mut seen = HashSet::new();
+    // Where the piece can lock, and the position it drops from
+    let mut landed = HashSet::new();
+    let mut found = Vec::new();
+    seen.insert(state(&spawn, false));
+
+    // Breadth first, so the first path to reach a placement is a shortest one
+    let mut i = 0;
+    while i < reached.len() {
+        let (tet, rotated) = (reached[i].tet.clone(), reached[i].rotated);
+        let mut dropped = tet.clone();
+        let fell = apply(&mut dropped, Input::Hold(Action::SoftDrop), rules, tets);
+        let t_spin = !fell && is_t_spin(&dropped, rotated, tets);
+        if landed.insert((state(&dropped, false), t_spin)) {
+            found.push((dropped, t_spin, i));
+        }
+
+        for &input in MOVES.iter() {
+            let mut next = tet.clone();
+            if !apply(&mut next, input, rules, tets) {
+                continue;
+            }
+            let next_rotated = tet_type == TetType::T && matches!(
+                input,
+                Input::Tap(Action::RotateCw) | Input::Tap(Action::RotateCcw) | Input::Tap(Action::Rotate180)
+            );
+            if seen.insert(state(&next, next_rotated)) {
+                reached.push(Reached { tet: next, rotated: next_rotated, from: Some((i, input)) });
+            }
+        }
+        i += 1;
+    }
+
+    // The same cells can be reached in different rotations; the first found
+    // is the shortest
+    let drop = if rules.allow.hard_drop {
+        Input::Tap(Action::HardDrop)
+    } else {
+        Input::Hold(Action::SoftDrop)
+    };
+    let mut cells_seen = HashSet::new();
+    let mut placements = Vec::new();
+    for (tet, t_spin, mut from) in found {
+        if !cells_seen.insert((sorted_cells(&tet), t_spin)) {
+            continue;
+        }
+        let mut inputs = vec![drop];
+        while let Some((previous, input)) = reached[from].from {
+            inputs.push(input);
+            from = previous;
+        }
+        inputs.reverse();
+        placements.push(Placement { tet, inputs, t_spin });
+    }
+    placements.sort_by_key(|placement| placement.inputs.len());
+    placements
+}
+
+// The placement of `tet_type` covering exactly `cells` (in any order), if it
+// can be reached
+pub fn find(tets: &Tets, tet_type: TetType, cells: &[Point2], rules: &Ruleset) -> Option<Placement> {
+    let mut target = cells.to_vec();
+    target.sort_by_key(|cell| (cell.y, cell.x));
+    placements(tets, tet_type, rules)
+        .into_iter()
+        .filter(|placement| placement.cells() == target)
+        // Prefer the spin if there is one
+        .max_by_key(|placement| placement.t_spin)
+}
+
+// The quickest way to cover `cells`, spin or not
+pub fn shortest(tets: &Tets, tet_type: TetType, cells: &[Point2], rules: &Ruleset) -> Option<Placement> {
+    let mut target = cells.to_vec();
+    target.sort_by_key(|cell| (cell.y, cell.x));
+    placements(tets, tet_type, rules)
+        .into_iter()
+        .filter(|placement| placement.cells() == target)
+        .min_by_key(|placement| placement.inputs.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::test_boards::board;
+    use crate::rules::Rotation;
+
+    fn cells(cells: &[(i8, i8)]) -> Vec<Point2> {
+        cells.iter().map(|&(row, col)| Point2::new(col, row)).collect()
+    }
+
+    // A T-spin triple slot in the second column, only reachable by kicking
+    // the T down two rows as it turns
+    const TST: [&str; 5] = [
+        "##........",
+        "..........",
+        "#.########",
+        "#..#######",
+        "#.########",
+    ];
+
+    fn tst_cells() -> Vec<Point2> {
+        cells(&[(17, 1), (18, 1), (18, 2), (19, 1)])
+    }
+
+    #[test]
+    fn symmetric_rotations_counted_once() {
+        let rules = Ruleset::default();
+        let counts = [(TetType::O, 9), (TetType::I, 17), (TetType::S, 17), (TetType::Z, 17), (TetType::T, 34)];
+        for &(tet_type, count) in counts.iter() {
+            let found = placements(&Tets::default(), tet_type, &rules);
+            assert_eq!(found.len(), count, "{:?}", tet_type);
+            let distinct: HashSet<_> = found.iter().map(Placement::cells).collect();
+            assert_eq!(distinct.len(), count, "{:?}", tet_type);
+        }
+    }
+
+    #[test]
+    fn soft_drop_tuck() {
+        let tets = board(&["####......", ".........."]);
+        let placement = find(&tets, TetType::I, &cells(&[(19, 0), (19, 1), (19, 2), (19, 3)]), &Ruleset::default())
+            .unwrap();
+        assert!(placement.inputs.contains(&Input::Hold(Action::SoftDrop)));
+        assert_eq!(placement.inputs.last(), Some(&Input::Tap(Action::HardDrop)));
+    }
+
+    #[test]
+    fn t_spin_through_kick() {
+        let tets = board(&TST);
+        let placement = find(&tets, TetType::T, &tst_cells(), &Ruleset::default()).unwrap();
+        assert!(placement.t_spin);
+        assert_eq!(tets.clone().lock(&placement.tet), 3);
+    }
+
+    #[test]
+    fn no_kicks() {
+        let rules = Ruleset { rotation: Rotation::NoKicks, ..Ruleset::default() };
+        assert!(find(&board(&TST), TetType::T, &tst_cells(), &rules).is_none());
+    }
+
+    #[test]
+    fn only_allowed_actions() {
+        let mut rules = Ruleset::default();
+        rules.allow.hard_drop = false;
+        rules.allow.rotate_180 = false;
+        for placement in placements(&Tets::default(), TetType::T, &rules) {
+            assert!(!placement.inputs.contains(&Input::Tap(Action::Rotate180)));
+            assert!(!placement.inputs.contains(&Input::Tap(Action::HardDrop)));
+            assert_eq!(placement.inputs.last(), Some(&Input::Hold(Action::SoftDrop)));
+        }
+    }
+}
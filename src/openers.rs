@@ -0,0 +1,342 @@
+// Opener drills: a fixed queue and the stack it should build, drawn as
+// outlines over the board. Every lock is checked against the stack and the
+// drill starts over on a mistake.
+
+use std::convert::TryFrom;
+use std::io::Read;
+
+use ggez::{Context, GameError, GameResult};
+use ggez::event::{Axis, Button, GamepadId, KeyCode, KeyMods};
+use ggez::filesystem;
+use ggez::graphics::{self, Color};
+
+use serde::Deserialize;
+
+use crate::game::{self, Block, Game, Tets};
+use crate::input::Control;
+use crate::menu;
+use crate::movegen;
+use crate::practice::Practice;
+use crate::rules::Ruleset;
+use crate::scene::{self, Menu, Scene, Transition};
+use crate::skin;
+use crate::tet::TetType;
+
+type Point2 = ggez::nalgebra::Point2<i8>;
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+// The bundled openers, in the resource directory
+pub const LIBRARY_PATH: &str = "/openers.toml";
+
+// Where one piece of the queue goes
+#[derive(Clone)]
+pub struct Target {
+    pub tet_type: TetType,
+    // Sorted top to bottom, then left to right
+    pub cells: Vec<Point2>,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "SavedOpener")]
+pub struct Opener {
+    pub name: String,
+    pub queue: Vec<TetType>,
+    // The board the drill starts from
+    pub start: Tets,
+    pub targets: Vec<Target>,
+}
+
+// An opener as written: the queue, and a picture of the bottom of the board
+// once every piece is placed. Each piece's blocks are marked with its place
+// in the queue (0-9, then a-z), '#' marks blocks already on the board and
+// '.' empty cells.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SavedOpener {
+    name: String,
+    queue: Vec<TetType>,
+    board: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Library {
+    opener: Vec<Opener>,
+}
+
+const MARKS: &str = "0123456789abcdefghijklmnopqrstuvwxyz";
+
+impl TryFrom<SavedOpener> for Opener {
+    type Error = String;
+
+    fn try_from(saved: SavedOpener) -> Result<Opener, String> {
+        let fail = |reason: String| Err(format!("opener {:?}: {}", saved.name, reason));
+        if saved.queue.is_empty() || saved.queue.len() > MARKS.len() {
+            return fail(format!("queue must have 1 to {} pieces", MARKS.len()));
+        }
+        let rows: Vec<&str> = saved.board.lines()
+            .map(str::trim)
+            .filter(|row| !row.is_empty())
+            .collect();
+        let mut start = Tets::default();
+        if rows.len() > start.height() {
+            return fail(format!("board is taller than {} rows", start.height()));
+        }
+        let mut cells = vec![Vec::new(); saved.queue.len()];
+        let top = start.height() - rows.len();
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != start.width() {
+                return fail(format!("board rows must be {} wide", start.width()));
+            }
+            for (x, mark) in row.chars().enumerate() {
+                let cell = Point2::new(x as i8, (top + y) as i8);
+                match (mark, MARKS.find(mark)) {
+                    ('.', _) => (),
+                    ('#', _) => start.set(cell.y, cell.x, Some(Block::Garbage)),
+                    (_, Some(i)) if i < saved.queue.len() => cells[i].push(cell),
+                    _ => return fail(format!("board has {:?}, which isn't in the queue", mark)),
+                }
+            }
+        }
+
+        // Each piece has to fit where it's drawn, in queue order
+        let mut tets = start.clone();
+        let mut targets = Vec::new();
+        for (i, (&tet_type, cells)) in saved.queue.iter().zip(cells).enumerate() {
+            let placement = match movegen::find(&tets, tet_type, &cells, &Ruleset::default()) {
+                Some(placement) => placement,
+                None => return fail(format!("piece {} ({:?}) can't be placed where it's drawn", i, tet_type)),
+            };
+            tets.lock(&placement.tet);
+            targets.push(Target { tet_type, cells: placement.cells() });
+        }
+        Ok(Opener { name: saved.name, queue: saved.queue, start, targets })
+    }
+}
+
+impl Opener {
+    pub fn library(ctx: &mut Context) -> GameResult<Vec<Opener>> {
+        let mut data = String::new();
+        filesystem::open(ctx, LIBRARY_PATH)?.read_to_string(&mut data)?;
+        Opener::parse_library(&data).map_err(|err| GameError::ConfigError(format!("{}: {}", LIBRARY_PATH, err)))
+    }
+
+    // The openers in a library file, of which there has to be at least one
+    fn parse_library(data: &str) -> Result<Vec<Opener>, String> {
+        let library: Library = toml::from_str(data).map_err(|err| err.to_string())?;
+        if library.opener.is_empty() {
+            return Err("no openers".to_string());
+        }
+        Ok(library.opener)
+    }
+}
+
+pub struct OpenerSelect {
+    menu: Menu,
+    openers: Vec<Opener>,
+    error: Option<String>,
+}
+
+impl OpenerSelect {
+    pub fn new(ctx: &mut Context) -> GameResult<OpenerSelect> {
+        let openers = Opener::library(ctx)?;
+        let names = openers.iter().map(|opener| opener.name.clone()).collect();
+        Ok(OpenerSelect { menu: Menu::with_items("Openers", names), openers, error: None })
+    }
+}
+
+impl Scene for OpenerSelect {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if let KeyCode::Escape = keycode {
+            return Transition::Pop;
+        }
+        match self.menu.key_down(keycode) {
+            Some(i) => menu::open(OpenerDrill::new(ctx, self.openers[i].clone()), &mut self.error),
+            None => Transition::None,
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)?;
+        scene::draw_errors(ctx, self.error.as_slice())
+    }
+}
+
+pub struct OpenerDrill {
+    practice: Practice,
+    opener: Opener,
+    // Targets not yet covered, by index
+    remaining: Vec<usize>,
+    // Value of `game.placed()` when last checked
+    placed: usize,
+    completed: usize,
+    misses: usize,
+    streak: usize,
+    best_streak: usize,
+}
+
+impl OpenerDrill {
+    pub fn new(ctx: &mut Context, opener: Opener) -> GameResult<OpenerDrill> {
+        let practice = Practice::new(ctx, Game::new()?, opener.start.clone(), None, opener.queue.clone())?;
+        Ok(OpenerDrill {
+            placed: practice.game().placed(),
+            practice,
+            remaining: (0..opener.targets.len()).collect(),
+            opener,
+            completed: 0,
+            misses: 0,
+            streak: 0,
+            best_streak: 0,
+        })
+    }
+
+    fn reset(&mut self) {
+        self.practice.restart();
+        self.placed = self.practice.game().placed();
+        self.remaining = (0..self.opener.targets.len()).collect();
+    }
+
+    // Pieces can go down in any order hold allows, as long as each covers a
+    // target of its own type
+    fn check(&mut self) {
+        self.placed = self.practice.game().placed();
+        let lock = match self.practice.game().last_lock() {
+            Some(lock) => lock,
+            None => return,
+        };
+        let mut cells = lock.tet.cells();
+        cells.sort_by_key(|cell| (cell.y, cell.x));
+        let targets = &self.opener.targets;
+        let hit = self.remaining.iter().position(|&i| {
+            targets[i].tet_type == lock.tet.tet_type && targets[i].cells == cells
+        });
+        match hit {
+            Some(position) => {
+                self.remaining.remove(position);
+                if self.remaining.is_empty() {
+                    self.completed += 1;
+                    self.streak += 1;
+                    self.best_streak = self.best_streak.max(self.streak);
+                    self.reset();
+                }
+            },
+            None => self.miss(),
+        }
+    }
+
+    fn miss(&mut self) {
+        self.misses += 1;
+        self.streak = 0;
+        self.reset();
+    }
+}
+
+impl Scene for OpenerDrill {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<Transition> {
+        if self.practice.game().placed() != self.placed {
+            self.check();
+        }
+        if self.practice.game().is_over() {
+            self.miss();
+        }
+        self.practice.update(ctx)?;
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, repeat: bool) -> Transition {
+        if let KeyCode::Escape = keycode {
+            return Transition::Pop;
+        }
+        if let (Some(Control::Restart), false) = (self.practice.key_down(ctx, keycode, repeat), repeat) {
+            self.reset();
+        }
+        Transition::None
+    }
+
+    fn key_up(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        self.practice.key_up(keycode);
+    }
+
+    fn button_down(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) -> Transition {
+        if let Button::Mode = button {
+            return Transition::Pop;
+        }
+        if let Some(Control::Restart) = self.practice.button_down(button) {
+            self.reset();
+        }
+        Transition::None
+    }
+
+    fn button_up(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) {
+        self.practice.button_up(button);
+    }
+
+    fn axis(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        self.practice.axis(axis, value);
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.practice.draw(ctx)?;
+        let game = self.practice.game();
+        // Targets the piece in play (or held) could cover stand out
+        let playable = [
+            game.current_tet().map(|tet| tet.tet_type),
+            game.held_tet(),
+        ];
+        for &i in &self.remaining {
+            let target = &self.opener.targets[i];
+            let color = if playable.contains(&Some(target.tet_type)) {
+                Color::from_rgb(80, 220, 120)
+            } else {
+                Color::from_rgb(110, 110, 110)
+            };
+            game::draw_outline(ctx, &target.cells, color)?;
+        }
+
+        let lines = [
+            self.opener.name.clone(),
+            format!("Done: {}", self.completed),
+            format!("Misses: {}", self.misses),
+            format!("Streak: {}", self.streak),
+            format!("Best: {}", self.best_streak),
+        ];
+        let x = game.sidebar_x() + 10.0;
+        for (i, line) in lines.iter().enumerate() {
+            graphics::draw(
+                ctx,
+                &skin::text(line.as_str(), graphics::DEFAULT_FONT_SCALE),
+                (Point2f32::new(x, 150.0 + 25.0 * i as f32), skin::text_color()),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn window_size(&self) -> (f32, f32) {
+        self.practice.window_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_library_loads() {
+        let openers = Opener::parse_library(include_str!("../res/openers.toml")).unwrap();
+        for name in ["TKI", "DT Cannon", "PCO", "4-wide"].iter() {
+            assert!(openers.iter().any(|opener| opener.name == *name), "{}", name);
+        }
+        for opener in &openers {
+            assert_eq!(opener.targets.len(), opener.queue.len(), "{}", opener.name);
+        }
+    }
+
+    #[test]
+    fn empty_library() {
+        assert!(Opener::parse_library("opener = []").is_err());
+    }
+}
@@ -0,0 +1,347 @@
+// Perfect clear finder: searches the piece in play, hold and the queue for a
+// way to clear every block off the board, using only moves a player could
+// make (see movegen). Practice shows what it finds over the board; bots and
+// tools can call `solve` directly.
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+use crate::ai::Situation;
+use crate::game::Tets;
+use crate::movegen::{self, Placement};
+use crate::rules::Ruleset;
+use crate::tet::{Rot, TetType};
+
+type Point2 = ggez::nalgebra::Point2<i8>;
+
+// Tallest stack the search tries to clear
+pub const MAX_HEIGHT: usize = 6;
+// How long to look before giving up. Most perfect clears that are there turn
+// up well within it.
+pub const TIME_LIMIT: Duration = Duration::from_secs(1);
+
+// One piece of a solution
+#[derive(Clone)]
+pub struct Step {
+    // Swap with the hold piece before placing
+    pub hold: bool,
+    pub placement: Placement,
+}
+
+impl Step {
+    pub fn cells(&self) -> Vec<Point2> {
+        self.placement.cells()
+    }
+}
+
+// The rows being cleared, a bit per filled cell
+type Rows = Vec<u128>;
+
+// A position in the search: the rows still to clear and the pieces to come
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Key {
+    rows: Rows,
+    current: TetType,
+    held: Option<TetType>,
+    // Index into the queue of the next piece to spawn
+    next: usize,
+    can_hold: bool,
+}
+
+// A way a piece can lie, as offsets (rows down, columns across) from its
+// first cell, reading the board like text
+type Shape = Vec<(usize, i32)>;
+
+struct Search<'a> {
+    width: usize,
+    queue: &'a [TetType],
+    rules: &'a Ruleset,
+    deadline: Instant,
+    shapes: HashMap<TetType, Vec<Shape>>,
+    // Positions already searched without finding anything
+    dead: HashSet<Key>,
+    // Whether rows can be filled from a set of pieces, ignoring how
+    tilings: HashMap<(Rows, Vec<(TetType, usize)>), bool>,
+}
+
+fn rows_of(tets: &Tets, rows: usize) -> Rows {
+    tets.iter()
+        .skip(tets.height() - rows)
+        .map(|row| row.iter().enumerate()
+            .filter(|(_, block)| block.is_some())
+            .fold(0, |bits, (col, _)| bits | 1 << col))
+        .collect()
+}
+
+// Rows up from the floor to the top of the stack
+fn stack_height(tets: &Tets) -> usize {
+    tets.iter()
+        .position(|row| row.iter().any(|block| block.is_some()))
+        .map_or(0, |top| tets.height() - top)
+}
+
+fn shapes(tet_type: TetType) -> Vec<Shape> {
+    let mut shapes = Vec::new();
+    for &rot in [Rot::Zero, Rot::R, Rot::Two, Rot::L].iter() {
+        let mut blocks: Vec<(i8, i8)> = tet_type.blocks(rot).iter().map(|block| (block.y, block.x)).collect();
+        blocks.sort_unstable();
+        let (top, left) = blocks[0];
+        let shape: Shape = blocks.iter().map(|&(y, x)| ((y - top) as usize, (x - left) as i32)).collect();
+        if !shapes.contains(&shape) {
+            shapes.push(shape);
+        }
+    }
+    shapes
+}
+
+impl<'a> Search<'a> {
+    fn full(&self) -> u128 {
+        u128::MAX >> (128 - self.width)
+    }
+
+    // Whether the gaps in `rows` can be filled exactly with these pieces, in
+    // any order and ignoring how they'd get there. Rows stay where they are,
+    // so a solution that clears a line and then drops a piece through where
+    // it was is missed; perfect clears rarely need one.
+    fn tileable(&mut self, rows: &mut Rows, counts: &mut Vec<(TetType, usize)>) -> bool {
+        let full = self.full();
+        let row = match rows.iter().position(|&bits| bits != full) {
+            Some(row) => row,
+            None => return true,
+        };
+        let col = (!rows[row]).trailing_zeros() as i32;
+        for i in 0..counts.len() {
+            let (tet_type, count) = counts[i];
+            if count == 0 {
+                continue;
+            }
+            for shape in self.shapes[&tet_type].clone() {
+                let fits = shape.iter().all(|&(dy, dx)| {
+                    let x = col + dx;
+                    row + dy < rows.len() && x >= 0 && (x as usize) < self.width
+                        && rows[row + dy] & 1 << x == 0
+                });
+                if !fits {
+                    continue;
+                }
+                for &(dy, dx) in shape.iter() {
+                    rows[row + dy] |= 1 << (col + dx);
+                }
+                counts[i].1 -= 1;
+                let tiled = self.tileable(rows, counts);
+                counts[i].1 += 1;
+                for &(dy, dx) in shape.iter() {
+                    rows[row + dy] &= !(1 << (col + dx));
+                }
+                if tiled {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // Holding lets any one of the next pieces but one be put off, so the
+    // rows have to be filled from the first `needed + 1` pieces
+    fn can_fill(&mut self, key: &Key) -> bool {
+        let empty: usize = key.rows.iter().map(|bits| self.width - bits.count_ones() as usize).sum();
+        if !empty.is_multiple_of(4) {
+            return false;
+        }
+        let needed = empty / 4;
+        let mut pieces: Vec<TetType> = key.held.iter().cloned()
+            .chain(Some(key.current))
+            .chain(self.queue.iter().skip(key.next).cloned())
+            .take(needed + 1)
+            .collect();
+        if pieces.len() < needed {
+            return false;
+        }
+        pieces.sort_unstable();
+        let mut counts: Vec<(TetType, usize)> = Vec::new();
+        for tet_type in pieces {
+            match counts.last_mut() {
+                Some((last, count)) if *last == tet_type => *count += 1,
+                _ => counts.push((tet_type, 1)),
+            }
+        }
+
+        let memo = (key.rows.clone(), counts.clone());
+        if let Some(&tileable) = self.tilings.get(&memo) {
+            return tileable;
+        }
+        for &(tet_type, _) in counts.iter() {
+            self.shapes.entry(tet_type).or_insert_with(|| shapes(tet_type));
+        }
+        let tileable = self.tileable(&mut key.rows.clone(), &mut counts);
+        self.tilings.insert(memo, tileable);
+        tileable
+    }
+
+    // Placements that stay inside the rows being cleared, one for each set
+    // of cells, lowest first
+    fn placements(&self, tets: &Tets, rows: usize, tet_type: TetType) -> Vec<Placement> {
+        let top = (tets.height() - rows) as i8;
+        let mut placements: Vec<(Vec<Point2>, Placement)> = movegen::placements(tets, tet_type, self.rules)
+            .into_iter()
+            .filter(|placement| placement.tet.cells().iter().all(|cell| cell.y >= top))
+            .map(|placement| (placement.cells(), placement))
+            .collect();
+        // Shortest first, so the one kept for each set of cells is the
+        // quickest to play
+        placements.sort_by_key(|(cells, placement)| {
+            let depth: i32 = cells.iter().map(|cell| cell.y as i32).sum();
+            let order: Vec<(i8, i8)> = cells.iter().map(|cell| (cell.y, cell.x)).collect();
+            (-depth, order, placement.inputs.len())
+        });
+        placements.dedup_by(|a, b| a.0 == b.0);
+        placements.into_iter().map(|(_, placement)| placement).collect()
+    }
+
+    fn search(&mut self, tets: &Tets, key: Key, steps: &mut Vec<Step>) -> bool {
+        if key.rows.is_empty() {
+            return true;
+        }
+        if Instant::now() > self.deadline || self.dead.contains(&key) || !self.can_fill(&key) {
+            return false;
+        }
+
+        // (use hold, piece to place, piece in play after it, held piece
+        // after it, next piece from the queue after it)
+        let (queue, next, current) = (self.queue, key.next, key.current);
+        let mut options = vec![(false, current, queue.get(next).cloned(), key.held, next + 1)];
+        if key.can_hold {
+            match key.held {
+                Some(held) if held != current => {
+                    options.push((true, held, queue.get(next).cloned(), Some(current), next + 1));
+                },
+                None => if let Some(&first) = queue.get(next) {
+                    options.push((true, first, queue.get(next + 1).cloned(), Some(current), next + 2));
+                },
+                _ => (),
+            }
+        }
+
+        for (hold, tet_type, current, held, next) in options {
+            for placement in self.placements(tets, key.rows.len(), tet_type) {
+                let mut after = tets.clone();
+                let cleared = after.lock(&placement.tet);
+                let rows = rows_of(&after, key.rows.len() - cleared);
+                let found = match current {
+                    Some(current) => {
+                        steps.push(Step { hold, placement });
+                        let can_hold = self.rules.allow.hold;
+                        let next_key = Key { rows, current, held, next, can_hold };
+                        self.search(&after, next_key, steps) || {
+                            steps.pop();
+                            false
+                        }
+                    },
+                    // Out of pieces
+                    None if rows.is_empty() => {
+                        steps.push(Step { hold, placement });
+                        true
+                    },
+                    None => false,
+                };
+                if found {
+                    return true;
+                }
+                if Instant::now() > self.deadline {
+                    return false;
+                }
+            }
+        }
+        self.dead.insert(key);
+        false
+    }
+}
+
+// The pieces to place, in order, for a perfect clear of at most `max_height`
+// rows, trying the fewest rows first. None if there isn't one, or it wasn't
+// found in time. Only pieces of four blocks are considered.
+pub fn solve(situation: &Situation, max_height: usize) -> Option<Vec<Step>> {
+    let tets = situation.tets;
+    let four_blocks = Some(situation.current).iter()
+        .chain(situation.held.iter())
+        .chain(situation.queue.iter())
+        .all(|tet_type| tet_type.blocks(Rot::Zero).len() == 4);
+    if !four_blocks || tets.width() > 128 {
+        return None;
+    }
+
+    let mut search = Search {
+        width: tets.width(),
+        queue: situation.queue,
+        rules: situation.rules,
+        deadline: Instant::now() + TIME_LIMIT,
+        shapes: HashMap::new(),
+        dead: HashSet::new(),
+        tilings: HashMap::new(),
+    };
+    let lowest = stack_height(tets).max(1);
+    for rows in lowest..=max_height.min(tets.height()) {
+        let key = Key {
+            rows: rows_of(tets, rows),
+            current: situation.current,
+            held: situation.held,
+            next: 0,
+            can_hold: situation.can_hold,
+        };
+        let mut steps = Vec::new();
+        if search.search(tets, key, &mut steps) {
+            return Some(steps);
+        }
+        if Instant::now() > search.deadline {
+            break;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::test_boards::{board, rows};
+
+    fn solve_with(tets: &Tets, current: TetType, queue: &[TetType]) -> Option<Vec<Step>> {
+        let rules = Ruleset::default();
+        let situation = Situation { tets, current, held: None, queue, can_hold: true, rules: &rules };
+        solve(&situation, MAX_HEIGHT)
+    }
+
+    #[test]
+    fn four_line_perfect_clear() {
+        let tets = board(&["######....", "######....", "######....", "######...."]);
+        let steps = solve_with(&tets, TetType::J, &[TetType::L, TetType::J, TetType::L, TetType::T]).unwrap();
+        assert_eq!(steps.len(), 4);
+        let mut after = tets.clone();
+        let cleared: usize = steps.iter().map(|step| after.lock(&step.placement.tet)).sum();
+        assert_eq!(cleared, 4);
+        assert_eq!(stack_height(&after), 0);
+    }
+
+    // The opening perfect clear, from a full bag and the start of the next
+    #[test]
+    fn empty_board_perfect_clear() {
+        let tets = Tets::default();
+        let queue = [
+            TetType::O, TetType::T, TetType::S, TetType::Z, TetType::J, TetType::L,
+            TetType::I, TetType::T, TetType::O, TetType::L,
+        ];
+        let steps = solve_with(&tets, TetType::I, &queue);
+        assert!(steps.is_some());
+        let mut after = tets.clone();
+        for step in steps.unwrap() {
+            after.lock(&step.placement.tet);
+        }
+        assert_eq!(rows(&after), rows(&Tets::default()));
+    }
+
+    #[test]
+    fn covered_hole_has_no_solution() {
+        let tets = board(&["#########.", "#.########"]);
+        let queue = [TetType::I, TetType::O, TetType::T, TetType::S, TetType::Z, TetType::J, TetType::L];
+        assert!(solve_with(&tets, TetType::I, &queue).is_none());
+    }
+}
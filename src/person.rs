@@ -0,0 +1,315 @@
+// Someone playing a game at the keyboard or a gamepad: their bindings and
+// handling, the skin they see, and what's shown over the board just for them
+// (hints, finesse mistakes, notices). Restarting, undo and the like depend on
+// the mode, so they're handed back to the scene.
+
+use std::time::Duration;
+
+use ggez::{Context, GameResult};
+use ggez::event::{Axis, Button, KeyCode};
+use ggez::graphics::{self, Color};
+use ggez::timer;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ai::{self, Situation, Weights};
+use crate::fumen;
+use crate::game::{self, Game, TILE_SIZE};
+use crate::input::{Action, ButtonMap, Control, KeyMap};
+use crate::settings::Settings;
+use crate::skin::Skin;
+use crate::tet::TetType;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+type Point2 = ggez::nalgebra::Point2<i8>;
+
+// Suggested placements drawn over the board
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Hints {
+    Off,
+    Current,
+    // Also show the best placement using the hold piece
+    WithHold,
+}
+
+impl Hints {
+    pub fn next(self) -> Hints {
+        match self {
+            Hints::Off => Hints::Current,
+            Hints::Current => Hints::WithHold,
+            Hints::WithHold => Hints::Off,
+        }
+    }
+}
+
+// Hints for one piece, so the search only runs when the piece changes
+struct HintCache {
+    placed: usize,
+    current: TetType,
+    held: Option<TetType>,
+    best: Option<Vec<Point2>>,
+    best_with_hold: Option<Vec<Point2>>,
+}
+
+pub struct Person {
+    keys: KeyMap,
+    buttons: ButtonMap,
+    // Auto-shift delay and repeat interval for shifts started by a key, and
+    // by the stick
+    key_handling: (Duration, Duration),
+    stick_handling: (Duration, Duration),
+    // How far the stick has to be pushed
+    deadzone: f32,
+    // What each stick axis (horizontal, vertical) is holding down
+    stick: [Option<Action>; 2],
+    skin: Skin,
+    hints: Hints,
+    // Hints can't be turned on, e.g. racing an opponent
+    hints_forbidden: bool,
+    hint_cache: Option<HintCache>,
+    // Value of `game.placed()` when last checked
+    placed: usize,
+    // Where the last inefficiently placed piece went, and how much longer to
+    // highlight it
+    finesse_marker: Option<(Vec<Point2>, Duration)>,
+    // Said for a moment, e.g. after exporting fumen
+    notice: Option<(String, Duration)>,
+}
+
+impl Person {
+    pub const STICK_WAIT: Duration = Duration::from_millis(200);
+    pub const STICK_INTERVAL: Duration = Duration::from_millis(70);
+    pub const DEADZONE: f32 = 0.5;
+    const FINESSE_MARKER_TIME: Duration = Duration::from_millis(500);
+    const NOTICE_TIME: Duration = Duration::from_secs(2);
+
+    // Someone playing `game` with their settings, which also set up the
+    // game's sounds and HUD
+    pub fn new(ctx: &mut Context, settings: &Settings, game: &mut Game) -> GameResult<Person> {
+        settings.apply(game);
+        Ok(Person {
+            keys: settings.keys.clone(),
+            buttons: settings.buttons.clone(),
+            key_handling: (Duration::from_millis(settings.das), Duration::from_millis(settings.arr)),
+            stick_handling: (Duration::from_millis(settings.stick_das), Duration::from_millis(settings.stick_arr)),
+            deadzone: settings.deadzone as f32 / 100.0,
+            stick: [None; 2],
+            skin: Skin::load(ctx, &settings.skin, game.pieces())?,
+            hints: settings.hints,
+            hints_forbidden: false,
+            hint_cache: None,
+            placed: game.placed(),
+            finesse_marker: None,
+            notice: None,
+        })
+    }
+
+    pub fn keys(&self) -> &KeyMap {
+        &self.keys
+    }
+
+    pub fn buttons(&self) -> &ButtonMap {
+        &self.buttons
+    }
+
+    pub fn skin(&self) -> &Skin {
+        &self.skin
+    }
+
+    pub fn set_hints(&mut self, hints: Hints) {
+        if !self.hints_forbidden {
+            self.hints = hints;
+        }
+    }
+
+    pub fn forbid_hints(&mut self) {
+        self.hints = Hints::Off;
+        self.hints_forbidden = true;
+    }
+
+    // Shows a message under the board for a moment
+    pub fn notify(&mut self, notice: String) {
+        self.notice = Some((notice, Self::NOTICE_TIME));
+    }
+
+    // Pauses or unpauses, letting go of whatever the stick held
+    pub fn toggle_pause(&mut self, game: &mut Game) {
+        game.toggle_pause();
+        self.stick = [None; 2];
+    }
+
+    // Call after the game updates
+    pub fn update(&mut self, ctx: &mut Context, game: &mut Game) {
+        let delta = timer::delta(ctx);
+        self.notice = self.notice.take()
+            .and_then(|(text, time)| time.checked_sub(delta).map(|left| (text, left)));
+        if game.placed() != self.placed {
+            self.placed = game.placed();
+            self.finesse_marker = game.last_lock()
+                .filter(|lock| lock.finesse_fault())
+                .map(|lock| (lock.tet.cells(), Self::FINESSE_MARKER_TIME));
+        }
+        if !game.is_over() && !game.is_paused() {
+            self.finesse_marker = self.finesse_marker.take()
+                .and_then(|(cells, time)| time.checked_sub(delta).map(|left| (cells, left)));
+        }
+        if self.hints != Hints::Off {
+            game.assist();
+            self.update_hints(game);
+        }
+    }
+
+    // Runs the search for the piece in play, unless it's already been run
+    // for it. Drawing only reads what this found.
+    fn update_hints(&mut self, game: &Game) {
+        let situation = match Situation::of(game) {
+            Some(situation) => situation,
+            None => return,
+        };
+        let fresh = self.hint_cache.as_ref().is_some_and(|cache| {
+            cache.placed == game.placed()
+                && cache.current == situation.current
+                && cache.held == situation.held
+        });
+        if fresh {
+            return;
+        }
+        let plans = ai::plans(&situation, &Weights::default(), false);
+        let best = |hold: bool| plans.iter()
+            .find(|plan| plan.hold == hold)
+            .map(|plan| plan.placement.cells());
+        self.hint_cache = Some(HintCache {
+            placed: game.placed(),
+            current: situation.current,
+            held: situation.held,
+            best: best(false),
+            best_with_hold: best(true),
+        });
+    }
+
+    // Plays the key's action, if it has one. Controls left to the scene come
+    // back.
+    pub fn key_down(&mut self, ctx: &mut Context, game: &mut Game, keycode: KeyCode, repeat: bool) -> Option<Control> {
+        let export = match keycode {
+            KeyCode::F5 => Some(fumen::snapshot(game.tets(), game.current_tet())),
+            KeyCode::F6 => {
+                let (start, history) = game.replay();
+                Some(fumen::replay(start, history))
+            },
+            _ => None,
+        };
+        if let Some(data) = export {
+            let notice = match data.and_then(|data| game::export_fumen(ctx, &data)) {
+                Ok(()) => "Exported to fumen.txt".to_string(),
+                Err(err) => format!("Couldn't save fumen: {}", err),
+            };
+            self.notify(notice);
+            return None;
+        }
+        let handling = self.key_handling;
+        self.control_down(game, self.keys.control(keycode), repeat, handling)
+    }
+
+    pub fn key_up(&mut self, game: &mut Game, keycode: KeyCode) {
+        if let Some(action) = self.keys.action(keycode) {
+            game.release(action);
+        }
+    }
+
+    pub fn button_down(&mut self, game: &mut Game, button: Button) -> Option<Control> {
+        let handling = self.key_handling;
+        self.control_down(game, self.buttons.control(button), false, handling)
+    }
+
+    pub fn button_up(&mut self, game: &mut Game, button: Button) {
+        if let Some(action) = self.buttons.action(button) {
+            game.release(action);
+        }
+    }
+
+    // The left stick shifts and soft drops once pushed past the deadzone
+    pub fn axis(&mut self, game: &mut Game, axis: Axis, value: f32) {
+        let (index, held) = match axis {
+            Axis::LeftStickX if value <= -self.deadzone => (0, Some(Action::Left)),
+            Axis::LeftStickX if value >= self.deadzone => (0, Some(Action::Right)),
+            Axis::LeftStickX => (0, None),
+            // Up is positive
+            Axis::LeftStickY if value <= -self.deadzone => (1, Some(Action::SoftDrop)),
+            Axis::LeftStickY => (1, None),
+            _ => return,
+        };
+        if self.stick[index] == held {
+            return;
+        }
+        if let Some(action) = self.stick[index] {
+            game.release(action);
+        }
+        self.stick[index] = held;
+        if let Some(action) = held {
+            let (wait, interval) = self.stick_handling;
+            game.set_handling(wait, interval);
+            game.press(action);
+        }
+    }
+
+    fn control_down(
+        &mut self,
+        game: &mut Game,
+        control: Option<Control>,
+        repeat: bool,
+        (wait, interval): (Duration, Duration),
+    ) -> Option<Control> {
+        let control = control?;
+        if game.is_over() {
+            return Some(control);
+        }
+        match control {
+            Control::Pause if !repeat => self.toggle_pause(game),
+            Control::Hints if !repeat && !game.is_paused() => self.set_hints(self.hints.next()),
+            Control::Pause | Control::Hints => (),
+            _ => match control.action() {
+                Some(action) => {
+                    if !(repeat && action.is_held()) {
+                        game.set_handling(wait, interval);
+                        game.press(action);
+                    }
+                },
+                None => return Some(control),
+            },
+        }
+        None
+    }
+
+    // The game, and what's shown over it for this person
+    pub fn draw(&self, ctx: &mut Context, game: &Game) -> GameResult {
+        game.draw_game(ctx, &self.skin)?;
+
+        let playing = !game.is_over() && !game.is_paused();
+        let cache = self.hint_cache.as_ref().filter(|cache| cache.placed == game.placed());
+        if let (true, Hints::Current | Hints::WithHold, Some(cache)) = (playing, self.hints, cache) {
+            if let (Hints::WithHold, Some(cells)) = (self.hints, &cache.best_with_hold) {
+                game::draw_outline(ctx, cells, Color::from_rgb(90, 170, 230))?;
+            }
+            if let Some(cells) = &cache.best {
+                game::draw_outline(ctx, cells, Color::from_rgb(240, 220, 80))?;
+            }
+        }
+        if let (true, Some((cells, _))) = (playing, &self.finesse_marker) {
+            game::draw_outline(ctx, cells, Color::from_rgb(230, 40, 40))?;
+        }
+
+        if game.is_paused() {
+            game.draw_prompt(ctx, &self.skin, &format!("Press {} to Resume", self.keys.prompt(Control::Pause)))?;
+        } else if game.is_over() {
+            game.draw_prompt(ctx, &self.skin, &format!("Press {} to Restart", self.keys.prompt(Control::Restart)))?;
+        }
+
+        if let Some((notice, _)) = &self.notice {
+            let text = self.skin.text(notice.as_str(), graphics::DEFAULT_FONT_SCALE);
+            let offset = text.width(ctx) as f32 / 2.0;
+            let y = game.tets().height() as f32 * TILE_SIZE - 25.0;
+            graphics::draw(ctx, &text, (Point2f32::new(game.board_center() - offset, y), self.skin.text_color()))?;
+        }
+        Ok(())
+    }
+}
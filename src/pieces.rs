@@ -0,0 +1,130 @@
+// The pieces a game deals from: the tetrominoes, the other built-in sets, or
+// a set of the player's own read from pieces.json.
+
+use std::io::Read;
+
+use ggez::{Context, GameError, GameResult};
+use ggez::filesystem;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+use crate::tet::{Kicks, Shape, TetType};
+
+// Where a custom set is read from, in the user data directory
+pub const CUSTOM_PATH: &str = "/pieces.json";
+
+// One of each piece is dealt, in a random order, before any repeats
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PieceSet {
+    pieces: Vec<TetType>,
+}
+
+impl Default for PieceSet {
+    fn default() -> PieceSet {
+        PieceSet::standard()
+    }
+}
+
+// Adds a built-in set's shapes, which are known to be valid and uniquely
+// named
+fn built_in(shapes: Vec<Shape>) -> PieceSet {
+    PieceSet {
+        pieces: shapes.into_iter()
+            .map(|shape| TetType::register(shape).expect("built-in pieces are valid"))
+            .collect(),
+    }
+}
+
+// A built-in piece turning about the middle of its box (left of and above
+// it, for even sizes), kicking like I if the box is as big as I's
+fn shape(name: &str, cells: &[[i8; 2]], size: i8, color: [u8; 3]) -> Shape {
+    let middle = (size - 1) / 2;
+    Shape {
+        name: name.to_string(),
+        cells: cells.to_vec(),
+        size,
+        center: [middle, middle],
+        kicks: if size > 3 { Kicks::SrsI } else { Kicks::Srs },
+        color,
+        sprite: None,
+    }
+}
+
+impl PieceSet {
+    pub fn standard() -> PieceSet {
+        PieceSet { pieces: TetType::STANDARD.to_vec() }
+    }
+
+    // The eighteen one-sided pentominoes, mirror images named with a '
+    pub fn pentominoes() -> PieceSet {
+        built_in(vec![
+            shape("F5", &[[1, 0], [2, 0], [0, 1], [1, 1], [1, 2]], 3, [200, 120, 60]),
+            shape("F5'", &[[0, 0], [1, 0], [1, 1], [2, 1], [1, 2]], 3, [170, 100, 50]),
+            shape("I5", &[[0, 2], [1, 2], [2, 2], [3, 2], [4, 2]], 5, [0, 240, 240]),
+            shape("L5", &[[3, 0], [0, 1], [1, 1], [2, 1], [3, 1]], 4, [240, 160, 0]),
+            shape("L5'", &[[0, 0], [0, 1], [1, 1], [2, 1], [3, 1]], 4, [0, 0, 240]),
+            shape("N5", &[[2, 0], [3, 0], [0, 1], [1, 1], [2, 1]], 4, [240, 90, 90]),
+            shape("N5'", &[[0, 0], [1, 0], [1, 1], [2, 1], [3, 1]], 4, [90, 240, 90]),
+            shape("P5", &[[0, 0], [1, 0], [0, 1], [1, 1], [0, 2]], 3, [240, 240, 0]),
+            shape("P5'", &[[0, 0], [1, 0], [0, 1], [1, 1], [1, 2]], 3, [200, 200, 60]),
+            shape("T5", &[[0, 0], [1, 0], [2, 0], [1, 1], [1, 2]], 3, [160, 0, 240]),
+            shape("U5", &[[0, 0], [2, 0], [0, 1], [1, 1], [2, 1]], 3, [240, 100, 200]),
+            shape("V5", &[[0, 0], [0, 1], [0, 2], [1, 2], [2, 2]], 3, [100, 160, 240]),
+            shape("W5", &[[0, 0], [0, 1], [1, 1], [1, 2], [2, 2]], 3, [120, 240, 180]),
+            shape("X5", &[[1, 0], [0, 1], [1, 1], [2, 1], [1, 2]], 3, [240, 240, 240]),
+            shape("Y5", &[[2, 0], [0, 1], [1, 1], [2, 1], [3, 1]], 4, [180, 120, 240]),
+            shape("Y5'", &[[1, 0], [0, 1], [1, 1], [2, 1], [3, 1]], 4, [140, 90, 200]),
+            shape("Z5", &[[0, 0], [1, 0], [1, 1], [1, 2], [2, 2]], 3, [240, 0, 0]),
+            shape("Z5'", &[[1, 0], [2, 0], [1, 1], [0, 2], [1, 2]], 3, [0, 240, 0]),
+        ])
+    }
+
+    pub fn trominoes() -> PieceSet {
+        built_in(vec![
+            shape("I3", &[[0, 1], [1, 1], [2, 1]], 3, [0, 240, 240]),
+            shape("L3", &[[0, 0], [0, 1], [1, 1]], 2, [240, 160, 0]),
+        ])
+    }
+
+    // A set from a JSON list of shapes, as in `Shape`
+    pub fn load(ctx: &mut Context, path: &str) -> GameResult<PieceSet> {
+        let mut data = String::new();
+        filesystem::open(ctx, path)?.read_to_string(&mut data)?;
+        let invalid = |reason: String| GameError::ConfigError(format!("{}: {}", path, reason));
+        let shapes: Vec<Shape> = serde_json::from_str(&data)
+            .map_err(|err| invalid(err.to_string()))?;
+        if shapes.is_empty() {
+            return Err(invalid("no pieces".to_string()));
+        }
+        let mut pieces = Vec::new();
+        for shape in shapes {
+            let tet_type = TetType::register(shape).map_err(invalid)?;
+            if pieces.contains(&tet_type) {
+                return Err(invalid(format!("piece {:?} is listed twice", tet_type.name())));
+            }
+            pieces.push(tet_type);
+        }
+        Ok(PieceSet { pieces })
+    }
+
+    pub fn pieces(&self) -> &[TetType] {
+        &self.pieces
+    }
+
+    // Side of the biggest box any piece turns within
+    pub fn box_size(&self) -> usize {
+        self.pieces.iter().map(|tet_type| tet_type.shape().size as usize).max().unwrap_or(0)
+    }
+
+    // Only the tetrominoes, which is all fumen and TBP bots know
+    pub fn is_standard(&self) -> bool {
+        self.pieces.iter().all(|tet_type| tet_type.is_standard())
+    }
+
+    pub fn batch<R: Rng + ?Sized>(&self, rng: &mut R) -> Vec<TetType> {
+        let mut batch = self.pieces.clone();
+        batch.shuffle(rng);
+        batch
+    }
+}
@@ -0,0 +1,148 @@
+// Games played by something other than the keyboard: external bots, the
+// built-in AI, ...
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use ggez::{Context, GameResult};
+use ggez::event::{KeyCode, KeyMods};
+use ggez::timer;
+
+use crate::game::Game;
+use crate::input::Action;
+use crate::movegen::{self, Input};
+use crate::scene::{Scene, Transition};
+use crate::settings::Settings;
+use crate::skin::Skin;
+
+pub trait Controller {
+    // Called every frame, before the game itself updates
+    fn control(&mut self, ctx: &mut Context, game: &mut Game) -> GameResult;
+}
+
+pub struct AutoPlayer {
+    game: Game,
+    controller: Box<dyn Controller>,
+    // Whoever's watching sees their own skin
+    skin: Skin,
+}
+
+impl AutoPlayer {
+    pub fn new(ctx: &mut Context, game: Game, controller: Box<dyn Controller>) -> GameResult<AutoPlayer> {
+        let name = Settings::load(ctx).skin;
+        let skin = Skin::load(ctx, &name, game.pieces())?;
+        Ok(AutoPlayer { game, controller, skin })
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn game_mut(&mut self) -> &mut Game {
+        &mut self.game
+    }
+
+    pub fn update(&mut self, ctx: &mut Context) -> GameResult {
+        self.controller.control(ctx, &mut self.game)?;
+        self.game.update(ctx)
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        self.game.draw_game(ctx, &self.skin)?;
+        if self.game.is_dead() {
+            self.game.draw_prompt(ctx, &self.skin, "Press R to Restart")?;
+        }
+        Ok(())
+    }
+}
+
+impl Scene for AutoPlayer {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<Transition> {
+        AutoPlayer::update(self, ctx)?;
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        match keycode {
+            KeyCode::Escape => return Transition::Pop,
+            KeyCode::R if self.game.is_dead() => self.game.restart(),
+            _ => (),
+        }
+        Transition::None
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        AutoPlayer::draw(self, ctx)
+    }
+
+    fn window_size(&self) -> (f32, f32) {
+        self.game.window_size()
+    }
+}
+
+// Feeds a planned sequence of key presses to a game, one every `interval`.
+// Held keys stay down until the piece stops moving.
+pub struct Inputs {
+    queue: VecDeque<Input>,
+    interval: Duration,
+    timer: Duration,
+    holding: Option<Action>,
+}
+
+impl Inputs {
+    pub fn new(interval: Duration) -> Inputs {
+        Inputs {
+            queue: VecDeque::new(),
+            interval,
+            timer: interval,
+            holding: None,
+        }
+    }
+
+    pub fn set(&mut self, inputs: Vec<Input>) {
+        self.queue = inputs.into();
+        self.timer = self.interval;
+    }
+
+    pub fn clear(&mut self, game: &mut Game) {
+        self.queue.clear();
+        if let Some(action) = self.holding.take() {
+            game.release(action);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty() && self.holding.is_none()
+    }
+
+    pub fn step(&mut self, ctx: &mut Context, game: &mut Game) {
+        if let Some(action) = self.holding {
+            let stopped = game.current_tet().is_none_or(|tet| {
+                let mut tet = tet.clone();
+                !movegen::apply(&mut tet, Input::Tap(action), game.rules(), game.tets())
+            });
+            if stopped {
+                game.release(action);
+                self.holding = None;
+                self.timer = self.interval;
+            }
+            return;
+        }
+        self.timer = self.timer.checked_sub(timer::delta(ctx)).unwrap_or_default();
+        if self.timer > Duration::from_millis(0) {
+            return;
+        }
+        match self.queue.pop_front() {
+            Some(Input::Hold(action)) => {
+                game.press(action);
+                self.holding = Some(action);
+            },
+            Some(Input::Tap(action)) => {
+                game.press(action);
+                game.release(action);
+            },
+            None => (),
+        }
+        self.timer = self.interval;
+    }
+}
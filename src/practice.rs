@@ -0,0 +1,265 @@
+// Practicing a setup: every restart goes back to the same board, held piece
+// and first pieces. A perfect clear with the pieces in view can be asked for
+// and is shown over the board, piece by piece.
+
+use ggez::{Context, GameResult};
+use ggez::event::{Axis, Button, GamepadId, KeyCode, KeyMods};
+use ggez::graphics::{self, Color};
+
+use crate::ai::Situation;
+use crate::game::{self, Game, Tets, SIDEBAR_WIDTH, TILE_SIZE};
+use crate::input::Control;
+use crate::pc;
+use crate::person::Person;
+use crate::scene::{Scene, Transition};
+use crate::settings::Settings;
+use crate::tet::TetType;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+// What asking for a perfect clear turned up
+enum PcOverlay {
+    // The pieces still to place, in order
+    Plan(Vec<pc::Step>),
+    NotFound,
+}
+
+pub struct Practice {
+    game: Game,
+    person: Person,
+    // What every restart begins with
+    tets: Tets,
+    held: Option<TetType>,
+    pieces: Vec<TetType>,
+    // A perfect clear asked for. It goes away once a piece is placed
+    // anywhere else.
+    pc_overlay: Option<PcOverlay>,
+    // Value of `game.placed()` when last checked
+    placed: usize,
+}
+
+impl Practice {
+    // Plays `game` from this board, with a piece held and the given pieces
+    // coming first
+    pub fn new(
+        ctx: &mut Context,
+        mut game: Game,
+        tets: Tets,
+        held: Option<TetType>,
+        pieces: Vec<TetType>,
+    ) -> GameResult<Practice> {
+        let settings = Settings::load(ctx);
+        let mut person = Person::new(ctx, &settings, &mut game)?;
+        person.set_hints(settings.practice_hints);
+        let mut practice = Practice { game, person, tets, held, pieces, pc_overlay: None, placed: 0 };
+        practice.restart();
+        Ok(practice)
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn game_mut(&mut self) -> &mut Game {
+        &mut self.game
+    }
+
+    pub fn person(&self) -> &Person {
+        &self.person
+    }
+
+    // Shows a message under the board for a moment
+    pub fn notify(&mut self, notice: String) {
+        self.person.notify(notice);
+    }
+
+    // Practices something else from now on
+    pub fn set_setup(&mut self, tets: Tets, held: Option<TetType>, pieces: Vec<TetType>) {
+        self.tets = tets;
+        self.held = held;
+        self.pieces = pieces;
+        self.restart();
+    }
+
+    pub fn restart(&mut self) {
+        self.game.restart();
+        self.game.set_tets(self.tets.clone());
+        self.game.set_held(self.held);
+        if let Some((&first, rest)) = self.pieces.split_first() {
+            self.game.set_queue(rest);
+            self.game.spawn(first);
+        }
+        self.pc_overlay = None;
+        self.placed = self.game.placed();
+    }
+
+    // Looks for a perfect clear with the pieces in play, held and queued, or
+    // hides the one being shown
+    fn toggle_perfect_clear(&mut self) {
+        if self.pc_overlay.take().is_some() {
+            return;
+        }
+        let found = match Situation::of(&self.game) {
+            Some(situation) => pc::solve(&situation, pc::MAX_HEIGHT),
+            None => return,
+        };
+        self.pc_overlay = Some(match found {
+            Some(steps) => PcOverlay::Plan(steps),
+            None => PcOverlay::NotFound,
+        });
+        self.game.assist();
+    }
+
+    // Following the perfect clear being shown moves it on a piece, and
+    // anything else ends it
+    fn follow_plan(&mut self) {
+        let cells = self.game.last_lock().map(|lock| {
+            let mut cells = lock.tet.cells();
+            cells.sort_by_key(|cell| (cell.y, cell.x));
+            cells
+        });
+        self.pc_overlay = match (self.pc_overlay.take(), cells) {
+            (Some(PcOverlay::Plan(mut steps)), Some(cells)) if steps.first().is_some_and(|step| step.cells() == cells) => {
+                steps.remove(0);
+                if steps.is_empty() { None } else { Some(PcOverlay::Plan(steps)) }
+            },
+            _ => None,
+        };
+    }
+
+    pub fn update(&mut self, ctx: &mut Context) -> GameResult {
+        self.game.update(ctx)?;
+        self.person.update(ctx, &mut self.game);
+        if self.game.placed() != self.placed {
+            self.placed = self.game.placed();
+            self.follow_plan();
+        }
+        Ok(())
+    }
+
+    // Controls left to the scene come back, as from `Person`
+    pub fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, repeat: bool) -> Option<Control> {
+        let control = self.person.key_down(ctx, &mut self.game, keycode, repeat);
+        self.control_down(control, repeat)
+    }
+
+    pub fn key_up(&mut self, keycode: KeyCode) {
+        self.person.key_up(&mut self.game, keycode);
+    }
+
+    pub fn button_down(&mut self, button: Button) -> Option<Control> {
+        let control = self.person.button_down(&mut self.game, button);
+        self.control_down(control, false)
+    }
+
+    pub fn button_up(&mut self, button: Button) {
+        self.person.button_up(&mut self.game, button);
+    }
+
+    pub fn axis(&mut self, axis: Axis, value: f32) {
+        self.person.axis(&mut self.game, axis, value);
+    }
+
+    fn control_down(&mut self, control: Option<Control>, repeat: bool) -> Option<Control> {
+        match control {
+            Some(Control::PerfectClear) => {
+                if !repeat && !self.game.is_over() && !self.game.is_paused() {
+                    self.toggle_perfect_clear();
+                }
+                None
+            },
+            control => control,
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        self.person.draw(ctx, &self.game)?;
+        let skin = self.person.skin();
+
+        let playing = !self.game.is_over() && !self.game.is_paused();
+        if let (true, Some(PcOverlay::Plan(steps))) = (playing, &self.pc_overlay) {
+            // Pieces after a line clear are where they'll go once it's
+            // cleared, so they wait until then
+            let mut tets = self.game.tets().clone();
+            for (i, step) in steps.iter().enumerate() {
+                let color = if i == 0 { Color::from_rgb(240, 120, 220) } else { Color::from_rgb(150, 80, 140) };
+                let cells = step.cells();
+                game::draw_outline(ctx, &cells, color)?;
+                let label = skin.text(if step.hold { format!("{}H", i + 1) } else { (i + 1).to_string() }, graphics::DEFAULT_FONT_SCALE);
+                graphics::draw(ctx, &label, (
+                    Point2f32::new(
+                        SIDEBAR_WIDTH * TILE_SIZE + TILE_SIZE * cells[0].x as f32 + 4.0,
+                        TILE_SIZE * cells[0].y as f32 + 3.0,
+                    ),
+                    color,
+                ))?;
+                if tets.lock(&step.placement.tet) > 0 {
+                    break;
+                }
+            }
+        }
+
+        let pc_display = match &self.pc_overlay {
+            Some(PcOverlay::Plan(steps)) => format!("PC: {} pieces", steps.len()),
+            Some(PcOverlay::NotFound) => "No PC found".to_string(),
+            None => return Ok(()),
+        };
+        graphics::draw(
+            ctx,
+            &skin.text(pc_display, graphics::DEFAULT_FONT_SCALE),
+            (Point2f32::new(10.0, 150.0), skin.text_color()),
+        )
+    }
+
+    pub fn window_size(&self) -> (f32, f32) {
+        self.game.window_size()
+    }
+}
+
+// A setup on its own, e.g. from a fumen
+impl Scene for Practice {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<Transition> {
+        Practice::update(self, ctx)?;
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, repeat: bool) -> Transition {
+        if let KeyCode::Escape = keycode {
+            return Transition::Pop;
+        }
+        if let (Some(Control::Restart), false) = (Practice::key_down(self, ctx, keycode, repeat), repeat) {
+            self.restart();
+        }
+        Transition::None
+    }
+
+    fn key_up(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        Practice::key_up(self, keycode);
+    }
+
+    fn button_down(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) -> Transition {
+        if let Button::Mode = button {
+            return Transition::Pop;
+        }
+        if let Some(Control::Restart) = Practice::button_down(self, button) {
+            self.restart();
+        }
+        Transition::None
+    }
+
+    fn button_up(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) {
+        Practice::button_up(self, button);
+    }
+
+    fn axis(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        Practice::axis(self, axis, value);
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        Practice::draw(self, ctx)
+    }
+
+    fn window_size(&self) -> (f32, f32) {
+        Practice::window_size(self)
+    }
+}
@@ -0,0 +1,611 @@
+// Lifetime statistics for each local profile, and the screens for looking at
+// them and switching between profiles.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ggez::{Context, GameResult};
+use ggez::event::{KeyCode, KeyMods};
+use ggez::graphics::{self, Color, DrawParam};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::{self, Game};
+use crate::save;
+use crate::scene::{Menu, Scene, Transition};
+use crate::scores;
+use crate::session::Mode;
+use crate::skin;
+use crate::stats::Stats;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+const PROFILES_PATH: &str = "/profiles.json";
+const PROFILES_VERSION: u64 = 1;
+const DEFAULT_NAME: &str = "Player";
+// Games kept for the PPS trend, oldest first
+const TREND_LEN: usize = 50;
+
+// The best a profile has done in a mode
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Best {
+    pub score: usize,
+    pub lines: usize,
+    // Fastest to the goal, in modes ranked by time
+    pub time: Option<Duration>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrendPoint {
+    // Seconds since the Unix epoch
+    pub date: u64,
+    pub pps: f64,
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub games: usize,
+    pub lines: usize,
+    pub time: Duration,
+    // Every game's statistics added up
+    pub totals: Stats,
+    pub bests: BTreeMap<String, Best>,
+    pub trend: Vec<TrendPoint>,
+}
+
+// What a profile counts of a finished game
+pub struct Played {
+    pub score: usize,
+    pub lines: usize,
+    pub elapsed: Duration,
+    pub stats: Stats,
+    // Hints were used
+    pub assisted: bool,
+    // Seconds since the Unix epoch
+    pub date: u64,
+}
+
+impl Played {
+    pub fn of(game: &Game) -> Played {
+        Played {
+            score: game.score(),
+            lines: game.lines(),
+            elapsed: game.elapsed(),
+            stats: game.stats().clone(),
+            assisted: game.assisted(),
+            date: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+        }
+    }
+}
+
+impl Profile {
+    // Counts a finished game. `goal_time` is how long it took to reach the
+    // goal of a mode ranked by time.
+    pub fn record(&mut self, mode: &str, played: &Played, goal_time: Option<Duration>) {
+        let stats = &played.stats;
+        self.games += 1;
+        self.lines += played.lines;
+        self.time += played.elapsed;
+        self.totals.add(stats);
+
+        // Games played with hints don't set bests, as high scores mark them
+        if !played.assisted {
+            let best = self.bests.entry(mode.to_string()).or_default();
+            best.score = best.score.max(played.score);
+            best.lines = best.lines.max(played.lines);
+            if let Some(time) = goal_time {
+                best.time = Some(best.time.map_or(time, |best| best.min(time)));
+            }
+        }
+
+        if stats.pieces > 0 {
+            self.trend.push(TrendPoint { date: played.date, pps: stats.pps(played.elapsed) });
+            let excess = self.trend.len().saturating_sub(TREND_LEN);
+            self.trend.drain(..excess);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Profiles {
+    current: String,
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl Default for Profiles {
+    fn default() -> Profiles {
+        let mut profiles = BTreeMap::new();
+        profiles.insert(DEFAULT_NAME.to_string(), Profile::default());
+        Profiles { current: DEFAULT_NAME.to_string(), profiles }
+    }
+}
+
+impl Profiles {
+    // The stored profiles; an unreadable file is reported and replaced
+    pub fn load(ctx: &mut Context) -> Profiles {
+        let mut profiles: Profiles = save::load_or_default(ctx, PROFILES_PATH, PROFILES_VERSION);
+        // There's always a current profile
+        let current = profiles.current.clone();
+        profiles.profiles.entry(current).or_default();
+        profiles
+    }
+
+    pub fn save(&self, ctx: &mut Context) -> GameResult {
+        save::write_versioned(ctx, PROFILES_PATH, PROFILES_VERSION, self)
+    }
+
+    // Adds a finished game to the current profile's statistics
+    pub fn record(ctx: &mut Context, mode: &str, game: &Game, goal_time: Option<Duration>) -> GameResult {
+        let mut profiles = Profiles::load(ctx);
+        profiles.current_mut().record(mode, &Played::of(game), goal_time);
+        profiles.save(ctx)
+    }
+
+    pub fn current_name(&self) -> &str {
+        &self.current
+    }
+
+    pub fn current(&self) -> &Profile {
+        &self.profiles[&self.current]
+    }
+
+    fn current_mut(&mut self) -> &mut Profile {
+        self.profiles.get_mut(&self.current).expect("no current profile")
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.profiles.keys().cloned().collect()
+    }
+
+    pub fn switch(&mut self, name: &str) {
+        if self.profiles.contains_key(name) {
+            self.current = name.to_string();
+        }
+    }
+
+    // Starts a profile and switches to it, unless the name is taken
+    pub fn add(&mut self, name: &str) -> bool {
+        if self.profiles.contains_key(name) {
+            return false;
+        }
+        self.profiles.insert(name.to_string(), Profile::default());
+        self.current = name.to_string();
+        true
+    }
+
+    // Removes a profile, unless it's the only one. Removing the current
+    // profile switches to another.
+    pub fn remove(&mut self, name: &str) -> bool {
+        if self.profiles.len() < 2 || self.profiles.remove(name).is_none() {
+            return false;
+        }
+        if self.current == name {
+            self.current = self.profiles.keys().next().cloned().unwrap_or_default();
+        }
+        true
+    }
+}
+
+// Hours and minutes, for time played over many games
+fn format_hours(duration: Duration) -> String {
+    let minutes = duration.as_secs() / 60;
+    format!("{}h {:02}m", minutes / 60, minutes % 60)
+}
+
+fn draw_text(ctx: &mut Context, text: &str, x: f32, y: f32, color: Color) -> GameResult {
+    let text = skin::text(text, 14.0);
+    graphics::draw(ctx, &text, (Point2f32::new(x, y), color))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Page {
+    Overview,
+    Clears,
+    Trend,
+    Bests,
+}
+
+impl Page {
+    const ALL: [Page; 4] = [Page::Overview, Page::Clears, Page::Trend, Page::Bests];
+
+    fn title(self) -> &'static str {
+        match self {
+            Page::Overview => "Overview",
+            Page::Clears => "Clears",
+            Page::Trend => "PPS trend",
+            Page::Bests => "Personal bests",
+        }
+    }
+}
+
+const LABEL_COLOR: Color = Color { r: 0.7, g: 0.7, b: 0.7, a: 1.0 };
+const BAR_COLOR: Color = Color { r: 0.35, g: 0.67, b: 0.9, a: 1.0 };
+const LINE_COLOR: Color = Color { r: 0.94, g: 0.86, b: 0.31, a: 1.0 };
+
+// The current profile's statistics, a page at a time
+pub struct StatsScreen {
+    profiles: Profiles,
+    page: usize,
+}
+
+impl StatsScreen {
+    pub fn new(ctx: &mut Context) -> StatsScreen {
+        StatsScreen { profiles: Profiles::load(ctx), page: 0 }
+    }
+
+    fn draw_overview(&self, ctx: &mut Context) -> GameResult {
+        let profile = self.profiles.current();
+        let totals = &profile.totals;
+        let clears: usize = totals.clears.iter().sum::<usize>() + totals.t_spins[1..].iter().sum::<usize>();
+        let rows = [
+            ("Games played", profile.games.to_string()),
+            ("Time played", format_hours(profile.time)),
+            ("Lines", profile.lines.to_string()),
+            ("Pieces", totals.pieces.to_string()),
+            ("PPS", format!("{:.2}", totals.pps(profile.time))),
+            ("KPP", format!("{:.2}", totals.kpp())),
+            ("Attack", totals.attack.to_string()),
+            ("APM", format!("{:.1}", totals.apm(profile.time))),
+            ("Line clears", clears.to_string()),
+            ("Perfect clears", totals.perfect_clears.to_string()),
+            ("Max combo", totals.max_combo.to_string()),
+            ("Max B2B", totals.max_b2b.to_string()),
+        ];
+        for (i, (label, value)) in rows.iter().enumerate() {
+            let y = 60.0 + 22.0 * i as f32;
+            draw_text(ctx, label, 100.0, y, LABEL_COLOR)?;
+            draw_text(ctx, value, 250.0, y, skin::text_color())?;
+        }
+        Ok(())
+    }
+
+    // A bar for each kind of clear, against the most common
+    fn draw_clears(&self, ctx: &mut Context) -> GameResult {
+        let totals = &self.profiles.current().totals;
+        let bars = [
+            ("Singles", totals.clears[0]),
+            ("Doubles", totals.clears[1]),
+            ("Triples", totals.clears[2]),
+            ("Tetrises", totals.clears[3]),
+            ("T-spin singles", totals.t_spins[1]),
+            ("T-spin doubles", totals.t_spins[2]),
+            ("T-spin triples", totals.t_spins[3]),
+            ("Perfect clears", totals.perfect_clears),
+        ];
+        let all: usize = bars[..7].iter().map(|&(_, count)| count).sum();
+        let most = bars.iter().map(|&(_, count)| count).max().unwrap_or(0).max(1);
+        let (left, right) = (120.0, game::WINDOW_WIDTH - 90.0);
+
+        let mut mesh = graphics::MeshBuilder::new();
+        let bottom = 60.0 + 32.0 * bars.len() as f32;
+        mesh.line(&[Point2f32::new(left, 55.0), Point2f32::new(left, bottom)], 1.0, LABEL_COLOR)?;
+        for (i, &(label, count)) in bars.iter().enumerate() {
+            let y = 60.0 + 32.0 * i as f32;
+            let width = (right - left) * count as f32 / most as f32;
+            if count > 0 {
+                mesh.rectangle(graphics::DrawMode::fill(), [left, y + 4.0, width, 16.0].into(), BAR_COLOR);
+            }
+            draw_text(ctx, label, 10.0, y + 4.0, LABEL_COLOR)?;
+            // Perfect clears are also some other clear, so aren't a share
+            let share = if i < 7 && all > 0 { format!(" ({}%)", count * 100 / all) } else { String::new() };
+            draw_text(ctx, &format!("{}{}", count, share), left + width + 6.0, y + 4.0, skin::text_color())?;
+        }
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())
+    }
+
+    // Pieces per second of recent games, oldest on the left
+    fn draw_trend(&self, ctx: &mut Context) -> GameResult {
+        let trend = &self.profiles.current().trend;
+        let (left, right, top, bottom) = (50.0, game::WINDOW_WIDTH - 20.0, 60.0, game::WINDOW_HEIGHT - 80.0);
+        // The scale goes up in half pieces per second
+        let highest = trend.iter().map(|point| point.pps).fold(0.0, f64::max);
+        let ceiling = ((highest * 2.0).ceil() / 2.0).max(0.5) as f32;
+
+        let mut mesh = graphics::MeshBuilder::new();
+        mesh.line(
+            &[Point2f32::new(left, top), Point2f32::new(left, bottom), Point2f32::new(right, bottom)],
+            1.0,
+            LABEL_COLOR,
+        )?;
+        let step = if trend.len() > 1 { (right - left) / (trend.len() - 1) as f32 } else { 0.0 };
+        let points: Vec<Point2f32> = trend.iter()
+            .enumerate()
+            .map(|(i, point)| Point2f32::new(
+                left + step * i as f32,
+                bottom - (bottom - top) * point.pps as f32 / ceiling,
+            ))
+            .collect();
+        if points.len() > 1 {
+            mesh.line(&points, 2.0, LINE_COLOR)?;
+        }
+        for &point in &points {
+            mesh.circle(graphics::DrawMode::fill(), point, 3.0, 0.5, LINE_COLOR);
+        }
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())?;
+
+        draw_text(ctx, &format!("{:.1}", ceiling), 10.0, top - 7.0, LABEL_COLOR)?;
+        draw_text(ctx, "0", 10.0, bottom - 7.0, LABEL_COLOR)?;
+        if trend.is_empty() {
+            return draw_text(ctx, "No games yet", 10.0, bottom + 10.0, skin::text_color());
+        }
+        let average = trend.iter().map(|point| point.pps).sum::<f64>() / trend.len() as f64;
+        let since = format!("Last {} games, since {}", trend.len(), scores::format_date(trend[0].date));
+        draw_text(ctx, &since, 10.0, bottom + 10.0, skin::text_color())?;
+        draw_text(ctx, &format!("Average {:.2}, best {:.2}", average, highest), 10.0, bottom + 30.0, skin::text_color())
+    }
+
+    fn draw_bests(&self, ctx: &mut Context) -> GameResult {
+        let bests = &self.profiles.current().bests;
+        let columns = [10.0, 150.0, 250.0, 330.0];
+        for (&x, header) in columns.iter().zip(["Mode", "Score", "Lines", "Time"].iter()) {
+            draw_text(ctx, header, x, 50.0, LABEL_COLOR)?;
+        }
+        let played = Mode::ALL.iter().filter_map(|mode| bests.get(mode.name()).map(|best| (mode, best)));
+        for (i, (mode, best)) in played.enumerate() {
+            let cells = [
+                mode.title().to_string(),
+                best.score.to_string(),
+                best.lines.to_string(),
+                best.time.map_or("-".to_string(), scores::format_duration),
+            ];
+            for (&x, cell) in columns.iter().zip(cells.iter()) {
+                draw_text(ctx, cell, x, 72.0 + 20.0 * i as f32, skin::text_color())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Scene for StatsScreen {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        let pages = Page::ALL.len();
+        match keycode {
+            KeyCode::Escape | KeyCode::Return => return Transition::Pop,
+            KeyCode::Left => self.page = (self.page + pages - 1) % pages,
+            KeyCode::Right => self.page = (self.page + 1) % pages,
+            KeyCode::P => return Transition::Push(Box::new(ProfileSelect::new(ctx))),
+            _ => (),
+        }
+        Transition::None
+    }
+
+    // The profile may have been switched
+    fn resume(&mut self, ctx: &mut Context) {
+        self.profiles = Profiles::load(ctx);
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let page = Page::ALL[self.page];
+        let title = skin::text(format!("< {}: {} >", self.profiles.current_name(), page.title()), 24.0);
+        let offset = title.width(ctx) as f32 / 2.0;
+        graphics::draw(ctx, &title, (Point2f32::new(game::WINDOW_WIDTH / 2.0 - offset, 15.0), skin::text_color()))?;
+
+        match page {
+            Page::Overview => self.draw_overview(ctx)?,
+            Page::Clears => self.draw_clears(ctx)?,
+            Page::Trend => self.draw_trend(ctx)?,
+            Page::Bests => self.draw_bests(ctx)?,
+        }
+        draw_text(ctx, "Left/Right: page  P: profiles", 10.0, game::WINDOW_HEIGHT - 25.0, LABEL_COLOR)
+    }
+}
+
+// Picks the profile games count towards: Enter switches, Delete removes
+pub struct ProfileSelect {
+    profiles: Profiles,
+    menu: Menu,
+    // The name being typed for a new profile
+    naming: Option<String>,
+    message: String,
+}
+
+impl ProfileSelect {
+    const HELP: &'static str = "Enter: switch  Delete: remove";
+
+    pub fn new(ctx: &mut Context) -> ProfileSelect {
+        let mut screen = ProfileSelect {
+            profiles: Profiles::load(ctx),
+            menu: Menu::new("Profiles", &[]),
+            naming: None,
+            message: Self::HELP.to_string(),
+        };
+        screen.update_items();
+        screen
+    }
+
+    fn update_items(&mut self) {
+        let names = self.profiles.names();
+        let current = names.iter().position(|name| name == self.profiles.current_name()).unwrap_or(0);
+        let mut items: Vec<String> = names.iter()
+            .map(|name| if name == self.profiles.current_name() { format!("* {}", name) } else { name.clone() })
+            .collect();
+        items.push("New profile".to_string());
+        items.push("Back".to_string());
+        self.menu = Menu::with_items("Profiles", items);
+        self.menu.select(current);
+    }
+
+    // Writes the profiles, saying so under the menu if they couldn't be
+    fn save(&mut self, ctx: &mut Context) -> bool {
+        match self.profiles.save(ctx) {
+            Ok(()) => true,
+            Err(err) => {
+                self.message = format!("Couldn't save profiles: {}", err);
+                false
+            },
+        }
+    }
+}
+
+impl Scene for ProfileSelect {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if let Some(name) = &mut self.naming {
+            match keycode {
+                KeyCode::Escape => {
+                    self.naming = None;
+                    self.message = Self::HELP.to_string();
+                },
+                KeyCode::Back => {
+                    name.pop();
+                },
+                KeyCode::Return => {
+                    let name = name.trim().to_string();
+                    if name.is_empty() {
+                        return Transition::None;
+                    }
+                    self.naming = None;
+                    if self.profiles.add(&name) {
+                        self.update_items();
+                        self.message = Self::HELP.to_string();
+                        self.save(ctx);
+                    } else {
+                        self.message = format!("There's already a profile called {}", name);
+                    }
+                },
+                _ => (),
+            }
+            return Transition::None;
+        }
+
+        let names = self.profiles.names();
+        match keycode {
+            KeyCode::Escape => return Transition::Pop,
+            KeyCode::Delete => if let Some(name) = names.get(self.menu.selected()) {
+                if self.profiles.remove(name) {
+                    self.update_items();
+                    self.message = format!("Removed {}", name);
+                    self.save(ctx);
+                } else {
+                    self.message = "The only profile can't be removed".to_string();
+                }
+            },
+            _ => match self.menu.key_down(keycode) {
+                Some(i) if i < names.len() => {
+                    self.profiles.switch(&names[i]);
+                    if self.save(ctx) {
+                        return Transition::Pop;
+                    }
+                    self.update_items();
+                },
+                Some(i) if i == names.len() => {
+                    self.naming = Some(String::new());
+                },
+                Some(_) => return Transition::Pop,
+                None => (),
+            },
+        }
+        Transition::None
+    }
+
+    fn text_input(&mut self, _ctx: &mut Context, character: char) {
+        if let Some(name) = &mut self.naming {
+            let allowed = character.is_alphanumeric() || " -_.".contains(character);
+            if allowed && name.chars().count() < scores::MAX_NAME_LEN {
+                name.push(character);
+            }
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)?;
+        let message = match &self.naming {
+            Some(name) => format!("Name: {}_", name),
+            None => self.message.clone(),
+        };
+        let mut text = skin::text(message, graphics::DEFAULT_FONT_SCALE);
+        text.set_bounds(Point2f32::new(game::WINDOW_WIDTH - 20.0, 30.0), graphics::Align::Center);
+        graphics::draw(ctx, &text, (Point2f32::new(10.0, 98.0), skin::text_color()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn played(score: usize, lines: usize, secs: u64, pieces: usize) -> Played {
+        Played {
+            score,
+            lines,
+            elapsed: Duration::from_secs(secs),
+            stats: Stats { pieces, keys: pieces * 3, ..Stats::default() },
+            assisted: false,
+            date: secs,
+        }
+    }
+
+    #[test]
+    fn lifetime_totals() {
+        let mut profile = Profile::default();
+        profile.record("Marathon", &played(1200, 10, 60, 90), None);
+        profile.record("Marathon", &played(800, 14, 90, 120), None);
+        profile.record("Sprint", &played(300, 40, 50, 100), Some(Duration::from_secs(50)));
+        profile.record("Sprint", &played(300, 40, 45, 100), Some(Duration::from_secs(45)));
+
+        assert_eq!(profile.games, 4);
+        assert_eq!(profile.lines, 104);
+        assert_eq!(profile.time, Duration::from_secs(245));
+        assert_eq!(profile.totals.pieces, 410);
+        assert_eq!(profile.totals.keys, 1230);
+
+        let marathon = &profile.bests["Marathon"];
+        assert_eq!((marathon.score, marathon.lines, marathon.time), (1200, 14, None));
+        assert_eq!(profile.bests["Sprint"].time, Some(Duration::from_secs(45)));
+
+        let trend: Vec<f64> = profile.trend.iter().map(|point| point.pps).collect();
+        assert_eq!(trend, vec![1.5, 120.0 / 90.0, 2.0, 100.0 / 45.0]);
+    }
+
+    #[test]
+    fn assisted_games_count_but_set_no_bests() {
+        let mut profile = Profile::default();
+        profile.record("Marathon", &Played { assisted: true, ..played(5000, 50, 300, 400) }, None);
+        assert_eq!((profile.games, profile.lines), (1, 50));
+        assert!(profile.bests.is_empty());
+
+        // Games without a piece placed leave the trend alone
+        profile.record("Marathon", &played(0, 0, 2, 0), None);
+        assert_eq!(profile.trend.len(), 1);
+        assert_eq!(profile.bests["Marathon"].score, 0);
+    }
+
+    #[test]
+    fn trend_keeps_the_latest_games() {
+        let mut profile = Profile::default();
+        for secs in 1..=TREND_LEN as u64 + 10 {
+            profile.record("Zen", &played(0, 0, secs, 10), None);
+        }
+        assert_eq!(profile.trend.len(), TREND_LEN);
+        assert_eq!(profile.trend[0].date, 11);
+        assert_eq!(profile.games, TREND_LEN + 10);
+    }
+
+    #[test]
+    fn switching_profiles() {
+        let mut profiles = Profiles::default();
+        assert!(profiles.add("Second"));
+        assert!(!profiles.add("Second"));
+        assert_eq!(profiles.current_name(), "Second");
+        profiles.current_mut().record("Zen", &played(100, 4, 30, 20), None);
+        profiles.switch(DEFAULT_NAME);
+        assert_eq!(profiles.current().games, 0);
+
+        assert!(profiles.remove(DEFAULT_NAME));
+        assert_eq!(profiles.current_name(), "Second");
+        assert_eq!(profiles.current().games, 1);
+        assert!(!profiles.remove("Second"));
+    }
+}
@@ -0,0 +1,833 @@
+// Puzzles: a board, a fixed queue and a goal to reach with those pieces,
+// sometimes in fewer moves. They come in packs, TOML files in the puzzles
+// directory of the resources or the user data; which ones have been solved
+// is kept between runs.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+
+use ggez::{Context, GameError, GameResult};
+use ggez::event::{Axis, Button, GamepadId, KeyCode, KeyMods};
+use ggez::filesystem;
+use ggez::graphics::{self, Color};
+
+use serde::{Deserialize, Serialize, Serializer};
+use serde::ser::SerializeMap;
+
+use crate::editor::Editor;
+use crate::game::{self, Block, Game, Tets};
+use crate::input::Control;
+use crate::menu;
+use crate::pieces::PieceSet;
+use crate::practice::Practice;
+use crate::rules::{Randomizer, Ruleset};
+use crate::save;
+use crate::scene::{self, Menu, Scene, Transition};
+use crate::skin;
+use crate::tet::TetType;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+// Where packs are read from, in both the resources and the user data
+pub const PACKS_DIR: &str = "/puzzles";
+// The pack the editor saves to, in the user data
+pub const CUSTOM_PATH: &str = "/puzzles/custom.toml";
+const CUSTOM_NAME: &str = "Custom";
+
+const PROGRESS_PATH: &str = "/puzzles.json";
+const PROGRESS_VERSION: u64 = 1;
+
+// Written as `goal = { lines = 4 }`, `goal = { t_spin = 3 }`,
+// `goal = "no_holes"` or `goal = "clear_garbage"`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "SavedGoal")]
+pub enum Goal {
+    // Clear this many lines in all
+    Lines(usize),
+    // A T-spin clearing exactly this many lines
+    TSpin(usize),
+    // Once every piece is placed
+    NoHoles,
+    ClearGarbage,
+}
+
+// toml reads `goal = { lines = 4 }` as an enum but not the same table
+// written out as `[puzzle.goal]`, which is how it writes one, so goals are
+// read and written as a name or a name and a count by hand
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SavedGoal {
+    Named(String),
+    Counted(BTreeMap<String, usize>),
+}
+
+impl TryFrom<SavedGoal> for Goal {
+    type Error = String;
+
+    fn try_from(saved: SavedGoal) -> Result<Goal, String> {
+        let goal = match saved {
+            SavedGoal::Named(name) => match name.as_str() {
+                "no_holes" => Some(Goal::NoHoles),
+                "clear_garbage" => Some(Goal::ClearGarbage),
+                _ => None,
+            },
+            SavedGoal::Counted(counts) if counts.len() == 1 => match counts.into_iter().next() {
+                Some((name, lines)) if name == "lines" => Some(Goal::Lines(lines)),
+                Some((name, lines)) if name == "t_spin" => Some(Goal::TSpin(lines)),
+                _ => None,
+            },
+            SavedGoal::Counted(_) => None,
+        };
+        goal.ok_or_else(|| "goal must be \"no_holes\", \"clear_garbage\", { lines = n } or { t_spin = n }".to_string())
+    }
+}
+
+impl Serialize for Goal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (name, lines) = match *self {
+            Goal::Lines(lines) => ("lines", lines),
+            Goal::TSpin(lines) => ("t_spin", lines),
+            Goal::NoHoles => return serializer.serialize_str("no_holes"),
+            Goal::ClearGarbage => return serializer.serialize_str("clear_garbage"),
+        };
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(name, &lines)?;
+        map.end()
+    }
+}
+
+impl Goal {
+    pub const T_SPINS: [&'static str; 4] = ["T-spin", "T-spin single", "T-spin double", "T-spin triple"];
+
+    pub fn describe(self) -> String {
+        match self {
+            Goal::Lines(1) => "Clear a line".to_string(),
+            Goal::Lines(lines) => format!("Clear {} lines", lines),
+            Goal::TSpin(lines) => Goal::T_SPINS.get(lines).unwrap_or(&"T-spin").to_string(),
+            Goal::NoHoles => "Leave no holes".to_string(),
+            Goal::ClearGarbage => "Clear all garbage".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(try_from = "SavedPuzzle")]
+pub struct Puzzle {
+    pub name: String,
+    pub start: Tets,
+    pub queue: Vec<TetType>,
+    pub goal: Goal,
+    // Pieces that may be placed, if fewer than the queue
+    pub moves: Option<usize>,
+}
+
+// A puzzle as written, with a picture of the bottom of the board: '.' marks
+// empty cells, 'G' (or '#') garbage and a piece's letter a block of its
+// colour
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SavedPuzzle {
+    name: String,
+    queue: Vec<TetType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    moves: Option<usize>,
+    #[serde(default)]
+    board: String,
+    goal: Goal,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PackFile {
+    name: String,
+    #[serde(default)]
+    puzzle: Vec<Puzzle>,
+}
+
+#[derive(Serialize)]
+struct SavedPack<'a> {
+    name: &'a str,
+    puzzle: Vec<SavedPuzzle>,
+}
+
+fn parse_board(picture: &str) -> Result<Tets, String> {
+    let rows: Vec<&str> = picture.lines()
+        .map(str::trim)
+        .filter(|row| !row.is_empty())
+        .collect();
+    let mut tets = Tets::default();
+    if rows.len() > tets.height() {
+        return Err(format!("board is taller than {} rows", tets.height()));
+    }
+    let top = tets.height() - rows.len();
+    for (y, row) in rows.iter().enumerate() {
+        if row.chars().count() != tets.width() {
+            return Err(format!("board rows must be {} wide", tets.width()));
+        }
+        for (x, mark) in row.chars().enumerate() {
+            let block = match mark {
+                '.' => None,
+                'G' | '#' => Some(Block::Garbage),
+                _ => match TetType::named(&mark.to_string()) {
+                    Some(tet_type) => Some(Block::Tet(tet_type)),
+                    None => return Err(format!("board has {:?}, which isn't a piece", mark)),
+                },
+            };
+            tets.set((top + y) as i8, x as i8, block);
+        }
+    }
+    Ok(tets)
+}
+
+// From the highest block down. Blocks of pieces without a one-letter name
+// are written as garbage.
+fn board_picture(tets: &Tets) -> String {
+    tets.iter()
+        .skip_while(|row| row.iter().all(Option::is_none))
+        .map(|row| {
+            let mut line: String = row.iter()
+                .map(|block| match block {
+                    None => '.',
+                    Some(Block::Tet(tet_type)) if tet_type.name().chars().count() == 1 => {
+                        tet_type.name().chars().next().unwrap()
+                    },
+                    Some(_) => 'G',
+                })
+                .collect();
+            line.push('\n');
+            line
+        })
+        .collect()
+}
+
+pub fn has_garbage(tets: &Tets) -> bool {
+    tets.iter().flatten().any(|block| *block == Some(Block::Garbage))
+}
+
+// Empty cells with a block somewhere above them
+pub fn has_holes(tets: &Tets) -> bool {
+    (0..tets.width()).any(|col| {
+        let mut covered = false;
+        tets.iter().any(|row| {
+            let filled = row[col].is_some();
+            let hole = covered && !filled;
+            covered |= filled;
+            hole
+        })
+    })
+}
+
+impl TryFrom<SavedPuzzle> for Puzzle {
+    type Error = String;
+
+    fn try_from(saved: SavedPuzzle) -> Result<Puzzle, String> {
+        let start = parse_board(&saved.board)
+            .map_err(|reason| format!("puzzle {:?}: {}", saved.name, reason))?;
+        let puzzle = Puzzle {
+            name: saved.name,
+            start,
+            queue: saved.queue,
+            goal: saved.goal,
+            moves: saved.moves,
+        };
+        puzzle.check()?;
+        Ok(puzzle)
+    }
+}
+
+impl From<&Puzzle> for SavedPuzzle {
+    fn from(puzzle: &Puzzle) -> SavedPuzzle {
+        SavedPuzzle {
+            name: puzzle.name.clone(),
+            queue: puzzle.queue.clone(),
+            moves: puzzle.moves,
+            board: board_picture(&puzzle.start),
+            goal: puzzle.goal,
+        }
+    }
+}
+
+impl Puzzle {
+    // Whether the puzzle can be played, and could be solved
+    pub fn check(&self) -> Result<(), String> {
+        let fail = |reason: &str| Err(format!("puzzle {:?}: {}", self.name, reason));
+        if self.queue.is_empty() {
+            return fail("queue is empty");
+        }
+        if self.moves == Some(0) {
+            return fail("moves must be at least 1");
+        }
+        match self.goal {
+            Goal::Lines(0) => fail("goal must clear at least 1 line"),
+            Goal::TSpin(lines) if lines >= Goal::T_SPINS.len() => fail("a T-spin clears at most 3 lines"),
+            Goal::TSpin(_) if !self.queue.contains(&TetType::T) => fail("a T-spin needs a T in the queue"),
+            Goal::ClearGarbage if !has_garbage(&self.start) => fail("board has no garbage to clear"),
+            _ => Ok(()),
+        }
+    }
+
+    // Pieces that may be placed
+    pub fn limit(&self) -> usize {
+        self.moves.map_or(self.queue.len(), |moves| moves.min(self.queue.len()))
+    }
+
+    // Goals other than leaving no holes are reached with a placement, and
+    // the puzzle is over once it's reached or the pieces run out. Topping
+    // out fails it even if what's left on the board meets the goal.
+    fn outcome(&self, game: &Game) -> Option<Outcome> {
+        let tets = game.tets();
+        let reached = match self.goal {
+            Goal::Lines(lines) => game.lines() >= lines,
+            Goal::TSpin(lines) => game.last_lock().is_some_and(|lock| lock.t_spin && lock.lines == lines),
+            Goal::ClearGarbage => !has_garbage(tets),
+            Goal::NoHoles => false,
+        };
+        let out = game.placed() >= self.limit() || game.is_over();
+        Some(if game.is_dead() {
+            Outcome::Failed("Topped out")
+        } else if reached || (out && self.goal == Goal::NoHoles && !has_holes(tets)) {
+            Outcome::Solved
+        } else if out && self.moves.is_some_and(|moves| moves < self.queue.len()) {
+            Outcome::Failed("Out of moves")
+        } else if out {
+            Outcome::Failed("Out of pieces")
+        } else {
+            return None;
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct Pack {
+    pub name: String,
+    pub path: String,
+    pub puzzles: Vec<Puzzle>,
+}
+
+impl Pack {
+    pub fn load(ctx: &mut Context, path: &str) -> GameResult<Pack> {
+        let mut data = String::new();
+        filesystem::open(ctx, path)?.read_to_string(&mut data)?;
+        Pack::parse(path, &data)
+    }
+
+    // A pack from the contents of its file at `path`
+    pub fn parse(path: &str, data: &str) -> GameResult<Pack> {
+        let file: PackFile = toml::from_str(data)
+            .map_err(|err| GameError::ConfigError(format!("{}: {}", path, err)))?;
+        Ok(Pack { name: file.name, path: path.to_string(), puzzles: file.puzzle })
+    }
+
+    // Every pack, by file name, and why any that couldn't be read weren't
+    pub fn all(ctx: &mut Context) -> (Vec<Pack>, Vec<String>) {
+        let mut paths: Vec<String> = match filesystem::read_dir(ctx, PACKS_DIR) {
+            Ok(paths) => paths
+                .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+                .filter_map(|path| path.to_str().map(str::to_string))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        // A pack in both directories is read once, from the user data
+        paths.sort();
+        paths.dedup();
+        let (mut packs, mut errors) = (Vec::new(), Vec::new());
+        for path in paths {
+            match Pack::load(ctx, &path) {
+                Ok(pack) => packs.push(pack),
+                Err(err) => errors.push(err.to_string()),
+            }
+        }
+        (packs, errors)
+    }
+
+    // The pack the editor saves to, empty if nothing has been saved yet
+    pub fn custom(ctx: &mut Context) -> GameResult<Pack> {
+        if !filesystem::exists(ctx, CUSTOM_PATH) {
+            return Ok(Pack { name: CUSTOM_NAME.to_string(), path: CUSTOM_PATH.to_string(), puzzles: Vec::new() });
+        }
+        Pack::load(ctx, CUSTOM_PATH)
+    }
+
+    // What's written to the pack's file
+    pub fn data(&self) -> GameResult<String> {
+        let pack = SavedPack {
+            name: &self.name,
+            puzzle: self.puzzles.iter().map(SavedPuzzle::from).collect(),
+        };
+        toml::to_string_pretty(&pack)
+            .map_err(|err| GameError::ConfigError(format!("{}: {}", self.path, err)))
+    }
+
+    // Writes the pack to its path in the user data
+    pub fn save(&self, ctx: &mut Context) -> GameResult {
+        let data = self.data()?;
+        filesystem::create_dir(ctx, PACKS_DIR)?;
+        filesystem::create(ctx, &self.path)?.write_all(data.as_bytes())?;
+        Ok(())
+    }
+
+    // Adds a puzzle, or replaces the one with its name
+    pub fn put(&mut self, puzzle: Puzzle) {
+        match self.puzzles.iter_mut().find(|old| old.name == puzzle.name) {
+            Some(old) => *old = puzzle,
+            None => self.puzzles.push(puzzle),
+        }
+    }
+
+    fn key(&self, puzzle: &Puzzle) -> String {
+        format!("{}/{}", self.name, puzzle.name)
+    }
+}
+
+// Puzzles solved, by pack and puzzle name
+#[derive(Default, Serialize, Deserialize)]
+pub struct Progress {
+    solved: BTreeSet<String>,
+}
+
+impl Progress {
+    // An unreadable file is reported and replaced
+    pub fn load(ctx: &mut Context) -> Progress {
+        save::load_or_default(ctx, PROGRESS_PATH, PROGRESS_VERSION)
+    }
+
+    pub fn save(&self, ctx: &mut Context) -> GameResult {
+        save::write_versioned(ctx, PROGRESS_PATH, PROGRESS_VERSION, self)
+    }
+
+    pub fn is_solved(&self, pack: &Pack, puzzle: &Puzzle) -> bool {
+        self.solved.contains(&pack.key(puzzle))
+    }
+
+    pub fn solved_in(&self, pack: &Pack) -> usize {
+        pack.puzzles.iter().filter(|puzzle| self.is_solved(pack, puzzle)).count()
+    }
+
+    pub fn solve(ctx: &mut Context, pack: &Pack, puzzle: &Puzzle) -> GameResult {
+        let mut progress = Progress::load(ctx);
+        if progress.solved.insert(pack.key(puzzle)) {
+            progress.save(ctx)?;
+        }
+        Ok(())
+    }
+}
+
+// The packs, with how much of each is solved, and the editor
+pub struct PackSelect {
+    menu: Menu,
+    packs: Vec<Pack>,
+    errors: Vec<String>,
+}
+
+impl PackSelect {
+    pub fn new(ctx: &mut Context) -> PackSelect {
+        let mut select = PackSelect { menu: Menu::new("Puzzles", &[]), packs: Vec::new(), errors: Vec::new() };
+        select.refresh(ctx);
+        select
+    }
+
+    fn refresh(&mut self, ctx: &mut Context) {
+        let (packs, errors) = Pack::all(ctx);
+        let progress = Progress::load(ctx);
+        let mut items: Vec<String> = packs.iter()
+            .map(|pack| format!("{} ({}/{})", pack.name, progress.solved_in(pack), pack.puzzles.len()))
+            .collect();
+        items.push("New puzzle".to_string());
+        let selected = self.menu.selected();
+        self.menu = Menu::with_items("Puzzles", items);
+        self.menu.select(selected);
+        self.packs = packs;
+        self.errors = errors;
+    }
+}
+
+impl Scene for PackSelect {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if let KeyCode::Escape = keycode {
+            return Transition::Pop;
+        }
+        match self.menu.key_down(keycode) {
+            Some(i) if i < self.packs.len() => {
+                if self.packs[i].puzzles.is_empty() {
+                    return Transition::None;
+                }
+                Transition::Push(Box::new(PuzzleSelect::new(ctx, self.packs[i].clone())))
+            },
+            Some(_) => Transition::Push(Box::new(Editor::new())),
+            None => Transition::None,
+        }
+    }
+
+    fn resume(&mut self, ctx: &mut Context) {
+        self.refresh(ctx);
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)?;
+        scene::draw_errors(ctx, &self.errors)
+    }
+}
+
+// The puzzles in a pack, ticked once solved. E opens one in the editor.
+pub struct PuzzleSelect {
+    menu: Menu,
+    pack: Pack,
+    error: Option<String>,
+}
+
+impl PuzzleSelect {
+    pub fn new(ctx: &mut Context, pack: Pack) -> PuzzleSelect {
+        let mut select = PuzzleSelect { menu: Menu::new(&pack.name, &[]), pack, error: None };
+        select.refresh(ctx);
+        select
+    }
+
+    fn refresh(&mut self, ctx: &mut Context) {
+        let progress = Progress::load(ctx);
+        let pack = &self.pack;
+        let items = pack.puzzles.iter()
+            .map(|puzzle| {
+                let mark = if progress.is_solved(pack, puzzle) { "[x]" } else { "[ ]" };
+                format!("{} {}", mark, puzzle.name)
+            })
+            .collect();
+        let selected = self.menu.selected();
+        self.menu = Menu::with_items(&pack.name, items);
+        self.menu.select(selected);
+    }
+}
+
+impl Scene for PuzzleSelect {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        match keycode {
+            KeyCode::Escape => return Transition::Pop,
+            KeyCode::E => {
+                let puzzle = self.pack.puzzles[self.menu.selected()].clone();
+                return Transition::Push(Box::new(Editor::editing(puzzle)));
+            },
+            _ => (),
+        }
+        match self.menu.key_down(keycode) {
+            Some(i) => menu::open(PuzzlePlay::new(ctx, self.pack.clone(), i), &mut self.error),
+            None => Transition::None,
+        }
+    }
+
+    fn resume(&mut self, ctx: &mut Context) {
+        self.refresh(ctx);
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)?;
+        scene::draw_errors(ctx, self.error.as_slice())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Solved,
+    Failed(&'static str),
+}
+
+// Plays the puzzles of a pack from the one picked, moving on to the next
+// once one is solved
+pub struct PuzzlePlay {
+    practice: Practice,
+    pack: Pack,
+    index: usize,
+    // Value of `game.placed()` when last checked
+    placed: usize,
+    outcome: Option<Outcome>,
+}
+
+impl PuzzlePlay {
+    pub fn new(ctx: &mut Context, pack: Pack, index: usize) -> GameResult<PuzzlePlay> {
+        // Only the puzzle's own pieces are dealt
+        let rules = Ruleset { randomizer: Randomizer::None, ..Ruleset::default() };
+        let game = Game::with_rules(game::STANDARD_WIDTH, game::STANDARD_HEIGHT, PieceSet::standard(), rules)?;
+        let practice = Practice::new(ctx, game, Tets::default(), None, Vec::new())?;
+        let mut play = PuzzlePlay { practice, pack, index, placed: 0, outcome: None };
+        play.start();
+        Ok(play)
+    }
+
+    fn puzzle(&self) -> &Puzzle {
+        &self.pack.puzzles[self.index]
+    }
+
+    fn start(&mut self) {
+        let puzzle = self.puzzle();
+        let (start, queue) = (puzzle.start.clone(), puzzle.queue.clone());
+        self.practice.set_setup(start, None, queue);
+        self.placed = self.practice.game().placed();
+        self.outcome = None;
+    }
+
+    // Ends the puzzle once it's solved or failed, remembering a solve
+    fn check(&mut self, ctx: &mut Context) {
+        self.placed = self.practice.game().placed();
+        let outcome = match self.puzzle().outcome(self.practice.game()) {
+            Some(outcome) => outcome,
+            None => return,
+        };
+        if let Outcome::Solved = outcome {
+            if let Err(err) = Progress::solve(ctx, &self.pack, self.puzzle()) {
+                self.practice.notify(format!("Couldn't save progress: {}", err));
+            }
+        }
+        self.practice.game_mut().finish();
+        self.outcome = Some(outcome);
+    }
+}
+
+impl Scene for PuzzlePlay {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<Transition> {
+        if self.outcome.is_some() {
+            return Ok(Transition::None);
+        }
+        self.practice.update(ctx)?;
+        let game = self.practice.game();
+        if game.placed() != self.placed || game.is_over() {
+            self.check(ctx);
+        }
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, repeat: bool) -> Transition {
+        match keycode {
+            KeyCode::Escape => return Transition::Pop,
+            KeyCode::Return if self.outcome.is_some() => {
+                if let Some(Outcome::Solved) = self.outcome {
+                    if self.index + 1 < self.pack.puzzles.len() {
+                        self.index += 1;
+                    }
+                }
+                self.start();
+                return Transition::None;
+            },
+            _ => (),
+        }
+        if self.practice.person().keys().control(keycode) == Some(Control::Restart) {
+            self.start();
+        } else if self.outcome.is_none() {
+            self.practice.key_down(ctx, keycode, repeat);
+        }
+        Transition::None
+    }
+
+    fn key_up(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        self.practice.key_up(keycode);
+    }
+
+    fn button_down(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) -> Transition {
+        if let Button::Mode = button {
+            return Transition::Pop;
+        }
+        if self.practice.person().buttons().control(button) == Some(Control::Restart) {
+            self.start();
+        } else if self.outcome.is_none() {
+            self.practice.button_down(button);
+        }
+        Transition::None
+    }
+
+    fn button_up(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) {
+        self.practice.button_up(button);
+    }
+
+    fn axis(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        self.practice.axis(axis, value);
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.practice.draw(ctx)?;
+
+        let game = self.practice.game();
+        let puzzle = self.puzzle();
+        let left = puzzle.limit().saturating_sub(game.placed());
+        let lines = [
+            format!("{}/{}", self.index + 1, self.pack.puzzles.len()),
+            puzzle.name.clone(),
+            puzzle.goal.describe(),
+            format!("Pieces left: {}", left),
+        ];
+        let x = game.sidebar_x() + 10.0;
+        for (i, line) in lines.iter().enumerate() {
+            let mut text = skin::text(line.as_str(), graphics::DEFAULT_FONT_SCALE);
+            text.set_bounds(Point2f32::new(game::SIDEBAR_WIDTH * game::TILE_SIZE - 15.0, 40.0), graphics::Align::Left);
+            graphics::draw(ctx, &text, (Point2f32::new(x, 150.0 + 40.0 * i as f32), skin::text_color()))?;
+        }
+
+        let (message, color, next) = match &self.outcome {
+            Some(Outcome::Solved) => ("Solved!", Color::from_rgb(80, 220, 120), "Enter: next puzzle"),
+            Some(Outcome::Failed(reason)) => (*reason, Color::from_rgb(230, 80, 80), "Enter: try again"),
+            None => return Ok(()),
+        };
+        let center = game.board_center();
+        let title = skin::text(message, 32.0);
+        let offset = title.width(ctx) as f32 / 2.0;
+        graphics::draw(ctx, &title, (Point2f32::new(center - offset, 90.0), color))?;
+        let prompt = skin::text(next, graphics::DEFAULT_FONT_SCALE);
+        let offset = prompt.width(ctx) as f32 / 2.0;
+        graphics::draw(ctx, &prompt, (Point2f32::new(center - offset, 130.0), skin::text_color()))
+    }
+
+    fn window_size(&self) -> (f32, f32) {
+        self.practice.window_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Action;
+    use crate::tet::{Rot, Tet};
+
+    fn puzzle(data: &str) -> Puzzle {
+        toml::from_str(data).unwrap()
+    }
+
+    // A game set up the way `PuzzlePlay` starts one
+    fn play(puzzle: &Puzzle) -> Game {
+        let rules = Ruleset { randomizer: Randomizer::None, ..Ruleset::default() };
+        let mut game = Game::with_rules(game::STANDARD_WIDTH, game::STANDARD_HEIGHT, PieceSet::standard(), rules)
+            .unwrap();
+        game.set_tets(puzzle.start.clone());
+        game.set_queue(&puzzle.queue[1..]);
+        game.spawn(puzzle.queue[0]);
+        game
+    }
+
+    // The slot from the movegen tests, with the T turned in from above
+    const TST: &str = r#"
+        name = "TST"
+        queue = ["T", "I"]
+        board = """
+        ##........
+        ..........
+        #.########
+        #..#######
+        #.########
+        """
+    "#;
+
+    fn t_spin_into_slot(game: &mut Game) {
+        let above = Tet::from_center(TetType::T, Rot::Two, 1, 3, game::STANDARD_HEIGHT);
+        game.show(game.tets().clone(), Some(above));
+        game.press(Action::RotateCcw);
+        game.press(Action::HardDrop);
+    }
+
+    #[test]
+    fn t_spin_triple() {
+        let triple = puzzle(&format!("{}goal = {{ t_spin = 3 }}", TST));
+        let mut game = play(&triple);
+        assert_eq!(triple.outcome(&game), None);
+        t_spin_into_slot(&mut game);
+        assert!(game.last_lock().is_some_and(|lock| lock.t_spin));
+        assert_eq!(game.lines(), 3);
+        assert_eq!(triple.outcome(&game), Some(Outcome::Solved));
+
+        // A T-spin clearing other than the lines asked for doesn't count
+        let double = puzzle(&format!("{}goal = {{ t_spin = 2 }}", TST));
+        let mut game = play(&double);
+        t_spin_into_slot(&mut game);
+        assert_eq!(double.outcome(&game), None);
+    }
+
+    #[test]
+    fn no_holes() {
+        let flat = puzzle(r#"
+            name = "Flat"
+            queue = ["O"]
+            goal = "no_holes"
+        "#);
+        let mut game = play(&flat);
+        assert!(!has_holes(game.tets()));
+        game.press(Action::HardDrop);
+        assert_eq!(flat.outcome(&game), Some(Outcome::Solved));
+
+        // The O lands on the step, covering the cells beside it
+        let step = puzzle(r#"
+            name = "Step"
+            queue = ["O"]
+            board = "....#....."
+            goal = "no_holes"
+        "#);
+        let mut game = play(&step);
+        game.press(Action::HardDrop);
+        assert!(has_holes(game.tets()));
+        assert_eq!(step.outcome(&game), Some(Outcome::Failed("Out of pieces")));
+    }
+
+    #[test]
+    fn clear_garbage() {
+        let data = r#"
+            name = "Dig"
+            queue = ["I"]
+            board = "GGG....GGG"
+            goal = "clear_garbage"
+        "#;
+        let dig = puzzle(data);
+        assert!(has_garbage(&dig.start));
+        let mut game = play(&dig);
+        game.press(Action::HardDrop);
+        assert!(!has_garbage(game.tets()));
+        assert_eq!(dig.outcome(&game), Some(Outcome::Solved));
+
+        let nothing = Puzzle { start: Tets::default(), ..dig };
+        assert!(nothing.check().is_err());
+    }
+
+    #[test]
+    fn move_limits() {
+        let limited = puzzle(r#"
+            name = "Limited"
+            queue = ["O", "O", "I"]
+            moves = 1
+            goal = { lines = 1 }
+        "#);
+        assert_eq!(limited.limit(), 1);
+        let mut game = play(&limited);
+        game.press(Action::HardDrop);
+        assert_eq!(limited.outcome(&game), Some(Outcome::Failed("Out of moves")));
+
+        // More moves than pieces is just the pieces
+        let loose = Puzzle { moves: Some(5), ..limited.clone() };
+        assert_eq!(loose.limit(), 3);
+        assert!(Puzzle { moves: Some(0), ..limited }.check().is_err());
+    }
+
+    #[test]
+    fn bundled_pack_loads() {
+        let pack = Pack::parse("basics.toml", include_str!("../res/puzzles/basics.toml")).unwrap();
+        assert!(!pack.puzzles.is_empty());
+    }
+
+    #[test]
+    fn goals_written_back() {
+        let goals = [Goal::Lines(4), Goal::TSpin(3), Goal::NoHoles, Goal::ClearGarbage];
+        for &goal in goals.iter() {
+            let saved = SavedPuzzle {
+                name: "Goal".to_string(),
+                queue: vec![TetType::T],
+                moves: None,
+                board: "G.........\n".to_string(),
+                goal,
+            };
+            let read = puzzle(&toml::to_string(&saved).unwrap());
+            assert_eq!(read.goal, goal);
+            assert_eq!(board_picture(&read.start), saved.board);
+        }
+        assert!(toml::from_str::<Puzzle>("name = \"T\"\nqueue = [\"O\"]\ngoal = { t_spin = 2 }").is_err());
+    }
+}
@@ -0,0 +1,240 @@
+// High score tables and replays of recent games, and the screens showing
+// them.
+
+use ggez::{Context, GameResult};
+use ggez::event::{KeyCode, KeyMods};
+use ggez::graphics;
+
+use serde::{Deserialize, Serialize};
+
+use crate::fumen::{self, Page};
+use crate::game::{self, Game};
+use crate::save;
+use crate::scene::{Menu, Scene, Transition};
+use crate::scores::{self, HighScores};
+use crate::session::Mode;
+use crate::settings::Settings;
+use crate::skin::{self, Skin};
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+const REPLAYS_PATH: &str = "/replays.json";
+const REPLAYS_VERSION: u64 = 1;
+// Replays kept, newest first
+const MAX_REPLAYS: usize = 20;
+
+#[derive(Serialize, Deserialize)]
+pub struct Replay {
+    pub mode: String,
+    // Seconds since the Unix epoch
+    pub date: u64,
+    pub score: usize,
+    pub lines: usize,
+    // Every piece placed, as a multi-page fumen
+    pub fumen: String,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Replays {
+    replays: Vec<Replay>,
+}
+
+impl Replays {
+    // The stored replays; an unreadable file is reported and replaced
+    pub fn load(ctx: &mut Context) -> Replays {
+        save::load_or_default(ctx, REPLAYS_PATH, REPLAYS_VERSION)
+    }
+
+    pub fn save(&self, ctx: &mut Context) -> GameResult {
+        save::write_versioned(ctx, REPLAYS_PATH, REPLAYS_VERSION, self)
+    }
+
+    // Adds a replay to the stored ones, dropping the oldest if there are too
+    // many
+    pub fn record(ctx: &mut Context, replay: Replay) -> GameResult {
+        let mut replays = Replays::load(ctx);
+        replays.replays.insert(0, replay);
+        replays.replays.truncate(MAX_REPLAYS);
+        replays.save(ctx)
+    }
+}
+
+// The high score table of each mode, flipped through with left and right
+pub struct RecordsScreen {
+    scores: HighScores,
+    mode: usize,
+}
+
+impl RecordsScreen {
+    pub fn new(ctx: &mut Context) -> RecordsScreen {
+        RecordsScreen { scores: HighScores::load(ctx), mode: 0 }
+    }
+}
+
+impl Scene for RecordsScreen {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        let modes = Mode::ALL.len();
+        match keycode {
+            KeyCode::Escape | KeyCode::Return => return Transition::Pop,
+            KeyCode::Left => self.mode = (self.mode + modes - 1) % modes,
+            KeyCode::Right => self.mode = (self.mode + 1) % modes,
+            _ => (),
+        }
+        Transition::None
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let mode = Mode::ALL[self.mode];
+        let title = format!("< {} >", mode.title());
+        scores::draw_table(ctx, &title, self.scores.table(mode.name()), None)
+    }
+}
+
+// Recent games, to pick one to watch
+pub struct ReplaysScreen {
+    replays: Replays,
+    menu: Option<Menu>,
+}
+
+impl ReplaysScreen {
+    pub fn new(ctx: &mut Context) -> ReplaysScreen {
+        let replays = Replays::load(ctx);
+        let items: Vec<String> = replays.replays.iter()
+            .map(|replay| format!(
+                "{} {} - {}",
+                scores::format_date(replay.date),
+                replay.mode,
+                replay.score,
+            ))
+            .collect();
+        let menu = if items.is_empty() { None } else { Some(Menu::with_items("Replays", items)) };
+        ReplaysScreen { replays, menu }
+    }
+}
+
+impl Scene for ReplaysScreen {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if let KeyCode::Escape = keycode {
+            return Transition::Pop;
+        }
+        let chosen = match self.menu.as_mut().and_then(|menu| menu.key_down(keycode)) {
+            Some(chosen) => chosen,
+            None => return Transition::None,
+        };
+        match ReplayViewer::new(ctx, &self.replays.replays[chosen].fumen) {
+            Ok(viewer) => Transition::Push(Box::new(viewer)),
+            Err(err) => {
+                eprintln!("{}", err);
+                Transition::None
+            },
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        match &self.menu {
+            Some(menu) => menu.draw(ctx),
+            None => {
+                let empty = skin::text("No replays yet", graphics::DEFAULT_FONT_SCALE);
+                let offset = empty.width(ctx) as f32 / 2.0;
+                graphics::draw(
+                    ctx,
+                    &empty,
+                    (Point2f32::new(game::WINDOW_WIDTH / 2.0 - offset, 150.0), skin::text_color()),
+                )
+            },
+        }
+    }
+}
+
+// Steps through a replay a piece at a time
+pub struct ReplayViewer {
+    game: Game,
+    skin: Skin,
+    fumen: String,
+    pages: Vec<Page>,
+    page: usize,
+    // How the last export went
+    message: Option<String>,
+}
+
+impl ReplayViewer {
+    pub fn new(ctx: &mut Context, data: &str) -> GameResult<ReplayViewer> {
+        let game = Game::new()?;
+        let name = Settings::load(ctx).skin;
+        let skin = Skin::load(ctx, &name, game.pieces())?;
+        let mut viewer = ReplayViewer {
+            game,
+            skin,
+            fumen: data.to_string(),
+            pages: fumen::decode(data)?,
+            page: 0,
+            message: None,
+        };
+        viewer.show_page();
+        Ok(viewer)
+    }
+
+    fn show_page(&mut self) {
+        let page = &self.pages[self.page];
+        let upcoming: Vec<_> = self.pages[self.page + 1..].iter()
+            .filter_map(|page| page.piece.as_ref().map(|tet| tet.tet_type))
+            .collect();
+        self.game.show(page.tets.clone(), page.piece.clone());
+        self.game.set_queue(&upcoming);
+    }
+}
+
+impl Scene for ReplayViewer {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        match keycode {
+            KeyCode::Escape => return Transition::Pop,
+            KeyCode::Left if self.page > 0 => self.page -= 1,
+            KeyCode::Right if self.page + 1 < self.pages.len() => self.page += 1,
+            KeyCode::Home => self.page = 0,
+            KeyCode::End => self.page = self.pages.len() - 1,
+            KeyCode::E => {
+                self.message = Some(match game::export_fumen(ctx, &self.fumen) {
+                    Ok(()) => "Exported to fumen.txt".to_string(),
+                    Err(err) => format!("Couldn't save: {}", err),
+                });
+            },
+            _ => (),
+        }
+        self.show_page();
+        Transition::None
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.game.draw_game(ctx, &self.skin)?;
+        let x = self.game.sidebar_x() + 10.0;
+        let lines = [
+            format!("Piece {}/{}", self.page + 1, self.pages.len()),
+            "Left/Right: step".to_string(),
+            "E: export".to_string(),
+        ];
+        for (i, line) in lines.iter().chain(&self.message).enumerate() {
+            graphics::draw(
+                ctx,
+                &skin::text(line.as_str(), graphics::DEFAULT_FONT_SCALE),
+                (Point2f32::new(x, 150.0 + 25.0 * i as f32), skin::text_color()),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn window_size(&self) -> (f32, f32) {
+        self.game.window_size()
+    }
+}
@@ -0,0 +1,435 @@
+// How a game plays: gravity, timings, scoring, levels, how pieces are dealt
+// and how they turn. The guideline rules are built in; other rulesets are
+// read from TOML files, any part left out keeping its guideline value.
+
+use std::io::Read;
+use std::time::Duration;
+
+use ggez::{Context, GameError, GameResult};
+use ggez::filesystem;
+
+use rand::Rng;
+
+use serde::{Deserialize, Serialize};
+
+use crate::pieces::PieceSet;
+use crate::tet::TetType;
+
+// Shipped rulesets, in the resource directory
+pub const NES_PATH: &str = "/rules/nes.toml";
+pub const TGM_PATH: &str = "/rules/tgm.toml";
+pub const ZEN_PATH: &str = "/rules/zen.toml";
+// The player's own rules, in the user data directory
+pub const HOUSE_PATH: &str = "/rules.toml";
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Ruleset {
+    pub name: String,
+    // Times are counted in frames at this rate, if given, else in
+    // milliseconds
+    pub frame_rate: Option<f64>,
+    // Steps of the gravity curve, each lasting until the next
+    pub gravity: Vec<Gravity>,
+    pub levels: Levels,
+    pub timing: Timing,
+    pub scoring: Scoring,
+    pub randomizer: Randomizer,
+    pub rotation: Rotation,
+    pub top_out: TopOut,
+    pub allow: Allow,
+}
+
+// How long a piece takes to fall a row, from a level on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Gravity {
+    pub level: usize,
+    pub interval: u64,
+}
+
+// The level goes up every so many lines, from the first to the last
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Levels {
+    pub first: usize,
+    pub lines: usize,
+    // The level stops going up here, if anywhere
+    #[serde(default)]
+    pub last: Option<usize>,
+    // The player can start at any level up to this one
+    pub highest_start: Option<usize>,
+    pub transition: Transition,
+}
+
+// When the level first goes up, for games started past the first level
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transition {
+    // After the usual number of lines
+    #[default]
+    Steady,
+    // As on the NES: after ten levels' worth of lines, or fewer (but at
+    // least five levels' worth) for the highest starts
+    Nes,
+}
+
+// Auto-shift delay and repeat interval are the player's to
+// set, unless the ruleset fixes them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Timing {
+    pub lock_delay: u64,
+    pub soft_drop: u64,
+    pub spawn_delay: u64,
+    pub das: Option<u64>,
+    pub arr: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Scoring {
+    // Points for clearing one line, two, and so on; clearing more lines than
+    // are listed scores the last
+    pub clears: Vec<usize>,
+    pub multiplier: Multiplier,
+    // Points for each row dropped
+    pub soft_drop: usize,
+    pub hard_drop: usize,
+}
+
+// What line clear points are multiplied by
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Multiplier {
+    #[default]
+    Level,
+    LevelPlusOne,
+    None,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Randomizer {
+    // One of each piece, shuffled, before any repeats
+    #[default]
+    Bag,
+    // Any piece, every time
+    Random,
+    // Any piece, redrawn up to `rolls` times in all while it's one of the
+    // last `size` dealt
+    History { size: usize, rolls: usize },
+    // As on the NES: a roll with one extra outcome, redrawn once (without
+    // the extra) if it lands on that or repeats the last piece
+    Nes,
+    // Nothing: only the pieces a mode puts in the queue are played, as in
+    // puzzles
+    None,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rotation {
+    // Each piece's own kicks, SRS for the tetrominoes
+    #[default]
+    Srs,
+    // Only turns that fit in place
+    NoKicks,
+    // In place, else one column right, else one column left
+    Simple,
+}
+
+// What happens when a piece spawns overlapping the stack
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TopOut {
+    // The game is over
+    #[default]
+    End,
+    // The board is cleared and play goes on
+    Clear,
+}
+
+// Actions a ruleset can give or take away
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Allow {
+    pub hold: bool,
+    pub hard_drop: bool,
+    pub rotate_180: bool,
+    // Showing where the piece will land
+    pub ghost: bool,
+    // Taking back placements, as often as wanted
+    pub undo: bool,
+}
+
+impl Default for Ruleset {
+    fn default() -> Ruleset {
+        Ruleset {
+            name: "Guideline".to_string(),
+            frame_rate: None,
+            gravity: (1..=20).map(|level: i32| Gravity {
+                level: level as usize,
+                interval: ((0.8 - ((level - 1) as f32 * 0.007)).powi(level - 1) * 1000.0) as u64,
+            }).collect(),
+            levels: Levels { last: Some(20), ..Levels::default() },
+            timing: Timing::default(),
+            scoring: Scoring::default(),
+            randomizer: Randomizer::default(),
+            rotation: Rotation::default(),
+            top_out: TopOut::default(),
+            allow: Allow::default(),
+        }
+    }
+}
+
+impl Default for Levels {
+    fn default() -> Levels {
+        Levels { first: 1, lines: 10, last: None, highest_start: None, transition: Transition::default() }
+    }
+}
+
+impl Default for Timing {
+    fn default() -> Timing {
+        Timing { lock_delay: 500, soft_drop: 100, spawn_delay: 0, das: None, arr: None }
+    }
+}
+
+impl Default for Scoring {
+    fn default() -> Scoring {
+        Scoring {
+            clears: vec![100, 300, 500, 800],
+            multiplier: Multiplier::default(),
+            soft_drop: 1,
+            hard_drop: 2,
+        }
+    }
+}
+
+impl Default for Allow {
+    fn default() -> Allow {
+        Allow { hold: true, hard_drop: true, rotate_180: true, ghost: true, undo: false }
+    }
+}
+
+impl Ruleset {
+    pub fn load(ctx: &mut Context, path: &str) -> GameResult<Ruleset> {
+        let mut data = String::new();
+        filesystem::open(ctx, path)?.read_to_string(&mut data)?;
+        let invalid = |reason: String| GameError::ConfigError(format!("{}: {}", path, reason));
+        let rules: Ruleset = toml::from_str(&data).map_err(|err| invalid(err.to_string()))?;
+        rules.check().map_err(invalid)?;
+        Ok(rules)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let fail = |reason: &str| Err(reason.to_string());
+        if self.name.trim().is_empty() {
+            return fail("name is empty");
+        }
+        if let Some(rate) = self.frame_rate {
+            if !(rate.is_finite() && rate > 0.0) {
+                return fail("frame_rate must be above 0");
+            }
+        }
+        let Levels { first, lines, last, highest_start, .. } = self.levels;
+        if lines == 0 {
+            return fail("levels.lines must be at least 1");
+        }
+        if last.is_some_and(|last| first > last) {
+            return fail("levels.first can't be past levels.last");
+        }
+        if highest_start.is_some_and(|start| start < first || last.is_some_and(|last| start > last)) {
+            return fail("levels.highest_start must be from levels.first to levels.last");
+        }
+        match self.gravity.first() {
+            None => return fail("gravity has no steps"),
+            Some(step) if step.level > first => {
+                return Err(format!("gravity starts at level {}, after the first level ({})", step.level, first));
+            },
+            _ => (),
+        }
+        if self.gravity.windows(2).any(|steps| steps[0].level >= steps[1].level) {
+            return fail("gravity steps must be in order of level, one per level");
+        }
+        if self.duration(self.timing.soft_drop) == Duration::from_secs(0) {
+            return fail("timing.soft_drop must be above 0");
+        }
+        if self.scoring.clears.is_empty() {
+            return fail("scoring.clears is empty");
+        }
+        if let Randomizer::History { size, rolls } = self.randomizer {
+            if size == 0 || rolls == 0 {
+                return fail("randomizer.history needs a size and rolls of at least 1");
+            }
+        }
+        Ok(())
+    }
+
+    // A time as the ruleset counts it
+    fn duration(&self, amount: u64) -> Duration {
+        match self.frame_rate {
+            Some(rate) => Duration::from_secs_f64(amount as f64 / rate),
+            None => Duration::from_millis(amount),
+        }
+    }
+
+    // The level after clearing lines, in a game started at `start`
+    pub fn level(&self, lines: usize, start: usize) -> usize {
+        let per = self.levels.lines;
+        let level = match self.levels.transition {
+            Transition::Steady => start + lines / per,
+            Transition::Nes => {
+                let from_first = start.saturating_sub(self.levels.first) * per;
+                let first_up = std::cmp::min(
+                    from_first + per,
+                    std::cmp::max(10 * per, from_first.saturating_sub(5 * per)),
+                );
+                if lines < first_up { start } else { start + 1 + (lines - first_up) / per }
+            },
+        };
+        self.levels.last.map_or(level, |last| std::cmp::min(level, last))
+    }
+
+    // How long a piece takes to fall a row at a level
+    pub fn gravity(&self, level: usize) -> Duration {
+        let step = self.gravity.iter()
+            .take_while(|step| step.level <= level)
+            .last()
+            .unwrap_or(&self.gravity[0]);
+        self.duration(step.interval)
+    }
+
+    // Points for clearing lines at a level
+    pub fn clear_score(&self, lines: usize, level: usize) -> usize {
+        if lines == 0 {
+            return 0;
+        }
+        let clears = &self.scoring.clears;
+        let points = clears.get(lines - 1).or_else(|| clears.last()).copied().unwrap_or(0);
+        points * match self.scoring.multiplier {
+            Multiplier::Level => level,
+            Multiplier::LevelPlusOne => level + 1,
+            Multiplier::None => 1,
+        }
+    }
+
+    pub fn lock_delay(&self) -> Duration {
+        self.duration(self.timing.lock_delay)
+    }
+
+    pub fn soft_drop(&self) -> Duration {
+        self.duration(self.timing.soft_drop)
+    }
+
+    pub fn spawn_delay(&self) -> Duration {
+        self.duration(self.timing.spawn_delay)
+    }
+
+    // Auto-shift delay and repeat interval, if the ruleset fixes them
+    pub fn das(&self) -> Option<Duration> {
+        self.timing.das.map(|das| self.duration(das))
+    }
+
+    pub fn arr(&self) -> Option<Duration> {
+        self.timing.arr.map(|arr| self.duration(arr))
+    }
+
+    // The next pieces to deal. `recent` holds what the randomizer has dealt
+    // lately, oldest first, for randomizers that avoid repeats.
+    pub fn batch<R: Rng + ?Sized>(&self, pieces: &PieceSet, recent: &mut Vec<TetType>, rng: &mut R) -> Vec<TetType> {
+        let draw = |rng: &mut R| pieces.pieces()[rng.gen_range(0, pieces.pieces().len())];
+        match self.randomizer {
+            Randomizer::Bag => pieces.batch(rng),
+            Randomizer::Random => vec![draw(rng)],
+            Randomizer::History { size, rolls } => {
+                let mut piece = draw(rng);
+                for _ in 1..rolls {
+                    if !recent.contains(&piece) {
+                        break;
+                    }
+                    piece = draw(rng);
+                }
+                recent.push(piece);
+                if recent.len() > size {
+                    recent.remove(0);
+                }
+                vec![piece]
+            },
+            Randomizer::Nes => {
+                let count = pieces.pieces().len();
+                let roll = rng.gen_range(0, count + 1);
+                let piece = match pieces.pieces().get(roll) {
+                    Some(piece) if recent.last() != Some(piece) => *piece,
+                    _ => draw(rng),
+                };
+                recent.clear();
+                recent.push(piece);
+                vec![piece]
+            },
+            Randomizer::None => Vec::new(),
+        }
+    }
+}
+
+impl Rotation {
+    // Offsets tried in order when a turn is blocked, or None for the piece's
+    // own
+    pub fn kicks(self) -> Option<&'static [[i8; 2]]> {
+        match self {
+            Rotation::Srs => None,
+            Rotation::NoKicks => Some(&[[0, 0]]),
+            Rotation::Simple => Some(&[[0, 0], [1, 0], [-1, 0]]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(data: &str) -> Ruleset {
+        let rules: Ruleset = toml::from_str(data).unwrap();
+        rules.check().unwrap();
+        rules
+    }
+
+    #[test]
+    fn guideline_file_matches_built_in_rules() {
+        assert_eq!(parse(include_str!("../res/rules/guideline.toml")), Ruleset::default());
+    }
+
+    #[test]
+    fn shipped_rulesets_load() {
+        parse(include_str!("../res/rules/nes.toml"));
+        parse(include_str!("../res/rules/tgm.toml"));
+        parse(include_str!("../res/rules/zen.toml"));
+    }
+
+    // The first level up comes later the higher the start, as on the NES
+    #[test]
+    fn nes_first_level_up() {
+        let rules = parse(include_str!("../res/rules/nes.toml"));
+        for &(start, lines) in [(0, 10), (10, 100), (19, 140)].iter() {
+            assert_eq!(rules.level(lines - 1, start), start);
+            assert_eq!(rules.level(lines, start), start + 1);
+            assert_eq!(rules.level(lines + 10, start), start + 2);
+        }
+
+        // A start below the first level counts from the first
+        let mut rules = parse(include_str!("../res/rules/guideline.toml"));
+        rules.levels.transition = Transition::Nes;
+        assert_eq!(rules.level(0, 0), 0);
+        assert_eq!(rules.level(rules.levels.lines, 0), 1);
+    }
+
+    // Only the gravity stops speeding up at 29; the level and scores go on
+    #[test]
+    fn nes_levels_past_the_gravity_table() {
+        let rules = parse(include_str!("../res/rules/nes.toml"));
+        let level = rules.level(300, 19);
+        assert_eq!(level, 36);
+        assert_eq!(rules.gravity(level), rules.gravity(29));
+        assert!(rules.clear_score(4, level) > rules.clear_score(4, 29));
+    }
+}
@@ -0,0 +1,126 @@
+// Files kept between runs in the user data directory: the game saved on
+// quit, high scores, ... Each is JSON tagged with a version, so files from an
+// incompatible build are turned away rather than misread.
+
+use std::io::{Read, Write};
+
+use ggez::{Context, GameError, GameResult};
+use ggez::filesystem;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::game::{Game, Snapshot};
+
+// Bump when `Snapshot` changes
+const SAVE_VERSION: u64 = 7;
+
+#[derive(Serialize)]
+struct Versioned<'a, T> {
+    version: u64,
+    data: &'a T,
+}
+
+fn rejected(path: &str, reason: String) -> GameError {
+    GameError::ResourceLoadError(format!("{} rejected: {}", path, reason))
+}
+
+// Reads a file written by `write_versioned`. A missing file is `None`.
+pub fn read_versioned<T: DeserializeOwned>(
+    ctx: &mut Context,
+    path: &str,
+    version: u64,
+) -> GameResult<Option<T>> {
+    if !filesystem::exists(ctx, path) {
+        return Ok(None);
+    }
+    let mut data = String::new();
+    filesystem::open(ctx, path)?.read_to_string(&mut data)?;
+
+    // Check the version before the rest, so an old file gets a clear message
+    let mut value: serde_json::Value = serde_json::from_str(&data)
+        .map_err(|err| rejected(path, err.to_string()))?;
+    match value.get("version").and_then(serde_json::Value::as_u64) {
+        Some(found) if found == version => (),
+        Some(found) => return Err(rejected(path, format!("unsupported version {}", found))),
+        None => return Err(rejected(path, "no version".to_string())),
+    }
+    let data = value.get_mut("data").map(serde_json::Value::take).unwrap_or_default();
+    serde_json::from_value(data)
+        .map(Some)
+        .map_err(|err| rejected(path, err.to_string()))
+}
+
+// Like `read_versioned`, for files the game can do without: a missing file
+// is the default, and an unreadable one is reported and replaced by it
+pub fn load_or_default<T: DeserializeOwned + Default>(
+    ctx: &mut Context,
+    path: &str,
+    version: u64,
+) -> T {
+    read_versioned(ctx, path, version)
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            None
+        })
+        .unwrap_or_default()
+}
+
+pub fn write_versioned<T: Serialize>(
+    ctx: &mut Context,
+    path: &str,
+    version: u64,
+    data: &T,
+) -> GameResult {
+    let json = serde_json::to_string(&Versioned { version, data })
+        .map_err(|err| GameError::ResourceLoadError(err.to_string()))?;
+    filesystem::create(ctx, path)?.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+// The names of the directories in `root`, sorted, from the game's resources
+// and the user data directory alike
+pub fn list_dirs(ctx: &mut Context, root: &str) -> Vec<String> {
+    let mut dirs: Vec<String> = filesystem::read_dir(ctx, root)
+        .map(|entries| entries
+            .filter(|path| filesystem::is_dir(ctx, path))
+            .filter_map(|path| path.file_name().map(|name| name.to_string_lossy().into_owned()))
+            .collect())
+        .unwrap_or_default();
+    dirs.sort();
+    dirs.dedup();
+    dirs
+}
+
+// Each mode keeps its own saved game
+fn save_path(mode: &str) -> String {
+    format!("/save-{}.json", mode)
+}
+
+// The saved game for a mode, if there is one that fits how `game` is set up
+pub fn load(ctx: &mut Context, mode: &str, game: &Game) -> GameResult<Option<Snapshot>> {
+    let path = save_path(mode);
+    let snapshot: Option<Snapshot> = read_versioned(ctx, &path, SAVE_VERSION)?;
+    if let Some(snapshot) = &snapshot {
+        let tets = game.tets();
+        snapshot.check(tets.width(), tets.height(), game.pieces(), game.rules())
+            .map_err(|reason| rejected(&path, reason))?;
+    }
+    Ok(snapshot)
+}
+
+// Saves the game, or removes the save if the game is over
+pub fn store(ctx: &mut Context, mode: &str, game: &Game) -> GameResult {
+    match game.snapshot() {
+        Some(snapshot) => write_versioned(ctx, &save_path(mode), SAVE_VERSION, &snapshot),
+        None => remove(ctx, mode),
+    }
+}
+
+pub fn remove(ctx: &mut Context, mode: &str) -> GameResult {
+    let path = save_path(mode);
+    if filesystem::exists(ctx, &path) {
+        filesystem::delete(ctx, &path)?;
+    }
+    Ok(())
+}
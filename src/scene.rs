@@ -0,0 +1,338 @@
+// Screens stacked on top of each other: the title screen, menus, games, ...
+// Only the top one runs; leaving it goes back to the one underneath.
+
+use ggez::{Context, GameResult};
+use ggez::conf::WindowMode;
+use ggez::event::{self, Axis, Button, EventHandler, GamepadId, KeyCode, KeyMods, MouseButton};
+use ggez::graphics::{self, Color, Rect};
+
+use crate::game;
+use crate::settings::Settings;
+use crate::skin;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+// What to do with the stack after an event
+pub enum Transition {
+    None,
+    // Open a scene on top of this one
+    Push(Box<dyn Scene>),
+    // Leave this scene
+    Pop,
+    // Leave this scene for another
+    Replace(Box<dyn Scene>),
+    Quit,
+}
+
+pub trait Scene {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<Transition>;
+
+    // Draws everything but the background
+    fn draw(&mut self, ctx: &mut Context) -> GameResult;
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, keymod: KeyMods, repeat: bool) -> Transition;
+
+    fn key_up(&mut self, _ctx: &mut Context, _keycode: KeyCode, _keymod: KeyMods) {}
+
+    fn text_input(&mut self, _ctx: &mut Context, _character: char) {}
+
+    // Gamepads work menus like the keyboard unless a scene says otherwise
+    fn button_down(&mut self, ctx: &mut Context, button: Button, _id: GamepadId) -> Transition {
+        match menu_key(button) {
+            Some(keycode) => self.key_down(ctx, keycode, KeyMods::NONE, false),
+            None => Transition::None,
+        }
+    }
+
+    fn button_up(&mut self, _ctx: &mut Context, _button: Button, _id: GamepadId) {}
+
+    fn axis(&mut self, _ctx: &mut Context, _axis: Axis, _value: f32, _id: GamepadId) {}
+
+    // Positions are in window coordinates
+    fn mouse_button_down(&mut self, _ctx: &mut Context, _button: MouseButton, _x: f32, _y: f32) {}
+
+    fn mouse_button_up(&mut self, _ctx: &mut Context, _button: MouseButton, _x: f32, _y: f32) {}
+
+    fn mouse_motion(&mut self, _ctx: &mut Context, _x: f32, _y: f32) {}
+
+    // Called when the scene is left, or the window closes with it open
+    fn leave(&mut self, _ctx: &mut Context) -> GameResult {
+        Ok(())
+    }
+
+    // Called when the scene on top of this one is left
+    fn resume(&mut self, _ctx: &mut Context) {}
+
+    fn window_size(&self) -> (f32, f32) {
+        (game::WINDOW_WIDTH, game::WINDOW_HEIGHT)
+    }
+}
+
+// The key a gamepad button stands for in menus
+pub fn menu_key(button: Button) -> Option<KeyCode> {
+    match button {
+        Button::DPadUp => Some(KeyCode::Up),
+        Button::DPadDown => Some(KeyCode::Down),
+        Button::DPadLeft => Some(KeyCode::Left),
+        Button::DPadRight => Some(KeyCode::Right),
+        Button::South | Button::Start => Some(KeyCode::Return),
+        Button::East | Button::Mode => Some(KeyCode::Escape),
+        _ => None,
+    }
+}
+
+pub struct SceneStack {
+    scenes: Vec<Box<dyn Scene>>,
+    // The window colour of the chosen skin
+    background: Color,
+}
+
+impl SceneStack {
+    pub fn new(ctx: &mut Context, scene: Box<dyn Scene>) -> GameResult<SceneStack> {
+        let stack = SceneStack { scenes: vec![scene], background: Self::background(ctx) };
+        stack.fit_window(ctx)?;
+        Ok(stack)
+    }
+
+    // Resizes the window for the top scene
+    fn fit_window(&self, ctx: &mut Context) -> GameResult {
+        let (width, height) = match self.scenes.last() {
+            Some(scene) => scene.window_size(),
+            None => return Ok(()),
+        };
+        let current = graphics::screen_coordinates(ctx);
+        if current.w != width || current.h != height {
+            graphics::set_mode(ctx, WindowMode::default().dimensions(width, height))?;
+            graphics::set_screen_coordinates(ctx, Rect::new(0.0, 0.0, width, height))?;
+        }
+        Ok(())
+    }
+
+    // That of the skin chosen in the settings, which may have changed. The
+    // text of every screen follows the skin too.
+    fn background(ctx: &mut Context) -> Color {
+        let skin = Settings::load(ctx).skin;
+        skin::choose(ctx, &skin)
+    }
+
+    fn pop(&mut self, ctx: &mut Context) -> GameResult {
+        if let Some(mut scene) = self.scenes.pop() {
+            scene.leave(ctx)?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, ctx: &mut Context, transition: Transition) -> GameResult {
+        match transition {
+            Transition::None => return Ok(()),
+            Transition::Push(scene) => self.scenes.push(scene),
+            Transition::Pop => {
+                self.pop(ctx)?;
+                if let Some(scene) = self.scenes.last_mut() {
+                    scene.resume(ctx);
+                }
+            },
+            Transition::Replace(scene) => {
+                self.pop(ctx)?;
+                self.scenes.push(scene);
+            },
+            Transition::Quit => self.leave_all(ctx)?,
+        }
+        if self.scenes.is_empty() {
+            event::quit(ctx);
+            return Ok(());
+        }
+        self.background = Self::background(ctx);
+        self.fit_window(ctx)
+    }
+
+    fn leave_all(&mut self, ctx: &mut Context) -> GameResult {
+        while !self.scenes.is_empty() {
+            self.pop(ctx)?;
+        }
+        Ok(())
+    }
+
+    fn report(result: GameResult) {
+        if let Err(err) = result {
+            eprintln!("{}", err);
+        }
+    }
+}
+
+impl EventHandler for SceneStack {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => scene.update(ctx)?,
+            None => return Ok(()),
+        };
+        self.apply(ctx, transition)
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymod: KeyMods, repeat: bool) {
+        if let Some(scene) = self.scenes.last_mut() {
+            let transition = scene.key_down(ctx, keycode, keymod, repeat);
+            Self::report(self.apply(ctx, transition));
+        }
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymod: KeyMods) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.key_up(ctx, keycode, keymod);
+        }
+    }
+
+    fn text_input_event(&mut self, ctx: &mut Context, character: char) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.text_input(ctx, character);
+        }
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, button: Button, id: GamepadId) {
+        if let Some(scene) = self.scenes.last_mut() {
+            let transition = scene.button_down(ctx, button, id);
+            Self::report(self.apply(ctx, transition));
+        }
+    }
+
+    fn gamepad_button_up_event(&mut self, ctx: &mut Context, button: Button, id: GamepadId) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.button_up(ctx, button, id);
+        }
+    }
+
+    fn gamepad_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: f32, id: GamepadId) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.axis(ctx, axis, value, id);
+        }
+    }
+
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.mouse_button_down(ctx, button, x, y);
+        }
+    }
+
+    fn mouse_button_up_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.mouse_button_up(ctx, button, x, y);
+        }
+    }
+
+    fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.mouse_motion(ctx, x, y);
+        }
+    }
+
+    fn quit_event(&mut self, ctx: &mut Context) -> bool {
+        Self::report(self.leave_all(ctx));
+        false
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        graphics::clear(ctx, self.background);
+        if let Some(scene) = self.scenes.last_mut() {
+            scene.draw(ctx)?;
+        }
+        graphics::present(ctx)?;
+        Ok(())
+    }
+}
+
+// A list of options picked with the arrow keys and Enter
+pub struct Menu {
+    title: String,
+    items: Vec<String>,
+    selected: usize,
+}
+
+impl Menu {
+    pub fn new(title: &str, items: &[&str]) -> Menu {
+        Menu::with_items(title, items.iter().map(|item| item.to_string()).collect())
+    }
+
+    pub fn with_items(title: &str, items: Vec<String>) -> Menu {
+        Menu { title: title.to_string(), items, selected: 0 }
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    // Moves the selection, e.g. back to where it was after a refresh
+    pub fn select(&mut self, index: usize) {
+        self.selected = index.min(self.items.len().saturating_sub(1));
+    }
+
+    pub fn set_item(&mut self, index: usize, item: String) {
+        self.items[index] = item;
+    }
+
+    // Moves the selection, returning the item chosen with Enter
+    pub fn key_down(&mut self, keycode: KeyCode) -> Option<usize> {
+        match keycode {
+            KeyCode::Up => {
+                self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+                None
+            },
+            KeyCode::Down => {
+                self.selected = (self.selected + 1) % self.items.len();
+                None
+            },
+            KeyCode::Return | KeyCode::Space => Some(self.selected),
+            _ => None,
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        let screen = graphics::screen_coordinates(ctx);
+        let width = screen.w;
+        let title = skin::text(self.title.as_str(), 40.0);
+        let offset = title.width(ctx) as f32 / 2.0;
+        graphics::draw(ctx, &title, (Point2f32::new(width / 2.0 - offset, 50.0), skin::text_color()))?;
+
+        // Scroll to keep the selection in view
+        let visible = ((screen.h - 140.0) / 32.0) as usize;
+        let first = (self.selected + 1).saturating_sub(visible);
+        for (i, item) in self.items.iter().enumerate().skip(first).take(visible) {
+            let (label, color) = if i == self.selected {
+                (format!("> {} <", item), Color::from_rgb(240, 220, 80))
+            } else {
+                (item.clone(), skin::text_color())
+            };
+            let text = skin::text(label, 24.0);
+            let offset = text.width(ctx) as f32 / 2.0;
+            graphics::draw(
+                ctx,
+                &text,
+                (Point2f32::new(width / 2.0 - offset, 130.0 + 32.0 * (i - first) as f32), color),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// Messages shown under a menu, e.g. why a pack or a mode couldn't be loaded,
+// on a dark band so a long menu behind them doesn't get in the way
+pub fn draw_errors(ctx: &mut Context, errors: &[String]) -> GameResult {
+    if errors.is_empty() {
+        return Ok(());
+    }
+    let mut text = skin::text(errors.join("\n"), graphics::DEFAULT_FONT_SCALE);
+    let screen = graphics::screen_coordinates(ctx);
+    text.set_bounds(Point2f32::new(screen.w - 20.0, 150.0), graphics::Align::Center);
+    let height = text.height(ctx) as f32;
+    let band = graphics::Mesh::new_rectangle(
+        ctx,
+        graphics::DrawMode::fill(),
+        Rect::new(0.0, screen.h - 95.0, screen.w, height + 10.0),
+        Color::from_rgba(10, 10, 10, 230),
+    )?;
+    graphics::draw(ctx, &band, graphics::DrawParam::default())?;
+    graphics::draw(ctx, &text, (Point2f32::new(10.0, screen.h - 90.0), skin::text_color()))
+}
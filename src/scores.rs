@@ -0,0 +1,207 @@
+// Best games for each mode, kept between runs, and the screen listing them.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ggez::{Context, GameResult};
+use ggez::graphics::{self, Color, DrawParam};
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::Game;
+use crate::save;
+use crate::skin;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+const SCORES_PATH: &str = "/scores.json";
+const SCORES_VERSION: u64 = 1;
+// Entries kept per mode
+pub const TABLE_SIZE: usize = 10;
+pub const MAX_NAME_LEN: usize = 12;
+
+// What makes one game better than another in a mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ranking {
+    // Higher score
+    Score,
+    // Reaching the goal sooner
+    Time,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub score: usize,
+    pub lines: usize,
+    pub level: usize,
+    pub duration: Duration,
+    // Seconds since the Unix epoch
+    pub date: u64,
+    pub seed: u64,
+    // Hints were used
+    pub assisted: bool,
+}
+
+impl Entry {
+    // A finished game, under `name`
+    pub fn of(game: &Game, name: String) -> Entry {
+        Entry {
+            name,
+            score: game.score(),
+            lines: game.lines(),
+            level: game.level(),
+            duration: game.elapsed(),
+            date: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            seed: game.seed(),
+            assisted: game.assisted(),
+        }
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct HighScores {
+    // Best first
+    modes: BTreeMap<String, Vec<Entry>>,
+    // Offered again the next time a name is asked for
+    last_name: String,
+}
+
+impl HighScores {
+    // The stored scores; an unreadable file is reported and replaced
+    pub fn load(ctx: &mut Context) -> HighScores {
+        save::load_or_default(ctx, SCORES_PATH, SCORES_VERSION)
+    }
+
+    pub fn save(&self, ctx: &mut Context) -> GameResult {
+        save::write_versioned(ctx, SCORES_PATH, SCORES_VERSION, self)
+    }
+
+    pub fn table(&self, mode: &str) -> &[Entry] {
+        self.modes.get(mode).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn last_name(&self) -> &str {
+        &self.last_name
+    }
+
+    // Where an entry would go in the table, if it's good enough to make it
+    pub fn rank(&self, mode: &str, entry: &Entry, ranking: Ranking) -> Option<usize> {
+        if ranking == Ranking::Score && entry.score == 0 {
+            return None;
+        }
+        let table = self.table(mode);
+        let rank = table.iter()
+            .position(|other| match ranking {
+                Ranking::Score => entry.score > other.score,
+                Ranking::Time => entry.duration < other.duration,
+            })
+            .unwrap_or(table.len());
+        if rank < TABLE_SIZE { Some(rank) } else { None }
+    }
+
+    // Adds an entry, returning where it went
+    pub fn add(&mut self, mode: &str, entry: Entry, ranking: Ranking) -> Option<usize> {
+        let rank = self.rank(mode, &entry, ranking)?;
+        self.last_name = entry.name.clone();
+        let table = self.modes.entry(mode.to_string()).or_default();
+        table.insert(rank, entry);
+        table.truncate(TABLE_SIZE);
+        Some(rank)
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}.{:02}", secs / 60, secs % 60, duration.subsec_millis() / 10)
+}
+
+// Year-month-day of a Unix timestamp, in UTC
+pub fn format_date(timestamp: u64) -> String {
+    // Days to civil date, from Howard Hinnant's date algorithms
+    let days = (timestamp / 86400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{}-{:02}-{:02}", year, month, day)
+}
+
+// The table for `mode` over the whole window, with the `highlight`ed entry
+// in yellow
+pub fn draw_table(
+    ctx: &mut Context,
+    title: &str,
+    entries: &[Entry],
+    highlight: Option<usize>,
+) -> GameResult {
+    let screen = graphics::screen_coordinates(ctx);
+    let overlay = graphics::Mesh::new_rectangle(
+        ctx,
+        graphics::DrawMode::fill(),
+        [0.0, 0.0, screen.w, screen.h].into(),
+        Color::from_rgba(10, 10, 10, 240),
+    )?;
+    graphics::draw(ctx, &overlay, DrawParam::default())?;
+
+    let title_display = skin::text(format!("{} High Scores", title), 24.0);
+    let offset = title_display.width(ctx) as f32 / 2.0;
+    graphics::draw(
+        ctx,
+        &title_display,
+        (Point2f32::new(screen.w / 2.0 - offset, 15.0), skin::text_color()),
+    )?;
+
+    if entries.is_empty() {
+        let empty = skin::text("No scores yet", graphics::DEFAULT_FONT_SCALE);
+        let offset = empty.width(ctx) as f32 / 2.0;
+        graphics::draw(
+            ctx,
+            &empty,
+            (Point2f32::new(screen.w / 2.0 - offset, 60.0), skin::text_color()),
+        )?;
+    }
+
+    let size = 14.0;
+    let columns = [10.0, 35.0, 150.0, 220.0, 265.0, 300.0, 350.0];
+    let headers = ["#", "Name", "Score", "Lines", "Lvl", "Time", "Date"];
+    for (&x, header) in columns.iter().zip(headers.iter()) {
+        let text = skin::text(*header, size);
+        graphics::draw(ctx, &text, (Point2f32::new(x, 50.0), Color::from_rgb(180, 180, 180)))?;
+    }
+    for (i, entry) in entries.iter().enumerate() {
+        let color = if highlight == Some(i) {
+            Color::from_rgb(240, 220, 80)
+        } else {
+            skin::text_color()
+        };
+        let cells = [
+            format!("{}", i + 1),
+            entry.name.clone(),
+            format!("{}{}", entry.score, if entry.assisted { "*" } else { "" }),
+            format!("{}", entry.lines),
+            format!("{}", entry.level),
+            format_duration(entry.duration),
+            format_date(entry.date),
+        ];
+        for (&x, cell) in columns.iter().zip(cells.iter()) {
+            let text = skin::text(cell.as_str(), size);
+            graphics::draw(ctx, &text, (Point2f32::new(x, 75.0 + 22.0 * i as f32), color))?;
+        }
+    }
+
+    let hint = skin::text("* used hints    Press Enter to close", graphics::DEFAULT_FONT_SCALE);
+    let offset = hint.width(ctx) as f32 / 2.0;
+    graphics::draw(
+        ctx,
+        &hint,
+        (Point2f32::new(screen.w / 2.0 - offset, screen.h - 30.0), skin::text_color()),
+    )?;
+    Ok(())
+}
@@ -0,0 +1,558 @@
+// A game played alone for a score: offers to continue a saved game at the
+// start, ends when the mode's goal is reached, and asks for a name when a game
+// makes the high score table.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ggez::{Context, GameResult};
+use ggez::event::{Axis, Button, GamepadId, KeyCode, KeyMods};
+use ggez::graphics;
+
+use rand::Rng;
+
+use crate::audio::{Music, Sound};
+use crate::fumen;
+use crate::game::{self, Block, Checkpoint, Game, Snapshot, Tets};
+use crate::input::{Control, KeyMap};
+use crate::person::Person;
+use crate::pieces::{self, PieceSet};
+use crate::profile::Profiles;
+use crate::records::{Replay, Replays};
+use crate::rules::{self, Ruleset};
+use crate::save;
+use crate::scene::{Scene, Transition};
+use crate::scores::{self, Entry, HighScores, Ranking};
+use crate::settings::Settings;
+use crate::skin;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+const SPRINT_LINES: usize = 40;
+const ULTRA_TIME: Duration = Duration::from_secs(120);
+const DIG_ROWS: usize = 10;
+// Seconds at the end of a timed mode with a tick each
+const COUNTDOWN: u64 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    // Play until topping out
+    Marathon,
+    // Clear 40 lines as fast as possible
+    Sprint,
+    // Score as much as possible in two minutes
+    Ultra,
+    // Clear away rows of garbage as fast as possible
+    Dig,
+    // Marathon on a board only four columns wide
+    FourWide,
+    // Marathon on a board twice as wide
+    BigBoard,
+    // Marathon on a board twice as tall
+    Tall,
+    // Marathon with five-block pieces
+    Pentomino,
+    // Marathon with three-block pieces
+    Tromino,
+    // Marathon with the pieces in pieces.json
+    CustomPieces,
+    // Marathon under the shipped NES and TGM-like rulesets
+    Nes,
+    Tgm,
+    // Marathon under the rules in rules.toml
+    HouseRules,
+    // Endless, slow, with undo
+    Zen,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 14] = [
+        Mode::Marathon,
+        Mode::Sprint,
+        Mode::Ultra,
+        Mode::Dig,
+        Mode::FourWide,
+        Mode::BigBoard,
+        Mode::Tall,
+        Mode::Pentomino,
+        Mode::Tromino,
+        Mode::CustomPieces,
+        Mode::Nes,
+        Mode::Tgm,
+        Mode::HouseRules,
+        Mode::Zen,
+    ];
+
+    // Used for file names and high score tables
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Marathon => "marathon",
+            Mode::Sprint => "sprint",
+            Mode::Ultra => "ultra",
+            Mode::Dig => "dig",
+            Mode::FourWide => "fourwide",
+            Mode::BigBoard => "bigboard",
+            Mode::Tall => "tall",
+            Mode::Pentomino => "pentomino",
+            Mode::Tromino => "tromino",
+            Mode::CustomPieces => "custom",
+            Mode::Nes => "nes",
+            Mode::Tgm => "tgm",
+            Mode::HouseRules => "house",
+            Mode::Zen => "zen",
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            Mode::Marathon => "Marathon",
+            Mode::Sprint => "Sprint",
+            Mode::Ultra => "Ultra",
+            Mode::Dig => "Dig",
+            Mode::FourWide => "4-Wide",
+            Mode::BigBoard => "Big Board",
+            Mode::Tall => "Tall",
+            Mode::Pentomino => "Pentomino",
+            Mode::Tromino => "Tromino",
+            Mode::CustomPieces => "Custom Pieces",
+            Mode::Nes => "NES",
+            Mode::Tgm => "TGM-ish",
+            Mode::HouseRules => "House Rules",
+            Mode::Zen => "Zen",
+        }
+    }
+
+    // Columns and rows of the board
+    fn board_size(&self) -> (usize, usize) {
+        match self {
+            Mode::FourWide => (4, game::STANDARD_HEIGHT),
+            Mode::BigBoard => (game::STANDARD_WIDTH * 2, game::STANDARD_HEIGHT),
+            Mode::Tall => (game::STANDARD_WIDTH, game::STANDARD_HEIGHT * 2),
+            _ => (game::STANDARD_WIDTH, game::STANDARD_HEIGHT),
+        }
+    }
+
+    fn pieces(&self, ctx: &mut Context) -> GameResult<PieceSet> {
+        match self {
+            Mode::Pentomino => Ok(PieceSet::pentominoes()),
+            Mode::Tromino => Ok(PieceSet::trominoes()),
+            Mode::CustomPieces => PieceSet::load(ctx, pieces::CUSTOM_PATH),
+            _ => Ok(PieceSet::standard()),
+        }
+    }
+
+    pub fn rules(&self, ctx: &mut Context) -> GameResult<Ruleset> {
+        match self {
+            Mode::Nes => Ruleset::load(ctx, rules::NES_PATH),
+            Mode::Tgm => Ruleset::load(ctx, rules::TGM_PATH),
+            Mode::HouseRules => Ruleset::load(ctx, rules::HOUSE_PATH),
+            Mode::Zen => Ruleset::load(ctx, rules::ZEN_PATH),
+            _ => Ok(Ruleset::default()),
+        }
+    }
+
+    fn ranking(&self) -> Ranking {
+        match self {
+            Mode::Sprint | Mode::Dig => Ranking::Time,
+            _ => Ranking::Score,
+        }
+    }
+
+    fn goal_reached(&self, game: &Game) -> bool {
+        match self {
+            Mode::Sprint => game.lines() >= SPRINT_LINES,
+            Mode::Ultra => game.elapsed() >= ULTRA_TIME,
+            Mode::Dig => garbage_left(game.tets()) == 0,
+            _ => false,
+        }
+    }
+
+    // Whole seconds left, counting the one under way, in modes against the
+    // clock
+    fn seconds_left(&self, game: &Game) -> Option<u64> {
+        match self {
+            Mode::Ultra => {
+                let left = ULTRA_TIME.checked_sub(game.elapsed()).unwrap_or_default();
+                Some(left.as_secs() + u64::from(left.subsec_nanos() > 0))
+            },
+            _ => None,
+        }
+    }
+
+    // What's left to do, shown while playing
+    fn progress(&self, game: &Game, keys: &KeyMap) -> Option<String> {
+        match self {
+            Mode::Sprint => Some(format!("Lines left: {}", SPRINT_LINES.saturating_sub(game.lines()))),
+            Mode::Ultra => Some(format!(
+                "Time left: {}",
+                scores::format_duration(ULTRA_TIME.checked_sub(game.elapsed()).unwrap_or_default()),
+            )),
+            Mode::Dig => Some(format!("Garbage left: {}", garbage_left(game.tets()))),
+            Mode::Zen => Some(format!(
+                "Undo: {}\nRedo: {}",
+                keys.prompt(Control::Undo),
+                keys.prompt(Control::Redo),
+            )),
+            _ => None,
+        }
+    }
+}
+
+fn garbage_left(tets: &Tets) -> usize {
+    tets.iter()
+        .filter(|row| row.contains(&Some(Block::Garbage)))
+        .count()
+}
+
+// Rows of garbage with one hole each, at the bottom of an empty board like
+// `tets`
+fn dig_board(tets: &Tets) -> Tets {
+    let mut rng = rand::thread_rng();
+    let mut tets = tets.empty();
+    for row in tets.height() - DIG_ROWS..tets.height() {
+        let hole = rng.gen_range(0, tets.width());
+        for col in (0..tets.width()).filter(|&col| col != hole) {
+            tets.set(row as i8, col as i8, Some(Block::Garbage));
+        }
+    }
+    tets
+}
+
+enum Overlay {
+    None,
+    // A saved game the player hasn't said whether to continue
+    Continue(Box<Snapshot>),
+    // Typing a name for a new high score
+    NameEntry(String),
+    // The high score table, with the entry just added
+    Leaderboard(Option<usize>),
+}
+
+pub struct Session {
+    game: Game,
+    person: Person,
+    // Taken before every placement, for rulesets that allow undo; going back
+    // keeps where the game was for redo, until the next placement
+    undo: Vec<Checkpoint>,
+    redo: Vec<Checkpoint>,
+    music: Music,
+    // The last second of a timed mode counted down
+    countdown: Option<u64>,
+    mode: Mode,
+    scores: HighScores,
+    overlay: Overlay,
+    was_over: bool,
+}
+
+impl Session {
+    pub fn new(ctx: &mut Context, mode: Mode) -> GameResult<Session> {
+        // Before the save, which names the pieces and is checked against
+        // the game
+        let pieces = mode.pieces(ctx)?;
+        let rules = mode.rules(ctx)?;
+        let (width, height) = mode.board_size();
+        let mut game = Game::with_rules(width, height, pieces, rules)?;
+        let settings = Settings::load(ctx);
+        let mut person = Person::new(ctx, &settings, &mut game)?;
+        let overlay = match save::load(ctx, mode.name(), &game) {
+            Ok(Some(snapshot)) => Overlay::Continue(Box::new(snapshot)),
+            Ok(None) => Overlay::None,
+            Err(err) => {
+                person.notify(format!("Save discarded: {}", err));
+                Overlay::None
+            },
+        };
+        let mut session = Session {
+            game,
+            person,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            music: settings.music(ctx),
+            countdown: None,
+            mode,
+            scores: HighScores::load(ctx),
+            overlay,
+            was_over: false,
+        };
+        session.start();
+        Ok(session)
+    }
+
+    // A new game beginning at a later level, for rulesets that allow it. A
+    // continued game keeps the level it began at.
+    pub fn starting_at(ctx: &mut Context, mode: Mode, level: usize) -> GameResult<Session> {
+        let mut session = Session::new(ctx, mode)?;
+        session.game.set_start_level(level);
+        Ok(session)
+    }
+
+    // A new game in the same mode, from game over or part way through
+    fn restart(&mut self) {
+        self.game.restart();
+        self.was_over = false;
+        self.start();
+    }
+
+    // Sets up the board for a new game
+    fn start(&mut self) {
+        self.countdown = None;
+        self.undo.clear();
+        self.redo.clear();
+        if let Mode::Dig = self.mode {
+            self.game.set_tets(dig_board(self.game.tets()));
+        }
+    }
+
+    fn game_over(&mut self, ctx: &mut Context) {
+        let (start, history) = self.game.replay();
+        // Games on boards fumen can't hold aren't recorded
+        let fumen = fumen::replay(start, history);
+        if let (false, Ok(fumen)) = (history.is_empty(), fumen) {
+            let replay = Replay {
+                mode: self.mode.title().to_string(),
+                date: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.as_secs()),
+                score: self.game.score(),
+                lines: self.game.lines(),
+                fumen,
+            };
+            if let Err(err) = Replays::record(ctx, replay) {
+                self.person.notify(format!("Couldn't save replay: {}", err));
+            }
+        }
+
+        let ranking = self.mode.ranking();
+        let goal_time = if ranking == Ranking::Time && !self.game.is_dead() { Some(self.game.elapsed()) } else { None };
+        if let Err(err) = Profiles::record(ctx, self.mode.name(), &self.game, goal_time) {
+            self.person.notify(format!("Couldn't save profile: {}", err));
+        }
+
+        // Timed modes only count games that reached the goal
+        if ranking == Ranking::Time && self.game.is_dead() {
+            return;
+        }
+        let entry = Entry::of(&self.game, String::new());
+        if self.scores.rank(self.mode.name(), &entry, ranking).is_some() {
+            self.overlay = Overlay::NameEntry(self.scores.last_name().to_string());
+        }
+    }
+
+    fn resume(&mut self, snapshot: Snapshot) {
+        self.game.resume(snapshot);
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    // Runs something that might lock a piece, keeping where the game was
+    // beforehand for undo if it does
+    fn play<F: FnOnce(&mut Self) -> R, R>(&mut self, f: F) -> R {
+        let before = self.game.rules().allow.undo && self.game.current_tet().is_some();
+        let checkpoint = if before { Some(self.game.checkpoint()) } else { None };
+        let placed = self.game.placed();
+        let result = f(self);
+        if let (Some(checkpoint), true) = (checkpoint, self.game.placed() > placed) {
+            self.undo.push(checkpoint);
+            self.redo.clear();
+        }
+        result
+    }
+
+    // Takes back the last placement
+    fn undo(&mut self) {
+        if self.game.current_tet().is_none() {
+            return;
+        }
+        if let Some(checkpoint) = self.undo.pop() {
+            self.redo.push(self.game.checkpoint());
+            self.game.restore(checkpoint);
+        }
+    }
+
+    // Puts back the last placement taken back
+    fn redo(&mut self) {
+        if self.game.current_tet().is_none() {
+            return;
+        }
+        if let Some(checkpoint) = self.redo.pop() {
+            self.undo.push(self.game.checkpoint());
+            self.game.restore(checkpoint);
+        }
+    }
+
+    // Restarting, undo and redo, which are up to the mode
+    fn control_down(&mut self, control: Option<Control>, repeat: bool) {
+        let undo = self.game.rules().allow.undo && !self.game.is_over() && !self.game.is_paused();
+        match control {
+            Some(Control::Restart) if !repeat => self.restart(),
+            Some(Control::Undo) if undo => self.undo(),
+            Some(Control::Redo) if undo => self.redo(),
+            _ => (),
+        }
+    }
+
+    fn submit(&mut self, ctx: &mut Context, name: String) {
+        let name = if name.trim().is_empty() { "Player".to_string() } else { name };
+        let entry = Entry::of(&self.game, name);
+        let rank = self.scores.add(self.mode.name(), entry, self.mode.ranking());
+        if let Err(err) = self.scores.save(ctx) {
+            self.person.notify(format!("Couldn't save scores: {}", err));
+        }
+        self.overlay = Overlay::Leaderboard(rank);
+    }
+}
+
+impl Scene for Session {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<Transition> {
+        if let Overlay::None = self.overlay {
+            self.play(|session| session.game.update(ctx))?;
+            self.person.update(ctx, &mut self.game);
+        }
+        let playing = matches!(self.overlay, Overlay::None) && !self.game.is_over() && !self.game.is_paused();
+        self.music.update(ctx, self.mode.name(), self.game.level(), playing);
+        let seconds = self.mode.seconds_left(&self.game).filter(|&seconds| seconds <= COUNTDOWN);
+        if playing && seconds.is_some() && seconds != self.countdown {
+            self.game.cue(Sound::Countdown);
+            self.countdown = seconds;
+        }
+        if !self.game.is_over() && self.mode.goal_reached(&self.game) {
+            self.game.finish();
+        }
+        let over = self.game.is_over();
+        if over && !self.was_over {
+            self.game_over(ctx);
+        }
+        self.was_over = over;
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, repeat: bool) -> Transition {
+        match std::mem::replace(&mut self.overlay, Overlay::None) {
+            Overlay::None => match keycode {
+                KeyCode::Escape => return Transition::Pop,
+                KeyCode::L if self.game.is_over() => self.overlay = Overlay::Leaderboard(None),
+                _ => {
+                    let control = self.play(|session| session.person.key_down(ctx, &mut session.game, keycode, repeat));
+                    self.control_down(control, repeat);
+                },
+            },
+            Overlay::Continue(snapshot) => match keycode {
+                KeyCode::Escape => {
+                    self.overlay = Overlay::Continue(snapshot);
+                    return Transition::Pop;
+                },
+                KeyCode::Y | KeyCode::Return => self.resume(*snapshot),
+                KeyCode::N => {
+                    if let Err(err) = save::remove(ctx, self.mode.name()) {
+                        self.person.notify(format!("Couldn't remove saved game: {}", err));
+                    }
+                },
+                _ => self.overlay = Overlay::Continue(snapshot),
+            },
+            Overlay::NameEntry(mut name) => match keycode {
+                KeyCode::Return => self.submit(ctx, name),
+                // Skip saving the score
+                KeyCode::Escape => (),
+                KeyCode::Back => {
+                    name.pop();
+                    self.overlay = Overlay::NameEntry(name);
+                },
+                _ => self.overlay = Overlay::NameEntry(name),
+            },
+            Overlay::Leaderboard(rank) => match keycode {
+                KeyCode::Return | KeyCode::Escape | KeyCode::L => (),
+                _ => self.overlay = Overlay::Leaderboard(rank),
+            },
+        }
+        Transition::None
+    }
+
+    fn text_input(&mut self, _ctx: &mut Context, character: char) {
+        if let Overlay::NameEntry(name) = &mut self.overlay {
+            let allowed = character.is_alphanumeric() || " -_.".contains(character);
+            if allowed && name.chars().count() < scores::MAX_NAME_LEN {
+                name.push(character);
+            }
+        }
+    }
+
+    fn key_up(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        self.person.key_up(&mut self.game, keycode);
+    }
+
+    // Prompts take A for yes or OK and B for no or skip
+    fn button_down(&mut self, ctx: &mut Context, button: Button, _id: GamepadId) -> Transition {
+        let keycode = match (&self.overlay, button) {
+            (Overlay::None, Button::Mode) => KeyCode::Escape,
+            (Overlay::None, Button::North) if self.game.is_over() => KeyCode::L,
+            (Overlay::None, _) => {
+                let control = self.play(|session| session.person.button_down(&mut session.game, button));
+                self.control_down(control, false);
+                return Transition::None;
+            },
+            (_, Button::South) | (_, Button::Start) => KeyCode::Return,
+            (Overlay::Continue(_), Button::East) => KeyCode::N,
+            (_, Button::East) | (_, Button::Mode) => KeyCode::Escape,
+            _ => return Transition::None,
+        };
+        self.key_down(ctx, keycode, KeyMods::NONE, false)
+    }
+
+    fn button_up(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) {
+        self.person.button_up(&mut self.game, button);
+    }
+
+    fn axis(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        self.person.axis(&mut self.game, axis, value);
+    }
+
+    // Saves the game for next time. Leaves an unanswered save alone.
+    fn leave(&mut self, ctx: &mut Context) -> GameResult {
+        if let Overlay::Continue(_) = self.overlay {
+            return Ok(());
+        }
+        save::store(ctx, self.mode.name(), &self.game)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.person.draw(ctx, &self.game)?;
+
+        if let Some(progress) = self.mode.progress(&self.game, self.person.keys()) {
+            let x = self.game.sidebar_x() + 10.0;
+            graphics::draw(
+                ctx,
+                &skin::text(progress, graphics::DEFAULT_FONT_SCALE),
+                (Point2f32::new(x, 150.0), skin::text_color()),
+            )?;
+        }
+
+        let center = self.game.board_center();
+        let prompt = |ctx: &mut Context, message: &str, y: f32| -> GameResult {
+            let text = skin::text(message, 24.0);
+            let offset = text.width(ctx) as f32 / 2.0;
+            graphics::draw(ctx, &text, (Point2f32::new(center - offset, y), skin::text_color()))
+        };
+        let finished = self.game.is_over() && !self.game.is_dead();
+        if finished && self.mode.ranking() == Ranking::Time {
+            let time = format!("Time: {}", scores::format_duration(self.game.elapsed()));
+            prompt(ctx, &time, 130.0)?;
+        }
+        match &self.overlay {
+            Overlay::None if self.game.is_over() => prompt(ctx, "Press L for High Scores", 60.0)?,
+            Overlay::None => (),
+            Overlay::Continue(_) => prompt(ctx, "Continue saved game? (Y/N)", 10.0)?,
+            Overlay::NameEntry(name) => {
+                prompt(ctx, "New High Score!", 40.0)?;
+                prompt(ctx, &format!("Name: {}_", name), 70.0)?;
+            },
+            Overlay::Leaderboard(rank) => {
+                let table = self.scores.table(self.mode.name());
+                scores::draw_table(ctx, self.mode.title(), table, *rank)?;
+            },
+        }
+        Ok(())
+    }
+
+    fn window_size(&self) -> (f32, f32) {
+        self.game.window_size()
+    }
+}
@@ -0,0 +1,546 @@
+// Player preferences, kept between runs, and the screen for changing them.
+
+use std::time::Duration;
+
+use ggez::{Context, GameResult};
+use ggez::event::{Button, GamepadId, KeyCode, KeyMods};
+use ggez::graphics;
+
+use serde::{Deserialize, Serialize};
+
+use crate::audio::{self, Music};
+use crate::effects::Effect;
+use crate::game::{self, Game};
+use crate::input::{Bindable, Bindings, ButtonMap, Control, KeyMap};
+use crate::person::{Hints, Person};
+use crate::save;
+use crate::scene::{self, Menu, Scene, Transition};
+use crate::skin;
+use crate::stats::Metric;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+const SETTINGS_PATH: &str = "/settings.json";
+const SETTINGS_VERSION: u64 = 1;
+
+// Missing fields take their defaults, so new settings don't need a new
+// version
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    // Hints in scored modes, and when practicing setups, openers and
+    // puzzles
+    pub hints: Hints,
+    pub practice_hints: Hints,
+    // Auto-shift delay and repeat rate, in milliseconds
+    pub das: u64,
+    pub arr: u64,
+    // The same for the analog stick, and how far it has to be pushed, in
+    // percent
+    pub stick_das: u64,
+    pub stick_arr: u64,
+    pub deadzone: u64,
+    pub keys: KeyMap,
+    pub buttons: ButtonMap,
+    // Statistics shown beside the board, in this order
+    pub hud: Vec<Metric>,
+    // Volumes in percent, and the sound pack played
+    pub sfx_volume: u64,
+    pub music_volume: u64,
+    pub sound_pack: String,
+    // Visual effects turned off
+    pub effects_off: Vec<Effect>,
+    pub skin: String,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            hints: Hints::Off,
+            practice_hints: Hints::Current,
+            das: Game::MOVE_WAIT.as_millis() as u64,
+            arr: Game::MOVE_INTERVAL.as_millis() as u64,
+            stick_das: Person::STICK_WAIT.as_millis() as u64,
+            stick_arr: Person::STICK_INTERVAL.as_millis() as u64,
+            deadzone: (Person::DEADZONE * 100.0) as u64,
+            keys: KeyMap::default(),
+            buttons: ButtonMap::default(),
+            hud: Metric::defaults(),
+            sfx_volume: 80,
+            music_volume: 60,
+            sound_pack: audio::DEFAULT_PACK.to_string(),
+            effects_off: Vec::new(),
+            skin: skin::DEFAULT_SKIN.to_string(),
+        }
+    }
+}
+
+impl Settings {
+    // The stored settings; an unreadable file is reported and replaced
+    pub fn load(ctx: &mut Context) -> Settings {
+        save::load_or_default(ctx, SETTINGS_PATH, SETTINGS_VERSION)
+    }
+
+    pub fn save(&self, ctx: &mut Context) -> GameResult {
+        save::write_versioned(ctx, SETTINGS_PATH, SETTINGS_VERSION, self)
+    }
+
+    // What the game itself shows and plays. The rest is the player's own,
+    // see `Person`.
+    pub fn apply(&self, game: &mut Game) {
+        game.set_handling(Duration::from_millis(self.das), Duration::from_millis(self.arr));
+        game.set_hud(self.hud.clone());
+        game.set_sounds(&self.sound_pack, self.sfx_volume as f32 / 100.0);
+        game.set_effects_off(self.effects_off.clone());
+    }
+
+    pub fn music(&self, ctx: &mut Context) -> Music {
+        Music::new(ctx, &self.sound_pack, self.music_volume as f32 / 100.0)
+    }
+}
+
+pub struct SettingsScreen {
+    settings: Settings,
+    menu: Menu,
+    // Sound packs and skins to choose from
+    packs: Vec<String>,
+    skins: Vec<String>,
+}
+
+impl SettingsScreen {
+    const HINTS: usize = 0;
+    const PRACTICE_HINTS: usize = 1;
+    const DAS: usize = 2;
+    const ARR: usize = 3;
+    const STICK_DAS: usize = 4;
+    const STICK_ARR: usize = 5;
+    const DEADZONE: usize = 6;
+    const SFX_VOLUME: usize = 7;
+    const MUSIC_VOLUME: usize = 8;
+    const SOUND_PACK: usize = 9;
+    const SKIN: usize = 10;
+    const HUD: usize = 11;
+    const EFFECTS: usize = 12;
+    const CONTROLS: usize = 13;
+    const BACK: usize = 14;
+
+    pub fn new(ctx: &mut Context) -> SettingsScreen {
+        let mut screen = SettingsScreen {
+            settings: Settings::load(ctx),
+            menu: Menu::new("Settings", &["", "", "", "", "", "", "", "", "", "", "", "Sidebar stats", "Effects", "Controls", "Back"]),
+            packs: audio::packs(ctx),
+            skins: skin::skins(ctx),
+        };
+        screen.update_labels();
+        screen
+    }
+
+    fn update_labels(&mut self) {
+        let hints = |hints| match hints {
+            Hints::Off => "Off",
+            Hints::Current => "Current piece",
+            Hints::WithHold => "With hold",
+        };
+        self.menu.set_item(Self::HINTS, format!("Hints: {}", hints(self.settings.hints)));
+        self.menu.set_item(Self::PRACTICE_HINTS, format!("Practice hints: {}", hints(self.settings.practice_hints)));
+        self.menu.set_item(Self::DAS, format!("DAS: {} ms", self.settings.das));
+        self.menu.set_item(Self::ARR, format!("ARR: {} ms", self.settings.arr));
+        self.menu.set_item(Self::STICK_DAS, format!("Stick DAS: {} ms", self.settings.stick_das));
+        self.menu.set_item(Self::STICK_ARR, format!("Stick ARR: {} ms", self.settings.stick_arr));
+        self.menu.set_item(Self::DEADZONE, format!("Stick deadzone: {}%", self.settings.deadzone));
+        self.menu.set_item(Self::SFX_VOLUME, format!("Sound effects: {}%", self.settings.sfx_volume));
+        self.menu.set_item(Self::MUSIC_VOLUME, format!("Music: {}%", self.settings.music_volume));
+        self.menu.set_item(Self::SOUND_PACK, format!("Sound pack: {}", self.settings.sound_pack));
+        self.menu.set_item(Self::SKIN, format!("Skin: {}", self.settings.skin));
+    }
+
+    // Left and right change the selected setting
+    fn adjust(&mut self, up: bool) {
+        let step = |value: u64, by: u64, min: u64, max: u64| {
+            if up { (value + by).min(max) } else { value.saturating_sub(by).max(min) }
+        };
+        match self.menu.selected() {
+            Self::HINTS => self.settings.hints = self.settings.hints.next(),
+            Self::PRACTICE_HINTS => self.settings.practice_hints = self.settings.practice_hints.next(),
+            Self::DAS => self.settings.das = step(self.settings.das, 10, 50, 500),
+            Self::ARR => self.settings.arr = step(self.settings.arr, 5, 0, 200),
+            Self::STICK_DAS => self.settings.stick_das = step(self.settings.stick_das, 10, 50, 500),
+            Self::STICK_ARR => self.settings.stick_arr = step(self.settings.stick_arr, 5, 0, 200),
+            Self::DEADZONE => self.settings.deadzone = step(self.settings.deadzone, 5, 10, 90),
+            Self::SFX_VOLUME => self.settings.sfx_volume = step(self.settings.sfx_volume, 10, 0, 100),
+            Self::MUSIC_VOLUME => self.settings.music_volume = step(self.settings.music_volume, 10, 0, 100),
+            Self::SOUND_PACK => cycle(&mut self.settings.sound_pack, &self.packs, up),
+            Self::SKIN => cycle(&mut self.settings.skin, &self.skins, up),
+            _ => (),
+        }
+        self.update_labels();
+    }
+}
+
+impl Scene for SettingsScreen {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        match keycode {
+            KeyCode::Escape => return Transition::Pop,
+            KeyCode::Left => self.adjust(false),
+            KeyCode::Right => self.adjust(true),
+            _ => match self.menu.key_down(keycode) {
+                Some(Self::BACK) => return Transition::Pop,
+                // These screens save their own changes, so save ours first
+                Some(choice @ Self::HUD) | Some(choice @ Self::EFFECTS) | Some(choice @ Self::CONTROLS) => {
+                    if let Err(err) = self.settings.save(ctx) {
+                        eprintln!("settings: couldn't save: {}", err);
+                    }
+                    return match choice {
+                        Self::HUD => Transition::Push(Box::new(HudScreen::new(ctx))),
+                        Self::EFFECTS => Transition::Push(Box::new(EffectsScreen::new(ctx))),
+                        _ => Transition::Push(Box::new(ControlsScreen::new(ctx))),
+                    };
+                },
+                Some(_) => self.adjust(true),
+                None => (),
+            },
+        }
+        Transition::None
+    }
+
+    fn leave(&mut self, ctx: &mut Context) -> GameResult {
+        self.settings.save(ctx)
+    }
+
+    fn resume(&mut self, ctx: &mut Context) {
+        self.settings = Settings::load(ctx);
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)
+    }
+}
+
+// Steps `current` through `options`, starting over at either end
+fn cycle(current: &mut String, options: &[String], up: bool) {
+    let count = options.len();
+    if count == 0 {
+        return;
+    }
+    let next = match options.iter().position(|option| option == current) {
+        Some(i) if up => (i + 1) % count,
+        Some(i) => (i + count - 1) % count,
+        None => 0,
+    };
+    *current = options[next].clone();
+}
+
+// Adds an input to a control unless it's reserved or taken, saying why not
+fn bind<I: Bindable>(bindings: &mut Bindings<I>, control: Control, input: I) -> Option<String> {
+    match bindings.control(input) {
+        _ if !input.is_bindable() => Some(format!("{} can't be bound", input.name())),
+        Some(bound) if bound == control => None,
+        Some(bound) => Some(format!("{} is already used for {}", input.name(), bound.title())),
+        None => {
+            bindings.bind(control, input);
+            None
+        },
+    }
+}
+
+// Keys and gamepad buttons for each control, a page each: Enter adds one,
+// Delete clears them
+pub struct ControlsScreen {
+    settings: Settings,
+    menu: Menu,
+    // Showing the gamepad page
+    gamepad: bool,
+    // The control waiting for an input
+    capturing: Option<Control>,
+    message: String,
+}
+
+impl ControlsScreen {
+    const DEFAULTS: usize = Control::ALL.len();
+    const BACK: usize = Control::ALL.len() + 1;
+
+    pub fn new(ctx: &mut Context) -> ControlsScreen {
+        let mut items = vec![String::new(); Control::ALL.len()];
+        items.push("Reset to defaults".to_string());
+        items.push("Back".to_string());
+        let mut screen = ControlsScreen {
+            settings: Settings::load(ctx),
+            menu: Menu::with_items("", items),
+            gamepad: false,
+            capturing: None,
+            message: String::new(),
+        };
+        screen.show_page(false);
+        screen
+    }
+
+    fn show_page(&mut self, gamepad: bool) {
+        self.gamepad = gamepad;
+        self.menu.set_title(if gamepad { "< Gamepad >" } else { "< Keyboard >" });
+        self.message = self.help().to_string();
+        self.update_labels();
+    }
+
+    fn help(&self) -> &'static str {
+        if self.gamepad {
+            "A: add button  X: clear"
+        } else {
+            "Enter: add key  Delete: clear"
+        }
+    }
+
+    fn update_labels(&mut self) {
+        for (i, &control) in Control::ALL.iter().enumerate() {
+            let inputs = if self.gamepad {
+                self.settings.buttons.describe(control)
+            } else {
+                self.settings.keys.describe(control)
+            };
+            self.menu.set_item(i, format!("{}: {}", control.title(), inputs));
+        }
+    }
+
+    fn clear_selected(&mut self) {
+        if let Some(&control) = Control::ALL.get(self.menu.selected()) {
+            if self.gamepad {
+                self.settings.buttons.clear(control);
+            } else {
+                self.settings.keys.clear(control);
+            }
+            self.update_labels();
+        }
+    }
+
+    fn captured(&mut self, refused: Option<String>) {
+        self.message = refused.unwrap_or_else(|| self.help().to_string());
+        self.update_labels();
+    }
+
+    // Controls with neither a key nor a button
+    fn unbound(&self) -> Vec<&'static str> {
+        Control::ALL.iter()
+            .filter(|&&control| {
+                self.settings.keys.inputs(control).is_empty() &&
+                    self.settings.buttons.inputs(control).is_empty()
+            })
+            .map(|control| control.title())
+            .collect()
+    }
+}
+
+impl Scene for ControlsScreen {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        if let Some(control) = self.capturing {
+            match keycode {
+                KeyCode::Escape => {
+                    self.capturing = None;
+                    self.message = self.help().to_string();
+                },
+                _ if !self.gamepad => {
+                    self.capturing = None;
+                    let refused = bind(&mut self.settings.keys, control, keycode);
+                    self.captured(refused);
+                },
+                // Waiting for a gamepad button
+                _ => (),
+            }
+            return Transition::None;
+        }
+        match keycode {
+            KeyCode::Escape => return Transition::Pop,
+            KeyCode::Left | KeyCode::Right => self.show_page(!self.gamepad),
+            KeyCode::Delete | KeyCode::Back => self.clear_selected(),
+            _ => match self.menu.key_down(keycode) {
+                Some(Self::DEFAULTS) => {
+                    if self.gamepad {
+                        self.settings.buttons = ButtonMap::default();
+                    } else {
+                        self.settings.keys = KeyMap::default();
+                    }
+                    self.update_labels();
+                },
+                Some(Self::BACK) => return Transition::Pop,
+                Some(i) => {
+                    let control = Control::ALL[i];
+                    self.capturing = Some(control);
+                    let input = if self.gamepad { "button" } else { "key" };
+                    self.message = format!("Press a {} for {} (Esc to cancel)", input, control.title());
+                },
+                None => (),
+            },
+        }
+        Transition::None
+    }
+
+    fn button_down(&mut self, ctx: &mut Context, button: Button, _id: GamepadId) -> Transition {
+        match self.capturing {
+            Some(control) if self.gamepad => {
+                self.capturing = None;
+                let refused = bind(&mut self.settings.buttons, control, button);
+                self.captured(refused);
+                Transition::None
+            },
+            None if self.gamepad && button == Button::West => {
+                self.clear_selected();
+                Transition::None
+            },
+            _ => match scene::menu_key(button) {
+                Some(keycode) => self.key_down(ctx, keycode, KeyMods::NONE, false),
+                None => Transition::None,
+            },
+        }
+    }
+
+    fn leave(&mut self, ctx: &mut Context) -> GameResult {
+        self.settings.save(ctx)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)?;
+        let mut text = skin::text(self.message.as_str(), graphics::DEFAULT_FONT_SCALE);
+        text.set_bounds(Point2f32::new(game::WINDOW_WIDTH - 20.0, 30.0), graphics::Align::Center);
+        graphics::draw(ctx, &text, (Point2f32::new(10.0, 98.0), skin::text_color()))?;
+        let unbound = self.unbound();
+        if unbound.is_empty() {
+            return Ok(());
+        }
+        scene::draw_errors(ctx, &[format!("Nothing bound to {}", unbound.join(", "))])
+    }
+}
+
+// Which statistics are shown beside the board: Enter turns one on or off
+pub struct HudScreen {
+    settings: Settings,
+    menu: Menu,
+}
+
+impl HudScreen {
+    const BACK: usize = Metric::ALL.len();
+
+    pub fn new(ctx: &mut Context) -> HudScreen {
+        let mut items = vec![String::new(); Metric::ALL.len()];
+        items.push("Back".to_string());
+        let mut screen = HudScreen {
+            settings: Settings::load(ctx),
+            menu: Menu::with_items("Sidebar stats", items),
+        };
+        screen.update_labels();
+        screen
+    }
+
+    fn update_labels(&mut self) {
+        for (i, metric) in Metric::ALL.iter().enumerate() {
+            let mark = if self.settings.hud.contains(metric) { "x" } else { " " };
+            self.menu.set_item(i, format!("[{}] {}", mark, metric.title()));
+        }
+    }
+
+    // Shown metrics keep the order they're listed in
+    fn toggle(&mut self, metric: Metric) {
+        let shown = self.settings.hud.contains(&metric);
+        self.settings.hud = Metric::ALL.iter()
+            .copied()
+            .filter(|&other| if other == metric { !shown } else { self.settings.hud.contains(&other) })
+            .collect();
+        self.update_labels();
+    }
+}
+
+impl Scene for HudScreen {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        match keycode {
+            KeyCode::Escape => return Transition::Pop,
+            _ => match self.menu.key_down(keycode) {
+                Some(Self::BACK) => return Transition::Pop,
+                Some(i) => self.toggle(Metric::ALL[i]),
+                None => (),
+            },
+        }
+        Transition::None
+    }
+
+    fn leave(&mut self, ctx: &mut Context) -> GameResult {
+        self.settings.save(ctx)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)
+    }
+}
+
+// Which visual effects are shown: Enter turns one on or off
+pub struct EffectsScreen {
+    settings: Settings,
+    menu: Menu,
+}
+
+impl EffectsScreen {
+    const BACK: usize = Effect::ALL.len();
+
+    pub fn new(ctx: &mut Context) -> EffectsScreen {
+        let mut items = vec![String::new(); Effect::ALL.len()];
+        items.push("Back".to_string());
+        let mut screen = EffectsScreen {
+            settings: Settings::load(ctx),
+            menu: Menu::with_items("Effects", items),
+        };
+        screen.update_labels();
+        screen
+    }
+
+    fn update_labels(&mut self) {
+        for (i, effect) in Effect::ALL.iter().enumerate() {
+            let mark = if self.settings.effects_off.contains(effect) { " " } else { "x" };
+            self.menu.set_item(i, format!("[{}] {}", mark, effect.title()));
+        }
+    }
+
+    fn toggle(&mut self, effect: Effect) {
+        let off = &mut self.settings.effects_off;
+        match off.iter().position(|&other| other == effect) {
+            Some(i) => {
+                off.remove(i);
+            },
+            None => off.push(effect),
+        }
+        self.update_labels();
+    }
+}
+
+impl Scene for EffectsScreen {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        match keycode {
+            KeyCode::Escape => return Transition::Pop,
+            _ => match self.menu.key_down(keycode) {
+                Some(Self::BACK) => return Transition::Pop,
+                Some(i) => self.toggle(Effect::ALL[i]),
+                None => (),
+            },
+        }
+        Transition::None
+    }
+
+    fn leave(&mut self, ctx: &mut Context) -> GameResult {
+        self.settings.save(ctx)
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)
+    }
+}
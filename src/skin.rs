@@ -0,0 +1,377 @@
+// Skins: how the game looks. A skin is a directory under /skins with a
+// skin.toml giving its colours and ghost style, and naming the images it
+// uses: a texture for each block or one atlas of them all, a background for
+// the board, and a font. Whatever a skin leaves out or can't be loaded is
+// drawn in flat colour, or in the built-in font.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::iter;
+use std::sync::Mutex;
+
+use ggez::{Context, GameError, GameResult};
+use ggez::filesystem;
+use ggez::graphics::{self, Color, DrawParam, Font, Image, Rect, Scale, Text, TextFragment};
+
+use lazy_static::lazy_static;
+use serde::Deserialize;
+
+use crate::game::{Block, TILE_SIZE};
+use crate::pieces::PieceSet;
+use crate::save;
+use crate::tet::TetType;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+pub const SKINS_DIR: &str = "/skins";
+pub const DEFAULT_SKIN: &str = "classic";
+const SKIN_FILE: &str = "skin.toml";
+// How much of a translucent ghost shows
+const GHOST_ALPHA: f32 = 0.3;
+
+lazy_static! {
+    // Fonts by path. ggez keeps every font it loads for the rest of the run,
+    // so each is only loaded once, however many games use it.
+    static ref FONTS: Mutex<HashMap<String, Font>> = Mutex::new(HashMap::new());
+    // The font and text colour of the skin chosen for screens without a game
+    static ref SCREEN_TEXT: Mutex<(Option<Font>, Color)> = Mutex::new((None, rgb(Palette::default().text)));
+}
+
+// Colours as [r, g, b], defaulting to the game's original look
+#[derive(Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Palette {
+    // The window behind everything
+    pub background: [u8; 3],
+    // The board, unless the skin has an image for it
+    pub board: [u8; 3],
+    // The hold and next boxes
+    pub frame: [u8; 3],
+    pub text: [u8; 3],
+    pub garbage: [u8; 3],
+    // Flat colours for pieces, by name, in place of their own
+    pub pieces: HashMap<String, [u8; 3]>,
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette {
+            background: [80, 80, 80],
+            board: [0, 0, 0],
+            frame: [255, 255, 255],
+            text: [255, 255, 255],
+            garbage: [130, 130, 130],
+            pieces: HashMap::new(),
+        }
+    }
+}
+
+fn rgb([r, g, b]: [u8; 3]) -> Color {
+    Color::from_rgb(r, g, b)
+}
+
+// How the ghost piece, showing where the piece would land, is drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GhostStyle {
+    // The skin's ghost image in every cell
+    Image,
+    // The edges of each cell, in the piece's colour
+    Outline,
+    // The piece's own blocks, faded
+    #[default]
+    Translucent,
+}
+
+// Square tiles side by side in one image, for the blocks named in `order`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Atlas {
+    image: String,
+    order: Vec<String>,
+}
+
+// skin.toml. Paths are from the skin's directory, and blocks are named by
+// their piece, or "G" for garbage, as in TBP.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SkinFile {
+    // An image for each block...
+    blocks: HashMap<String, String>,
+    // ...or an atlas of them. Single images win over tiles.
+    atlas: Option<Atlas>,
+    ghost: GhostStyle,
+    ghost_image: Option<String>,
+    // Stretched over the board
+    board: Option<String>,
+    font: Option<String>,
+    palette: Palette,
+}
+
+impl SkinFile {
+    fn load(ctx: &mut Context, dir: &str) -> GameResult<SkinFile> {
+        let path = format!("{}/{}", dir, SKIN_FILE);
+        let mut data = String::new();
+        filesystem::open(ctx, &path)?.read_to_string(&mut data)?;
+        toml::from_str(&data).map_err(|err| GameError::ConfigError(format!("{}: {}", path, err)))
+    }
+}
+
+// Every skin installed, from the game's resources and the user data
+// directory alike
+pub fn skins(ctx: &mut Context) -> Vec<String> {
+    save::list_dirs(ctx, SKINS_DIR)
+}
+
+// Makes `name` the skin screens without a game draw their text in, and
+// gives its window colour. Problems with the skin are left for the game to
+// report.
+pub fn choose(ctx: &mut Context, name: &str) -> Color {
+    let dir = format!("{}/{}", SKINS_DIR, name);
+    let file = SkinFile::load(ctx, &dir).unwrap_or_default();
+    let font = file.font.as_ref().and_then(|font| load_font(ctx, &format!("{}/{}", dir, font)));
+    *SCREEN_TEXT.lock().unwrap() = (font, rgb(file.palette.text));
+    rgb(file.palette.background)
+}
+
+// Text for screens without a game, in the chosen skin's font
+pub fn text<F: Into<TextFragment>>(fragment: F, size: f32) -> Text {
+    let (font, _) = *SCREEN_TEXT.lock().unwrap();
+    styled(fragment, font, size)
+}
+
+pub fn text_color() -> Color {
+    SCREEN_TEXT.lock().unwrap().1
+}
+
+fn styled<F: Into<TextFragment>>(fragment: F, font: Option<Font>, size: f32) -> Text {
+    let mut text = Text::new(fragment);
+    text.set_font(font.unwrap_or_default(), Scale::uniform(size));
+    text
+}
+
+fn block_name(block: Block) -> &'static str {
+    match block {
+        Block::Tet(tet_type) => tet_type.name(),
+        Block::Garbage => "G",
+    }
+}
+
+fn load_font(ctx: &mut Context, path: &str) -> Option<Font> {
+    let mut fonts = FONTS.lock().unwrap();
+    if let Some(&font) = fonts.get(path) {
+        return Some(font);
+    }
+    match Font::new(ctx, path) {
+        Ok(font) => {
+            fonts.insert(path.to_string(), font);
+            Some(font)
+        },
+        Err(err) => {
+            eprintln!("{}: couldn't load: {}", path, err);
+            None
+        },
+    }
+}
+
+fn load_image(ctx: &mut Context, path: &str) -> Option<Image> {
+    match Image::new(ctx, path) {
+        Ok(image) => Some(image),
+        Err(err) => {
+            eprintln!("{}: couldn't load: {}", path, err);
+            None
+        },
+    }
+}
+
+// A block's texture: an image, or the part of one given by `src`, as a
+// fraction of it
+#[derive(Clone)]
+struct Sprite {
+    image: Image,
+    src: Rect,
+}
+
+impl Sprite {
+    fn whole(image: Image) -> Sprite {
+        Sprite { image, src: Rect::one() }
+    }
+
+    // Drawn `size` pixels square with its top left at `dest`
+    fn draw(&self, ctx: &mut Context, dest: Point2f32, size: f32, alpha: f32) -> GameResult {
+        let scale = [
+            size / (self.image.width() as f32 * self.src.w),
+            size / (self.image.height() as f32 * self.src.h),
+        ];
+        graphics::draw(
+            ctx,
+            &self.image,
+            DrawParam::default()
+                .src(self.src)
+                .dest(dest)
+                .scale(scale)
+                .color(Color::new(1.0, 1.0, 1.0, alpha)),
+        )
+    }
+}
+
+pub struct Skin {
+    name: String,
+    palette: Palette,
+    sprites: HashMap<Block, Sprite>,
+    // The flat colour of each block, which outlines use too
+    colors: HashMap<Block, Color>,
+    ghost: GhostStyle,
+    ghost_sprite: Option<Sprite>,
+    board: Option<Image>,
+    font: Option<Font>,
+}
+
+impl Skin {
+    // The skin called `name`, with textures for the tetrominoes, which
+    // setups and replays use whatever the set, the pieces in `pieces`, and
+    // garbage. An unreadable skin is reported and drawn in flat colour.
+    pub fn load(ctx: &mut Context, name: &str, pieces: &PieceSet) -> GameResult<Skin> {
+        let dir = format!("{}/{}", SKINS_DIR, name);
+        let file = SkinFile::load(ctx, &dir).unwrap_or_else(|err| {
+            eprintln!("skin {:?}: {}", name, err);
+            SkinFile::default()
+        });
+        let path = |file: &str| format!("{}/{}", dir, file);
+
+        let mut tiles = HashMap::new();
+        if let Some(atlas) = &file.atlas {
+            if let Some(image) = load_image(ctx, &path(&atlas.image)) {
+                let count = (image.width() / image.height().max(1)) as usize;
+                if atlas.order.len() > count {
+                    eprintln!("{}: only has {} tiles", path(&atlas.image), count);
+                }
+                for (i, block) in atlas.order.iter().take(count).enumerate() {
+                    let width = 1.0 / count as f32;
+                    let src = Rect::new(width * i as f32, 0.0, width, 1.0);
+                    tiles.insert(block.as_str(), Sprite { image: image.clone(), src });
+                }
+            }
+        }
+
+        let mut sprites = HashMap::new();
+        let mut colors = HashMap::new();
+        let tets = TetType::STANDARD.iter().chain(pieces.pieces()).map(|&tet_type| Block::Tet(tet_type));
+        for block in tets.chain(iter::once(Block::Garbage)) {
+            let block_name = block_name(block);
+            let color = match (block, file.palette.pieces.get(block_name)) {
+                (Block::Garbage, _) => rgb(file.palette.garbage),
+                (_, Some(&color)) => rgb(color),
+                (Block::Tet(tet_type), None) => rgb(tet_type.shape().color),
+            };
+            // Pieces from a set can bring their own image, for skins that
+            // don't know them
+            let own = match block {
+                Block::Tet(tet_type) => tet_type.shape().sprite.as_ref(),
+                Block::Garbage => None,
+            };
+            let sprite = match file.blocks.get(block_name) {
+                Some(image) => load_image(ctx, &path(image)).map(Sprite::whole),
+                None => None,
+            };
+            let sprite = match sprite.or_else(|| tiles.get(block_name).cloned()) {
+                Some(sprite) => sprite,
+                None => match own.and_then(|own| load_image(ctx, own)) {
+                    Some(image) => Sprite::whole(image),
+                    None => Sprite::whole(Image::solid(ctx, TILE_SIZE as u16, color)?),
+                },
+            };
+            sprites.insert(block, sprite);
+            colors.insert(block, color);
+        }
+
+        let ghost_sprite = match (file.ghost, &file.ghost_image) {
+            (GhostStyle::Image, Some(image)) => load_image(ctx, &path(image)).map(Sprite::whole),
+            _ => None,
+        };
+        // A ghost image that's missing falls back to a faded piece
+        let ghost = match (file.ghost, &ghost_sprite) {
+            (GhostStyle::Image, None) => GhostStyle::Translucent,
+            (ghost, _) => ghost,
+        };
+        let board = file.board.as_ref().and_then(|board| load_image(ctx, &path(board)));
+        let font = file.font.as_ref().and_then(|font| load_font(ctx, &path(font)));
+
+        Ok(Skin {
+            name: name.to_string(),
+            palette: file.palette,
+            sprites,
+            colors,
+            ghost,
+            ghost_sprite,
+            board,
+            font,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn background(&self) -> Color {
+        rgb(self.palette.background)
+    }
+
+    pub fn frame(&self) -> Color {
+        rgb(self.palette.frame)
+    }
+
+    pub fn text_color(&self) -> Color {
+        rgb(self.palette.text)
+    }
+
+    // Text in the skin's font, `size` pixels high
+    pub fn text<F: Into<TextFragment>>(&self, fragment: F, size: f32) -> Text {
+        styled(fragment, self.font, size)
+    }
+
+    // Blocks the skin wasn't loaded for look like garbage
+    fn sprite(&self, block: Block) -> &Sprite {
+        self.sprites.get(&block).unwrap_or(&self.sprites[&Block::Garbage])
+    }
+
+    pub fn draw_board(&self, ctx: &mut Context, area: Rect) -> GameResult {
+        match &self.board {
+            Some(image) => graphics::draw(
+                ctx,
+                image,
+                DrawParam::default()
+                    .dest(area.point())
+                    .scale([area.w / image.width() as f32, area.h / image.height() as f32]),
+            ),
+            None => {
+                let board = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::fill(), area, rgb(self.palette.board))?;
+                graphics::draw(ctx, &board, DrawParam::default())
+            },
+        }
+    }
+
+    // A block `size` pixels square with its top left at `dest`
+    pub fn draw_block(&self, ctx: &mut Context, block: Block, dest: Point2f32, size: f32) -> GameResult {
+        self.sprite(block).draw(ctx, dest, size, 1.0)
+    }
+
+    // A cell of the ghost of a piece
+    pub fn draw_ghost(&self, ctx: &mut Context, tet_type: TetType, dest: Point2f32) -> GameResult {
+        let block = Block::Tet(tet_type);
+        match (self.ghost, &self.ghost_sprite) {
+            (GhostStyle::Image, Some(sprite)) => sprite.draw(ctx, dest, TILE_SIZE, 1.0),
+            (GhostStyle::Outline, _) => {
+                let color = self.colors.get(&block).copied().unwrap_or_else(|| block.color());
+                let outline = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::stroke(2.0),
+                    [dest.x + 1.0, dest.y + 1.0, TILE_SIZE - 2.0, TILE_SIZE - 2.0].into(),
+                    color,
+                )?;
+                graphics::draw(ctx, &outline, DrawParam::default())
+            },
+            _ => self.sprite(block).draw(ctx, dest, TILE_SIZE, GHOST_ALPHA),
+        }
+    }
+}
@@ -0,0 +1,252 @@
+// Running statistics for a game: pace, efficiency, and what kinds of clears
+// were made.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::game::Lock;
+use crate::scores;
+
+// Lines sent for each clear, as in guideline versus games. Clears of more
+// than four lines, with bigger pieces, send one a line.
+fn clear_attack(lines: usize, t_spin: bool) -> usize {
+    match (lines, t_spin) {
+        (_, true) => lines * 2,
+        (0, _) | (1, _) => 0,
+        (2, _) => 1,
+        (3, _) => 2,
+        (lines, _) => lines,
+    }
+}
+
+// Extra lines for each clear in a row after the first
+const COMBO_ATTACK: [usize; 12] = [0, 1, 1, 2, 2, 3, 3, 4, 4, 4, 5, 5];
+const PERFECT_CLEAR_ATTACK: usize = 10;
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+    pub pieces: usize,
+    pub keys: usize,
+    pub attack: usize,
+    // Clears that weren't T-spins, by lines: singles to tetrises, with
+    // anything bigger counted as a tetris
+    pub clears: [usize; 4],
+    // T-spins by lines cleared, none to three
+    pub t_spins: [usize; 4],
+    pub perfect_clears: usize,
+    // Clears in a row after the first, and tetrises or T-spins in a row
+    // after the first, while they last
+    pub combo: Option<usize>,
+    pub b2b: Option<usize>,
+    pub max_combo: usize,
+    pub max_b2b: usize,
+}
+
+impl Stats {
+    // Counts a locked piece, and whether it left the board empty
+    pub fn record(&mut self, lock: &Lock, perfect_clear: bool) {
+        self.pieces += 1;
+        if lock.t_spin {
+            self.t_spins[lock.lines.min(3)] += 1;
+        }
+        if lock.lines == 0 {
+            self.combo = None;
+            return;
+        }
+        if !lock.t_spin {
+            self.clears[lock.lines.min(4) - 1] += 1;
+        }
+
+        let combo = self.combo.map_or(0, |combo| combo + 1);
+        self.combo = Some(combo);
+        self.max_combo = self.max_combo.max(combo);
+        // A clear that isn't hard to make breaks the chain
+        let difficult = lock.t_spin || lock.lines >= 4;
+        self.b2b = if difficult { Some(self.b2b.map_or(0, |b2b| b2b + 1)) } else { None };
+        self.max_b2b = self.max_b2b.max(self.b2b.unwrap_or(0));
+
+        self.attack += clear_attack(lock.lines, lock.t_spin);
+        self.attack += COMBO_ATTACK[combo.min(COMBO_ATTACK.len() - 1)];
+        if self.b2b.is_some_and(|b2b| b2b > 0) {
+            self.attack += 1;
+        }
+        if perfect_clear {
+            self.perfect_clears += 1;
+            self.attack += PERFECT_CLEAR_ATTACK;
+        }
+    }
+
+    // Adds up another game's counts, keeping the longer chains
+    pub fn add(&mut self, other: &Stats) {
+        self.pieces += other.pieces;
+        self.keys += other.keys;
+        self.attack += other.attack;
+        for (total, count) in self.clears.iter_mut().zip(other.clears.iter()) {
+            *total += count;
+        }
+        for (total, count) in self.t_spins.iter_mut().zip(other.t_spins.iter()) {
+            *total += count;
+        }
+        self.perfect_clears += other.perfect_clears;
+        self.max_combo = self.max_combo.max(other.max_combo);
+        self.max_b2b = self.max_b2b.max(other.max_b2b);
+    }
+
+    // Pieces per second
+    pub fn pps(&self, elapsed: Duration) -> f64 {
+        per_second(self.pieces, elapsed)
+    }
+
+    // Keys pressed per piece
+    pub fn kpp(&self) -> f64 {
+        if self.pieces == 0 { 0.0 } else { self.keys as f64 / self.pieces as f64 }
+    }
+
+    // Attack per minute
+    pub fn apm(&self, elapsed: Duration) -> f64 {
+        per_second(self.attack, elapsed) * 60.0
+    }
+
+    // Everything, a line at a time, for the end of a game
+    pub fn breakdown(&self, elapsed: Duration) -> Vec<String> {
+        vec![
+            format!("Time: {}", scores::format_duration(elapsed)),
+            format!("Pieces: {}  PPS: {:.2}", self.pieces, self.pps(elapsed)),
+            format!("Keys: {}  KPP: {:.2}", self.keys, self.kpp()),
+            format!("Attack: {}  APM: {:.1}", self.attack, self.apm(elapsed)),
+            format!("Singles: {}  Doubles: {}", self.clears[0], self.clears[1]),
+            format!("Triples: {}  Tetrises: {}", self.clears[2], self.clears[3]),
+            format!("TSS: {}  TSD: {}  TST: {}", self.t_spins[1], self.t_spins[2], self.t_spins[3]),
+            format!("Max combo: {}  Max B2B: {}", self.max_combo, self.max_b2b),
+        ]
+    }
+}
+
+fn per_second(count: usize, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 { count as f64 / secs } else { 0.0 }
+}
+
+// What can be shown beside the board while playing
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Metric {
+    Time,
+    Pieces,
+    Pps,
+    Kpp,
+    Apm,
+    Attack,
+    MaxCombo,
+    MaxB2b,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 8] = [
+        Metric::Time,
+        Metric::Pieces,
+        Metric::Pps,
+        Metric::Kpp,
+        Metric::Apm,
+        Metric::Attack,
+        Metric::MaxCombo,
+        Metric::MaxB2b,
+    ];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Metric::Time => "Time",
+            Metric::Pieces => "Pieces",
+            Metric::Pps => "PPS",
+            Metric::Kpp => "KPP",
+            Metric::Apm => "APM",
+            Metric::Attack => "Attack",
+            Metric::MaxCombo => "Max combo",
+            Metric::MaxB2b => "Max B2B",
+        }
+    }
+
+    pub fn show(self, stats: &Stats, elapsed: Duration) -> String {
+        let value = match self {
+            Metric::Time => scores::format_duration(elapsed),
+            Metric::Pieces => stats.pieces.to_string(),
+            Metric::Pps => format!("{:.2}", stats.pps(elapsed)),
+            Metric::Kpp => format!("{:.2}", stats.kpp()),
+            Metric::Apm => format!("{:.1}", stats.apm(elapsed)),
+            Metric::Attack => stats.attack.to_string(),
+            Metric::MaxCombo => stats.max_combo.to_string(),
+            Metric::MaxB2b => stats.max_b2b.to_string(),
+        };
+        format!("{}: {}", self.title(), value)
+    }
+
+    pub fn defaults() -> Vec<Metric> {
+        vec![Metric::Time, Metric::Pps, Metric::Apm]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tet::{Tet, TetType};
+
+    fn lock(tet_type: TetType, lines: usize, t_spin: bool) -> Lock {
+        Lock { tet: Tet::spawn(tet_type, 10), lines, t_spin, presses: 0, optimal: None }
+    }
+
+    #[test]
+    fn attack_and_chains() {
+        let mut stats = Stats::default();
+        stats.record(&lock(TetType::I, 4, false), false);
+        assert_eq!(stats.attack, 4);
+        // Back to back, and a clear in a row
+        stats.record(&lock(TetType::T, 2, true), false);
+        assert_eq!(stats.attack, 4 + 4 + 1 + 1);
+        // A single keeps the combo going but breaks back to back
+        stats.record(&lock(TetType::L, 1, false), false);
+        assert_eq!(stats.attack, 10 + 1);
+        assert_eq!(stats.b2b, None);
+        stats.record(&lock(TetType::O, 0, false), false);
+        assert_eq!(stats.combo, None);
+        stats.record(&lock(TetType::I, 4, false), true);
+        assert_eq!(stats.attack, 11 + 4 + PERFECT_CLEAR_ATTACK);
+
+        assert_eq!(stats.pieces, 5);
+        assert_eq!(stats.clears, [1, 0, 0, 2]);
+        assert_eq!(stats.t_spins, [0, 0, 1, 0]);
+        assert_eq!(stats.perfect_clears, 1);
+        assert_eq!((stats.max_combo, stats.max_b2b), (2, 1));
+    }
+
+    #[test]
+    fn rates() {
+        let stats = Stats { pieces: 30, keys: 96, attack: 12, ..Stats::default() };
+        let elapsed = Duration::from_secs(20);
+        assert_eq!(stats.pps(elapsed), 1.5);
+        assert_eq!(stats.kpp(), 3.2);
+        assert_eq!(stats.apm(elapsed), 36.0);
+        assert_eq!(Metric::Pps.show(&stats, elapsed), "PPS: 1.50");
+        assert_eq!(Metric::Apm.show(&stats, elapsed), "APM: 36.0");
+
+        // Nothing to divide by yet
+        let none = Stats::default();
+        assert_eq!(none.pps(Duration::from_secs(0)), 0.0);
+        assert_eq!(none.kpp(), 0.0);
+        assert_eq!(stats.apm(Duration::from_secs(0)), 0.0);
+    }
+
+    #[test]
+    fn totals_keep_the_longest_chains() {
+        let mut total = Stats { pieces: 10, keys: 25, attack: 4, max_combo: 3, max_b2b: 0, ..Stats::default() };
+        total.clears[3] = 1;
+        let mut game = Stats { pieces: 5, keys: 12, attack: 6, max_combo: 1, max_b2b: 2, ..Stats::default() };
+        game.clears[3] = 1;
+        game.t_spins[2] = 2;
+        total.add(&game);
+        assert_eq!((total.pieces, total.keys, total.attack), (15, 37, 10));
+        assert_eq!(total.clears, [0, 0, 0, 2]);
+        assert_eq!(total.t_spins, [0, 0, 2, 0]);
+        assert_eq!((total.max_combo, total.max_b2b), (3, 2));
+    }
+}
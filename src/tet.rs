@@ -1,63 +1,325 @@
-use rand::seq::SliceRandom;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::RwLock;
 
-use crate::game::{self, Tets};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::game::Tets;
 
 type Point2 = ggez::nalgebra::Point2<i8>;
 type Point2f32 = ggez::nalgebra::Point2<f32>;
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-pub enum TetType {
-    I, J, L, O, S, T, Z,
+// What a piece looks like and how it turns. The seven tetrominoes are built
+// in; other sets (see `pieces`) add their own.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shape {
+    // Unique among all pieces; used in saves, fumen and TBP
+    pub name: String,
+    // (x, y) of each block in spawn orientation, y pointing down, inside the
+    // box the piece turns within
+    pub cells: Vec<[i8; 2]>,
+    // Side of that box
+    pub size: i8,
+    // The block the piece turns around, as other tools (TBP bots, fumen)
+    // describe it, in spawn orientation
+    pub center: [i8; 2],
+    #[serde(default)]
+    pub kicks: Kicks,
+    // Drawn as a plain square of this colour, unless the skin or `sprite`
+    // has an image for it
+    pub color: [u8; 3],
+    #[serde(default)]
+    pub sprite: Option<String>,
 }
 
-impl TetType {
-    pub fn blocks(&self) -> [Point2; 4] {
-        match self {
-            TetType::I => [[0, 1].into(), [1, 1].into(), [2, 1].into(), [3, 1].into()],
-            TetType::J => [[0, 0].into(), [0, 1].into(), [1, 1].into(), [2, 1].into()],
-            TetType::L => [[0, 1].into(), [1, 1].into(), [2, 1].into(), [2, 0].into()],
-            TetType::O => [[0, 0].into(), [0, 1].into(), [1, 0].into(), [1, 1].into()],
-            TetType::S => [[0, 1].into(), [1, 1].into(), [1, 0].into(), [2, 0].into()],
-            TetType::T => [[1, 0].into(), [0, 1].into(), [1, 1].into(), [2, 1].into()],
-            TetType::Z => [[0, 0].into(), [1, 0].into(), [1, 1].into(), [2, 1].into()],
+impl Shape {
+    pub const MAX_SIZE: i8 = 5;
+
+    fn check(&self) -> Result<(), String> {
+        let fail = |reason: &str| Err(format!("piece {:?}: {}", self.name, reason));
+        if self.name.is_empty() {
+            return Err("a piece has no name".to_string());
+        }
+        if self.size < 1 || self.size > Self::MAX_SIZE {
+            return fail(&format!("size must be 1 to {}", Self::MAX_SIZE));
+        }
+        let inside = |[x, y]: [i8; 2]| x >= 0 && x < self.size && y >= 0 && y < self.size;
+        if self.cells.is_empty() || !self.cells.iter().all(|&cell| inside(cell)) {
+            return fail("cells must be inside its box");
+        }
+        let cells: HashSet<[i8; 2]> = self.cells.iter().copied().collect();
+        if cells.len() != self.cells.len() {
+            return fail("cells repeat");
+        }
+        if !inside(self.center) {
+            return fail("center must be inside its box");
+        }
+        // Every block has to be reachable from the first through its sides
+        let mut reached = vec![self.cells[0]];
+        let mut i = 0;
+        while i < reached.len() {
+            let [x, y] = reached[i];
+            for next in [[x - 1, y], [x + 1, y], [x, y - 1], [x, y + 1]].iter() {
+                if cells.contains(next) && !reached.contains(next) {
+                    reached.push(*next);
+                }
+            }
+            i += 1;
+        }
+        if reached.len() != cells.len() {
+            return fail("cells must all be joined");
+        }
+        self.kicks.check().or_else(|reason| fail(&reason))
+    }
+
+    fn new(name: &str, cells: &[[i8; 2]], size: i8, center: [i8; 2], kicks: Kicks, color: [u8; 3]) -> Shape {
+        Shape {
+            name: name.to_string(),
+            cells: cells.to_vec(),
+            size,
+            center,
+            kicks,
+            color,
+            sprite: None,
         }
     }
+}
+
+// Wall kicks: offsets tried in order when a turn is blocked, in board
+// coordinates (y pointing down)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kicks {
+    // SRS, as for J, L, S, T and Z
+    #[default]
+    Srs,
+    // SRS for I
+    SrsI,
+    // Only the turn in place
+    None,
+    // Tests for each clockwise turn (0 -> R, R -> 2, 2 -> L, L -> 0) and each
+    // counterclockwise one (0 -> L, L -> 2, 2 -> R, R -> 0)
+    Custom { cw: Vec<Vec<[i8; 2]>>, ccw: Vec<Vec<[i8; 2]>> },
+}
+
+impl Kicks {
+    fn check(&self) -> Result<(), String> {
+        if let Kicks::Custom { cw, ccw } = self {
+            let complete = |tests: &[Vec<[i8; 2]>]| tests.len() == 4 && tests.iter().all(|t| !t.is_empty());
+            if !complete(cw) || !complete(ccw) {
+                return Err("custom kicks need tests for all four turns each way".to_string());
+            }
+        }
+        Ok(())
+    }
 
-    // Offset needed to center the piece in a 4x4 tile size area, in its
-    // initial rotation (used for display in holding and preview areas)
-    pub fn center_4x4(&self) -> Point2f32 {
-        match self {
-            TetType::I => [0.0, 0.5].into(),
-            TetType::O => [1.0, 1.0].into(),
-            _ => [0.5, 1.0].into(),
+    fn tests(&self, dir: RotationDir, from: Rot) -> &[[i8; 2]] {
+        // Counterclockwise tables are ordered 0 -> L, L -> 2, 2 -> R, R -> 0
+        let index = match dir {
+            RotationDir::Clockwise => from as usize,
+            RotationDir::CounterClockwise => (4 - from as usize) % 4,
+        };
+        match (self, dir) {
+            (Kicks::Srs, RotationDir::Clockwise) => &C_KICKS[index],
+            (Kicks::Srs, RotationDir::CounterClockwise) => &CC_KICKS[index],
+            (Kicks::SrsI, RotationDir::Clockwise) => &C_I_KICKS[index],
+            (Kicks::SrsI, RotationDir::CounterClockwise) => &CC_I_KICKS[index],
+            (Kicks::None, _) => &[[0, 0]],
+            (Kicks::Custom { cw, .. }, RotationDir::Clockwise) => &cw[index],
+            (Kicks::Custom { ccw, .. }, RotationDir::CounterClockwise) => &ccw[index],
         }
     }
 }
 
+// A shape once it's in use, with its turns worked out
+struct Piece {
+    shape: Shape,
+    // Blocks in each rotation, indexed by `Rot`
+    rotations: [Vec<Point2>; 4],
+    // Whether turning changes anything; O doesn't
+    turns: bool,
+}
+
+impl Piece {
+    fn new(shape: Shape) -> Piece {
+        let size = shape.size;
+        let mut blocks: Vec<Point2> = shape.cells.iter().map(|&[x, y]| Point2::new(x, y)).collect();
+        let mut rotations: [Vec<Point2>; 4] = Default::default();
+        for rotation in rotations.iter_mut() {
+            *rotation = blocks.clone();
+            // Clockwise about the middle of the box
+            blocks = blocks.iter().map(|block| Point2::new(size - 1 - block.y, block.x)).collect();
+        }
+        let sorted = |blocks: &[Point2]| {
+            let mut cells: Vec<(i8, i8)> = blocks.iter().map(|block| (block.x, block.y)).collect();
+            cells.sort_unstable();
+            cells
+        };
+        let turns = sorted(&rotations[0]) != sorted(&rotations[1]);
+        Piece { shape, rotations, turns }
+    }
+}
+
+fn standard_shapes() -> Vec<Shape> {
+    vec![
+        Shape::new("I", &[[0, 1], [1, 1], [2, 1], [3, 1]], 4, [1, 1], Kicks::SrsI, [0, 240, 240]),
+        Shape::new("J", &[[0, 0], [0, 1], [1, 1], [2, 1]], 3, [1, 1], Kicks::Srs, [0, 0, 240]),
+        Shape::new("L", &[[0, 1], [1, 1], [2, 1], [2, 0]], 3, [1, 1], Kicks::Srs, [240, 160, 0]),
+        Shape::new("O", &[[0, 0], [0, 1], [1, 0], [1, 1]], 2, [0, 1], Kicks::None, [240, 240, 0]),
+        Shape::new("S", &[[0, 1], [1, 1], [1, 0], [2, 0]], 3, [1, 1], Kicks::Srs, [0, 240, 0]),
+        Shape::new("T", &[[1, 0], [0, 1], [1, 1], [2, 1]], 3, [1, 1], Kicks::Srs, [160, 0, 240]),
+        Shape::new("Z", &[[0, 0], [1, 0], [1, 1], [2, 1]], 3, [1, 1], Kicks::Srs, [240, 0, 0]),
+    ]
+}
+
+lazy_static! {
+    // Every piece in use, indexed by `TetType`. Pieces are only ever added,
+    // so they can be handed out for the rest of the run.
+    static ref PIECES: RwLock<Vec<&'static Piece>> = RwLock::new(
+        standard_shapes().into_iter()
+            .map(|shape| &*Box::leak(Box::new(Piece::new(shape))))
+            .collect()
+    );
+}
+
+thread_local! {
+    // This thread's copy of `PIECES`, so looking up a piece (which moving
+    // one does constantly) doesn't take the lock. Refreshed when a piece it
+    // hasn't seen turns up.
+    static SEEN_PIECES: RefCell<Vec<&'static Piece>> = const { RefCell::new(Vec::new()) };
+}
+
+// A kind of piece. The tetrominoes have constants; others come from
+// `TetType::register`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TetType(u16);
+
 impl TetType {
-    pub fn batch() -> [TetType; 7] {
-        let mut rng = rand::thread_rng();
-        let mut batch = [
-            TetType::I,
-            TetType::J,
-            TetType::L,
-            TetType::O,
-            TetType::S,
-            TetType::T,
-            TetType::Z
-        ];
-        batch.shuffle(&mut rng);
-        batch
+    pub const I: TetType = TetType(0);
+    pub const J: TetType = TetType(1);
+    pub const L: TetType = TetType(2);
+    pub const O: TetType = TetType(3);
+    pub const S: TetType = TetType(4);
+    pub const T: TetType = TetType(5);
+    pub const Z: TetType = TetType(6);
+
+    pub const STANDARD: [TetType; 7] = [
+        TetType::I, TetType::J, TetType::L, TetType::O, TetType::S, TetType::T, TetType::Z,
+    ];
+
+    // The piece with this shape, adding it if it's new. Names are unique, so
+    // a different shape under a name already in use is refused.
+    pub fn register(shape: Shape) -> Result<TetType, String> {
+        shape.check()?;
+        let mut pieces = PIECES.write().unwrap();
+        if let Some(index) = pieces.iter().position(|piece| piece.shape.name == shape.name) {
+            if pieces[index].shape != shape {
+                return Err(format!("piece {:?} is already defined differently", shape.name));
+            }
+            return Ok(TetType(index as u16));
+        }
+        pieces.push(Box::leak(Box::new(Piece::new(shape))));
+        Ok(TetType(pieces.len() as u16 - 1))
+    }
+
+    pub fn named(name: &str) -> Option<TetType> {
+        PIECES.read().unwrap().iter()
+            .position(|piece| piece.shape.name == name)
+            .map(|index| TetType(index as u16))
+    }
+
+    fn piece(self) -> &'static Piece {
+        SEEN_PIECES.with(|seen| {
+            let mut seen = seen.borrow_mut();
+            if seen.len() <= self.0 as usize {
+                *seen = PIECES.read().unwrap().clone();
+            }
+            seen[self.0 as usize]
+        })
+    }
+
+    pub fn shape(self) -> &'static Shape {
+        &self.piece().shape
+    }
+
+    pub fn name(self) -> &'static str {
+        &self.shape().name
+    }
+
+    pub fn is_standard(self) -> bool {
+        TetType::STANDARD.contains(&self)
+    }
+
+    pub fn blocks(self, rot: Rot) -> &'static [Point2] {
+        &self.piece().rotations[rot as usize]
+    }
+
+    // Block offsets from the rotation center, with y pointing up. Other
+    // tools (TBP bots, fumen) describe pieces by this center.
+    pub fn center_offsets(self, rot: Rot) -> Vec<[i8; 2]> {
+        let shape = self.shape();
+        let [cx, cy] = shape.center;
+        shape.cells.iter()
+            .map(|&[x, y]| {
+                let (x, y) = (x - cx, cy - y);
+                match rot {
+                    Rot::Zero => [x, y],
+                    Rot::R    => [y, -x],
+                    Rot::Two  => [-x, -y],
+                    Rot::L    => [-y, x],
+                }
+            })
+            .collect()
+    }
+
+    // Where to draw the piece in its initial rotation, in blocks, so it sits
+    // in the middle of a 4x4 tile area, and how much to shrink it by to fit
+    // (used for display in holding and preview areas)
+    pub fn preview(self) -> (Point2f32, f32) {
+        let blocks = self.blocks(Rot::Zero);
+        let left = blocks.iter().map(|block| block.x).min().unwrap();
+        let right = blocks.iter().map(|block| block.x).max().unwrap();
+        let top = blocks.iter().map(|block| block.y).min().unwrap();
+        let bottom = blocks.iter().map(|block| block.y).max().unwrap();
+        let (width, height) = ((right - left + 1) as f32, (bottom - top + 1) as f32);
+        let scale = (4.0 / width.max(height)).min(1.0);
+        let area = 4.0 / scale;
+        let offset = Point2f32::new((area - width) / 2.0 - left as f32, (area - height) / 2.0 - top as f32);
+        (offset, scale)
+    }
+}
+
+impl fmt::Debug for TetType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+// Pieces are written by name, like TBP
+impl Serialize for TetType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
     }
 }
 
+impl<'de> Deserialize<'de> for TetType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        TetType::named(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown piece {:?}", name)))
+    }
+}
+
+#[derive(Clone, Copy)]
 pub enum RotationDir {
     Clockwise,
     CounterClockwise,
 }
 
-#[derive(Clone, Copy, Debug)]
-enum Rot {
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Rot {
     Zero = 0,
     R    = 1,
     Two  = 2,
@@ -100,7 +362,7 @@ const C_I_KICKS: [[[i8; 2]; 5]; 4] = [
 
 const CC_KICKS: [[[i8; 2]; 5]; 4] = [
     [[0, 0], [ 1, 0], [ 1, -1], [0,  2], [ 1,  2]], // 0 -> L
-    [[0, 0], [-1, 0], [-1,  1], [0, -2], [ 1, -2]], // L -> 2
+    [[0, 0], [-1, 0], [-1,  1], [0, -2], [-1, -2]], // L -> 2
     [[0, 0], [-1, 0], [-1, -1], [0,  2], [-1,  2]], // 2 -> R
     [[0, 0], [ 1, 0], [ 1,  1], [0, -2], [ 1, -2]], // R -> 0
 ];
@@ -112,39 +374,102 @@ const CC_I_KICKS: [[[i8; 2]; 5]; 4] = [
     [[0, 0], [ 2, 0], [-1, 0], [ 2, -1], [-1,  2]], // R -> 0
 ];
 
-#[derive(Clone)]
+// Half turns only try shifting up a row or sideways
+const HALF_KICKS: [[i8; 2]; 4] = [[0, 0], [0, -1], [1, 0], [-1, 0]];
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(into = "SavedTet", from = "SavedTet")]
 pub struct Tet {
     pub tet_type: TetType,
-    pub blocks: [Point2; 4],
     pub pos: Point2,
     rot: Rot,
 }
 
+// How a piece is saved: its blocks follow from the type and rotation
+#[derive(Serialize, Deserialize)]
+struct SavedTet {
+    tet_type: TetType,
+    rot: Rot,
+    x: i8,
+    y: i8,
+}
+
+impl From<Tet> for SavedTet {
+    fn from(tet: Tet) -> SavedTet {
+        SavedTet { tet_type: tet.tet_type, rot: tet.rot, x: tet.pos.x, y: tet.pos.y }
+    }
+}
+
+impl From<SavedTet> for Tet {
+    fn from(saved: SavedTet) -> Tet {
+        Tet::rotated(saved.tet_type, saved.rot, Point2::new(saved.x, saved.y))
+    }
+}
+
 impl Tet {
     pub fn new(tet_type: TetType, pos: Point2) -> Self {
-        Self {
-            tet_type,
-            pos,
-            blocks: tet_type.blocks(),
-            rot: Rot::Zero,
-        }
+        Self { tet_type, pos, rot: Rot::Zero }
+    }
+
+    // A new piece at the top of a board `width` columns wide, where the game
+    // spawns it
+    pub fn spawn(tet_type: TetType, width: usize) -> Self {
+        Self::new(tet_type, Point2::new((width as i8 - 4) / 2, 0))
+    }
+
+    // The piece turned clockwise from spawn to `rot`, with the top left of
+    // its bounding box at `pos`
+    pub fn rotated(tet_type: TetType, rot: Rot, pos: Point2) -> Self {
+        Self { tet_type, pos, rot }
     }
 
-    fn left(&self) -> i8 {
-        self.blocks.iter().min_by(|b1, b2| b1.x.cmp(&b2.x)).unwrap().x
+    // The piece with its SRS rotation center at column `x`, `y` rows up from
+    // the bottom of a board `height` rows high
+    pub fn from_center(tet_type: TetType, rot: Rot, x: i8, y: i8, height: usize) -> Self {
+        let offsets = tet_type.center_offsets(rot);
+        let mut tet = Self::rotated(tet_type, rot, Point2::new(0, 0));
+        let top = offsets.iter().map(|offset| offset[1]).max().unwrap();
+        let left = offsets.iter().map(|offset| offset[0]).min().unwrap();
+        let block_top = tet.blocks().iter().map(|block| block.y).min().unwrap();
+        let block_left = tet.blocks().iter().map(|block| block.x).min().unwrap();
+        tet.pos = Point2::new(
+            x + left - block_left,
+            height as i8 - 1 - (y + top) - block_top,
+        );
+        tet
     }
 
-    fn right(&self) -> i8 {
-        self.blocks.iter().max_by(|b1, b2| b1.x.cmp(&b2.x)).unwrap().x
+    // Where `from_center` would put the center: (column, rows up from the
+    // bottom of a board `height` rows high)
+    pub fn center(&self, height: usize) -> (i8, i8) {
+        let offsets = self.tet_type.center_offsets(self.rot);
+        let top = offsets.iter().map(|offset| offset[1]).max().unwrap();
+        let left = offsets.iter().map(|offset| offset[0]).min().unwrap();
+        let cells = self.cells();
+        let cell_top = cells.iter().map(|cell| cell.y).min().unwrap();
+        let cell_left = cells.iter().map(|cell| cell.x).min().unwrap();
+        (cell_left - left, height as i8 - 1 - cell_top - top)
     }
 
-    fn bottom(&self) -> i8 {
-        self.blocks.iter().max_by(|b1, b2| b1.y.cmp(&b2.y)).unwrap().y
+    pub fn rot(&self) -> Rot {
+        self.rot
+    }
+
+    // Blocks relative to `pos`
+    pub fn blocks(&self) -> &'static [Point2] {
+        self.tet_type.blocks(self.rot)
+    }
+
+    // Positions of the blocks on the board
+    pub fn cells(&self) -> Vec<Point2> {
+        self.blocks().iter()
+            .map(|block| Point2::new(self.pos.x + block.x, self.pos.y + block.y))
+            .collect()
     }
 
     pub fn at_bottom(&self, tets: &Tets) -> bool {
-        for block in self.blocks.iter() {
-            if self.pos.y + block.y + 1 >= game::TILES_HIGH as i8 ||
+        for block in self.blocks().iter() {
+            if self.pos.y + block.y + 1 >= tets.height() as i8 ||
                tets.at(self.pos.y + block.y + 1, self.pos.x + block.x).is_some() {
                 return true;
             }
@@ -153,8 +478,8 @@ impl Tet {
     }
 
     pub fn fall(&mut self, tets: &Tets) -> bool {
-        for block in self.blocks.iter() {
-            if self.pos.y + block.y + 1 >= game::TILES_HIGH as i8 ||
+        for block in self.blocks().iter() {
+            if self.pos.y + block.y + 1 >= tets.height() as i8 ||
                tets.at(self.pos.y + block.y + 1, self.pos.x + block.x).is_some() {
                 return false;
             }
@@ -164,7 +489,7 @@ impl Tet {
     }
 
     pub fn move_left(&mut self, tets: &Tets) -> bool {
-        for block in self.blocks.iter() {
+        for block in self.blocks().iter() {
             if self.pos.x + block.x == 0 ||
                tets.at(self.pos.y + block.y, self.pos.x + block.x - 1).is_some() {
                 return false;
@@ -175,8 +500,8 @@ impl Tet {
     }
 
     pub fn move_right(&mut self, tets: &Tets) -> bool {
-        for block in self.blocks.iter() {
-            if self.pos.x + block.x + 1 >= game::TILES_WIDE as i8 ||
+        for block in self.blocks().iter() {
+            if self.pos.x + block.x + 1 >= tets.width() as i8 ||
                tets.at(self.pos.y + block.y, self.pos.x + block.x + 1).is_some() {
                 return false;
             }
@@ -185,79 +510,54 @@ impl Tet {
         true
     }
 
-    fn rotate_blocks(&self, dir: &RotationDir) -> Vec<Point2> {
-        match dir {
-            RotationDir::Clockwise => {
-                match self.tet_type {
-                    TetType::I => {
-                        // rotate around center of a 4x4
-                        self.blocks.iter().map(|block| {
-                            Point2::new((3 - block.y as i8).abs() as i8, block.x)
-                        }).collect()
-                    }
-                    _ => {
-                        // rotate around center of a 3x3
-                        self.blocks.iter().map(|block| {
-                            Point2::new((2 - block.y as i8).abs() as i8, block.x)
-                        }).collect()
-                    }
-                }
-            }
-            RotationDir::CounterClockwise => {
-                match self.tet_type {
-                    TetType::I => {
-                        // rotate around center of a 4x4
-                        self.blocks.iter().map(|block| {
-                            Point2::new(block.y, (3 - block.x as i8).abs() as i8)
-                        }).collect()
-                    }
-                    _ => {
-                        // rotate around center of a 3x3
-                        self.blocks.iter().map(|block| {
-                            Point2::new(block.y, (2 - block.x as i8).abs() as i8)
-                        }).collect()
-                    }
-                }
-            }
-        }
+    pub fn rotate(&mut self, dir: RotationDir, tets: &Tets) -> bool {
+        let tests = self.tet_type.shape().kicks.tests(dir, self.rot);
+        self.turn(Some(dir), tests, tets)
     }
 
-    pub fn rotate(&mut self, dir: RotationDir, tets: &Tets) -> bool {
-        if let TetType::O = self.tet_type {
+    // Turns the piece around in one go, rather than as two quarter turns
+    // that might each need a kick
+    pub fn rotate_180(&mut self, tets: &Tets) -> bool {
+        self.turn(None, &HALF_KICKS, tets)
+    }
+
+    // A quarter turn, or a half turn with no direction, trying these kicks
+    // instead of the piece's own
+    pub fn rotate_with(&mut self, dir: Option<RotationDir>, kicks: &[[i8; 2]], tets: &Tets) -> bool {
+        self.turn(dir, kicks, tets)
+    }
+
+    fn turn(&mut self, dir: Option<RotationDir>, tests: &[[i8; 2]], tets: &Tets) -> bool {
+        if !self.tet_type.piece().turns {
             return true;
         }
-        let moved_blocks = self.rotate_blocks(&dir);
-        // check for collisions/blocks past edge of screen
-        let can_move = |dx: i8, dy: i8| {
-            for block in &moved_blocks {
-                let x = self.pos.x + block.x + dx;
-                let y = self.pos.y + block.y + dy;
-                if x < 0 || x >= game::TILES_WIDE as i8 || y >= game::TILES_HIGH as i8 ||
-                    tets.at(y, x).is_some() {
-                    return false;
-                }
-            }
-            true
-        };
-        let tests = match dir {
-            RotationDir::Clockwise => {
-                if let TetType::I = self.tet_type { &C_I_KICKS } else { &C_KICKS }
-            }
-            RotationDir::CounterClockwise => {
-                if let TetType::I = self.tet_type { &CC_I_KICKS } else { &CC_KICKS }
-            }
-        };
-        for test in tests[self.rot as usize].iter() {
-            if can_move(test[0], test[1]) {
-                for (i, block) in moved_blocks.iter().enumerate() {
-                    self.blocks[i] = *block;
-                }
+        let mut rot = self.rot;
+        match dir {
+            Some(RotationDir::Clockwise) => rot.c(),
+            Some(RotationDir::CounterClockwise) => rot.cc(),
+            None => {
+                rot.c();
+                rot.c();
+            },
+        }
+        let moved_blocks = self.tet_type.blocks(rot);
+        for test in tests.iter() {
+            if self.fits(moved_blocks, test[0], test[1], tets) {
                 self.pos.x += test[0];
                 self.pos.y += test[1];
-                self.rot.c();
+                self.rot = rot;
                 return true;
             }
         }
         false
     }
+
+    // Whether blocks moved by (dx, dy) stay on the board and clear of others
+    fn fits(&self, blocks: &[Point2], dx: i8, dy: i8, tets: &Tets) -> bool {
+        blocks.iter().all(|block| {
+            let x = self.pos.x + block.x + dx;
+            let y = self.pos.y + block.y + dy;
+            !tets.outside(y, x) && tets.at(y, x).is_none()
+        })
+    }
 }
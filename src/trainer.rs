@@ -0,0 +1,186 @@
+// Finesse trainer: one piece at a time on an empty board, with a target
+// outline that has to be reached in the fewest possible key presses before
+// moving on.
+
+use rand::seq::SliceRandom;
+
+use ggez::{Context, GameResult};
+use ggez::event::{Axis, Button, GamepadId, KeyCode, KeyMods};
+use ggez::graphics::{self, Color};
+
+use crate::game::{self, Game, Tets};
+use crate::input::Action;
+use crate::movegen::{self, Placement};
+use crate::person::Person;
+use crate::rules::Ruleset;
+use crate::scene::{Scene, Transition};
+use crate::settings::Settings;
+use crate::skin;
+use crate::tet::TetType;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+enum Outcome {
+    Clean,
+    Missed,
+    TooManyKeys { presses: usize, optimal: usize },
+}
+
+pub struct FinesseTrainer {
+    game: Game,
+    person: Person,
+    target: Placement,
+    // Value of `game.placed()` when the target was set
+    placed: usize,
+    streak: usize,
+    best_streak: usize,
+    outcome: Option<Outcome>,
+}
+
+fn random_piece() -> TetType {
+    *TetType::STANDARD.choose(&mut rand::thread_rng()).unwrap()
+}
+
+fn random_target(tet_type: TetType, rules: &Ruleset) -> Placement {
+    let mut placements = movegen::placements(&Tets::default(), tet_type, rules);
+    placements.shuffle(&mut rand::thread_rng());
+    placements.swap_remove(0)
+}
+
+impl FinesseTrainer {
+    pub fn new(ctx: &mut Context) -> GameResult<FinesseTrainer> {
+        let mut game = Game::new()?;
+        let settings = Settings::load(ctx);
+        let person = Person::new(ctx, &settings, &mut game)?;
+        let tet_type = random_piece();
+        let target = random_target(tet_type, game.rules());
+        let mut trainer = FinesseTrainer {
+            placed: game.placed(),
+            game,
+            person,
+            target,
+            streak: 0,
+            best_streak: 0,
+            outcome: None,
+        };
+        trainer.reset(tet_type);
+        Ok(trainer)
+    }
+
+    fn reset(&mut self, tet_type: TetType) {
+        self.game.set_tets(Tets::default());
+        self.game.spawn(tet_type);
+        self.placed = self.game.placed();
+    }
+
+    fn check(&mut self) {
+        let lock = match self.game.last_lock() {
+            Some(lock) => lock,
+            None => return,
+        };
+        let mut cells = lock.tet.cells();
+        cells.sort_by_key(|cell| (cell.y, cell.x));
+        let outcome = if cells != self.target.cells() {
+            Outcome::Missed
+        } else if lock.finesse_fault() {
+            Outcome::TooManyKeys {
+                presses: lock.presses,
+                optimal: lock.optimal.unwrap_or(0),
+            }
+        } else {
+            Outcome::Clean
+        };
+
+        let tet_type = if let Outcome::Clean = outcome {
+            self.streak += 1;
+            self.best_streak = self.best_streak.max(self.streak);
+            let tet_type = random_piece();
+            self.target = random_target(tet_type, self.game.rules());
+            tet_type
+        } else {
+            self.streak = 0;
+            self.target.tet.tet_type
+        };
+        self.outcome = Some(outcome);
+        self.reset(tet_type);
+    }
+}
+
+impl Scene for FinesseTrainer {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<Transition> {
+        if self.game.placed() != self.placed {
+            self.check();
+        }
+        self.game.update(ctx)?;
+        self.person.update(ctx, &mut self.game);
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, repeat: bool) -> Transition {
+        match (keycode, self.person.keys().action(keycode)) {
+            (KeyCode::Escape, _) => return Transition::Pop,
+            // Swapping pieces would dodge the drill
+            (_, Some(Action::Hold)) => (),
+            _ => {
+                self.person.key_down(ctx, &mut self.game, keycode, repeat);
+            },
+        }
+        Transition::None
+    }
+
+    fn key_up(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        self.person.key_up(&mut self.game, keycode);
+    }
+
+    fn button_down(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) -> Transition {
+        match (button, self.person.buttons().action(button)) {
+            (Button::Mode, _) => return Transition::Pop,
+            (_, Some(Action::Hold)) => (),
+            _ => {
+                self.person.button_down(&mut self.game, button);
+            },
+        }
+        Transition::None
+    }
+
+    fn button_up(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) {
+        self.person.button_up(&mut self.game, button);
+    }
+
+    fn axis(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        self.person.axis(&mut self.game, axis, value);
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.person.draw(ctx, &self.game)?;
+        game::draw_outline(ctx, &self.target.cells(), Color::from_rgb(80, 220, 120))?;
+
+        let keys = self.target.presses();
+        let mut lines = vec![
+            format!("Keys: {}", keys),
+            format!("Streak: {}", self.streak),
+            format!("Best: {}", self.best_streak),
+        ];
+        lines.push(match self.outcome {
+            Some(Outcome::Clean) => "Clean!".to_string(),
+            Some(Outcome::Missed) => "Missed".to_string(),
+            Some(Outcome::TooManyKeys { presses, optimal }) => {
+                format!("Used {}/{}", presses, optimal)
+            },
+            None => String::new(),
+        });
+        let x = self.game.sidebar_x() + 10.0;
+        for (i, line) in lines.iter().enumerate() {
+            graphics::draw(
+                ctx,
+                &skin::text(line.as_str(), graphics::DEFAULT_FONT_SCALE),
+                (Point2f32::new(x, 150.0 + 25.0 * i as f32), skin::text_color()),
+            )?;
+        }
+        Ok(())
+    }
+
+    fn window_size(&self) -> (f32, f32) {
+        self.game.window_size()
+    }
+}
@@ -0,0 +1,386 @@
+use ggez::{Context, GameResult};
+use ggez::event::{Axis, Button, GamepadId, KeyCode, KeyMods};
+use ggez::graphics::{self, DrawParam};
+use ggez::input::gamepad;
+
+use crate::game::{self, Game};
+use crate::input::{Control, KeyMap};
+use crate::person::Person;
+use crate::player::AutoPlayer;
+use crate::scene::{self, Scene, Transition};
+use crate::settings::Settings;
+use crate::skin;
+
+type Point2f32 = ggez::nalgebra::Point2<f32>;
+
+// The lobby's window, the size of two standard games
+pub const WINDOW_WIDTH: f32 = game::WINDOW_WIDTH * 2.0;
+pub const WINDOW_HEIGHT: f32 = game::WINDOW_HEIGHT;
+
+// What a person plays with
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    // The keyboard and every gamepad, when only one person is playing
+    Any,
+    Keyboard,
+    Gamepad(GamepadId),
+}
+
+impl Device {
+    fn has_keys(&self) -> bool {
+        matches!(self, Device::Any | Device::Keyboard)
+    }
+
+    fn has_gamepad(&self, id: GamepadId) -> bool {
+        match self {
+            Device::Any => true,
+            Device::Keyboard => false,
+            Device::Gamepad(pad) => *pad == id,
+        }
+    }
+}
+
+// Someone at this computer, the game they're playing and what with
+struct Seat {
+    game: Game,
+    person: Person,
+    device: Device,
+}
+
+// Both are boxed, as a game is big and the two are kept side by side
+enum Side {
+    Person(Box<Seat>),
+    Auto(Box<AutoPlayer>),
+}
+
+impl Side {
+    fn game(&self) -> &Game {
+        match self {
+            Side::Person(seat) => &seat.game,
+            Side::Auto(player) => player.game(),
+        }
+    }
+
+    fn game_mut(&mut self) -> &mut Game {
+        match self {
+            Side::Person(seat) => &mut seat.game,
+            Side::Auto(player) => player.game_mut(),
+        }
+    }
+
+    fn device(&self) -> Option<Device> {
+        match self {
+            Side::Person(seat) => Some(seat.device),
+            Side::Auto(_) => None,
+        }
+    }
+
+    fn draw(&self, ctx: &mut Context) -> GameResult {
+        match self {
+            Side::Person(seat) => seat.person.draw(ctx, &seat.game),
+            Side::Auto(player) => player.draw(ctx),
+        }
+    }
+}
+
+// Two games side by side, played by people, a bot or the AI; the first to
+// top out loses
+pub struct Versus {
+    sides: [Side; 2],
+    winner: Option<usize>,
+}
+
+impl Versus {
+    // One person against a bot or the AI, on the right
+    pub fn new(ctx: &mut Context, mut opponent: AutoPlayer) -> GameResult<Versus> {
+        let mut game = Game::new()?;
+        let settings = Settings::load(ctx);
+        let mut person = Person::new(ctx, &settings, &mut game)?;
+        person.forbid_hints();
+        // Only the player's own game is heard
+        opponent.game_mut().set_sounds(&settings.sound_pack, 0.0);
+        Ok(Versus {
+            sides: [Side::Person(Box::new(Seat { game, person, device: Device::Any })), Side::Auto(Box::new(opponent))],
+            winner: None,
+        })
+    }
+
+    // Two people at the same computer, each with their own device
+    pub fn local(ctx: &mut Context, devices: [Device; 2]) -> GameResult<Versus> {
+        let settings = Settings::load(ctx);
+        let mut side = |device| -> GameResult<Side> {
+            let mut game = Game::new()?;
+            let mut person = Person::new(ctx, &settings, &mut game)?;
+            person.forbid_hints();
+            Ok(Side::Person(Box::new(Seat { game, person, device })))
+        };
+        Ok(Versus {
+            sides: [side(devices[0])?, side(devices[1])?],
+            winner: None,
+        })
+    }
+
+    fn restart(&mut self) {
+        for side in self.sides.iter_mut() {
+            side.game_mut().restart();
+        }
+        self.winner = None;
+    }
+
+    fn is_paused(&self) -> bool {
+        self.sides.iter().any(|side| side.game().is_paused())
+    }
+
+    // Pausing either game pauses both
+    fn sync_pause(&mut self, from: usize) {
+        let paused = self.sides[from].game().is_paused();
+        for side in self.sides.iter_mut() {
+            if let Side::Person(seat) = side {
+                if seat.game.is_paused() != paused {
+                    seat.person.toggle_pause(&mut seat.game);
+                }
+            }
+        }
+    }
+
+    // Passes a press on to the games played with the device it came from.
+    // Restarting starts a new round for both sides; once the round is over,
+    // it's the only thing that does anything.
+    fn press<U, C, P>(&mut self, uses: U, control: C, mut press: P)
+    where
+        U: Fn(&Device) -> bool,
+        C: Fn(&Person) -> Option<Control>,
+        P: FnMut(&mut Game, &mut Person),
+    {
+        for i in 0..self.sides.len() {
+            let (game, person) = match &mut self.sides[i] {
+                Side::Person(seat) if uses(&seat.device) => (&mut seat.game, &mut seat.person),
+                _ => continue,
+            };
+            if let Some(Control::Restart) = control(person) {
+                self.restart();
+                return;
+            }
+            if self.winner.is_none() {
+                press(game, person);
+                self.sync_pause(i);
+            }
+        }
+    }
+
+    fn people(&mut self) -> impl Iterator<Item = (&mut Game, &mut Person, Device)> {
+        self.sides.iter_mut().filter_map(|side| match side {
+            Side::Person(seat) => Some((&mut seat.game, &mut seat.person, seat.device)),
+            Side::Auto(_) => None,
+        })
+    }
+}
+
+impl Scene for Versus {
+    fn update(&mut self, ctx: &mut Context) -> GameResult<Transition> {
+        if self.winner.is_some() {
+            return Ok(Transition::None);
+        }
+        let paused = self.is_paused();
+        for side in self.sides.iter_mut() {
+            match side {
+                Side::Person(seat) => {
+                    seat.game.update(ctx)?;
+                    seat.person.update(ctx, &mut seat.game);
+                },
+                Side::Auto(player) if !paused => player.update(ctx)?,
+                Side::Auto(_) => (),
+            }
+        }
+        if self.sides[0].game().is_dead() {
+            self.winner = Some(1);
+        } else if self.sides[1].game().is_dead() {
+            self.winner = Some(0);
+        }
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, repeat: bool) -> Transition {
+        if let KeyCode::Escape = keycode {
+            return Transition::Pop;
+        }
+        self.press(
+            Device::has_keys,
+            |person| person.keys().control(keycode).filter(|_| !repeat),
+            |game, person| {
+                person.key_down(ctx, game, keycode, repeat);
+            },
+        );
+        Transition::None
+    }
+
+    fn key_up(&mut self, _ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods) {
+        for (game, person, device) in self.people() {
+            if device.has_keys() {
+                person.key_up(game, keycode);
+            }
+        }
+    }
+
+    fn button_down(&mut self, _ctx: &mut Context, button: Button, id: GamepadId) -> Transition {
+        if let Button::Mode = button {
+            return Transition::Pop;
+        }
+        self.press(
+            |device| device.has_gamepad(id),
+            |person| person.buttons().control(button),
+            |game, person| {
+                person.button_down(game, button);
+            },
+        );
+        Transition::None
+    }
+
+    fn button_up(&mut self, _ctx: &mut Context, button: Button, id: GamepadId) {
+        for (game, person, device) in self.people() {
+            if device.has_gamepad(id) {
+                person.button_up(game, button);
+            }
+        }
+    }
+
+    fn axis(&mut self, _ctx: &mut Context, axis: Axis, value: f32, id: GamepadId) {
+        for (game, person, device) in self.people() {
+            if device.has_gamepad(id) {
+                person.axis(game, axis, value);
+            }
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        self.sides[0].draw(ctx)?;
+
+        let (left_width, _) = self.sides[0].game().window_size();
+        graphics::push_transform(ctx, Some(
+            DrawParam::default()
+                .dest(Point2f32::new(left_width, 0.0))
+                .to_matrix()
+        ));
+        graphics::apply_transformations(ctx)?;
+        self.sides[1].draw(ctx)?;
+        graphics::pop_transform(ctx);
+        graphics::apply_transformations(ctx)?;
+
+        if let Some(winner) = self.winner {
+            let restart = match &self.sides[0] {
+                Side::Person(seat) if matches!(seat.device, Device::Gamepad(_)) => seat.person.buttons().prompt(Control::Restart),
+                Side::Person(seat) => seat.person.keys().prompt(Control::Restart),
+                Side::Auto(_) => KeyMap::default().prompt(Control::Restart),
+            };
+            let both_people = self.sides.iter().all(|side| side.device().is_some());
+            let message = match winner {
+                _ if both_people => format!("Player {} Wins! Press {} to Restart", winner + 1, restart),
+                0 => format!("You Win! Press {} to Restart", restart),
+                _ => format!("Opponent Wins! Press {} to Restart", restart),
+            };
+            let result_display = skin::text(message, 32.0);
+            let offset = result_display.width(ctx) as f32 / 2.0;
+            graphics::draw(
+                ctx,
+                &result_display,
+                (Point2f32::new(self.window_size().0 / 2.0 - offset, 10.0), skin::text_color()),
+            )?;
+        }
+        Ok(())
+    }
+
+    // The two games side by side
+    fn window_size(&self) -> (f32, f32) {
+        let (left_width, left_height) = self.sides[0].game().window_size();
+        let (right_width, right_height) = self.sides[1].game().window_size();
+        (left_width + right_width, left_height.max(right_height))
+    }
+}
+
+// Two people pick what they'll play with, in player order, by pressing Enter
+// on the keyboard or A on a gamepad
+pub struct VersusLobby {
+    joined: Vec<(Device, String)>,
+    // Why the last game couldn't be started
+    error: Option<String>,
+}
+
+impl VersusLobby {
+    pub fn new() -> VersusLobby {
+        VersusLobby { joined: Vec::new(), error: None }
+    }
+
+    fn join(&mut self, ctx: &mut Context, device: Device, name: String) -> Transition {
+        if self.joined.iter().any(|(joined, _)| *joined == device) {
+            return Transition::None;
+        }
+        self.joined.push((device, name));
+        if self.joined.len() < 2 {
+            return Transition::None;
+        }
+        match Versus::local(ctx, [self.joined[0].0, self.joined[1].0]) {
+            Ok(versus) => Transition::Replace(Box::new(versus)),
+            Err(err) => {
+                self.error = Some(err.to_string());
+                self.joined.clear();
+                Transition::None
+            },
+        }
+    }
+}
+
+impl Default for VersusLobby {
+    fn default() -> VersusLobby {
+        VersusLobby::new()
+    }
+}
+
+impl Scene for VersusLobby {
+    fn update(&mut self, _ctx: &mut Context) -> GameResult<Transition> {
+        Ok(Transition::None)
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode, _keymod: KeyMods, _repeat: bool) -> Transition {
+        match keycode {
+            KeyCode::Escape => Transition::Pop,
+            KeyCode::Return | KeyCode::Space => self.join(ctx, Device::Keyboard, "Keyboard".to_string()),
+            _ => Transition::None,
+        }
+    }
+
+    fn button_down(&mut self, ctx: &mut Context, button: Button, id: GamepadId) -> Transition {
+        match button {
+            Button::East | Button::Mode => Transition::Pop,
+            Button::South | Button::Start => {
+                let name = gamepad::gamepad(ctx, id).name().to_string();
+                self.join(ctx, Device::Gamepad(id), name)
+            },
+            _ => Transition::None,
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        let title = skin::text("Local Versus", 40.0);
+        let offset = title.width(ctx) as f32 / 2.0;
+        graphics::draw(ctx, &title, (Point2f32::new(WINDOW_WIDTH / 2.0 - offset, 50.0), skin::text_color()))?;
+
+        for player in 0..2 {
+            let device = match self.joined.get(player) {
+                Some((_, name)) => name.as_str(),
+                None => "press Enter or A to join",
+            };
+            let text = skin::text(format!("Player {}: {}", player + 1, device), 24.0);
+            let offset = text.width(ctx) as f32 / 2.0;
+            graphics::draw(
+                ctx,
+                &text,
+                (Point2f32::new(WINDOW_WIDTH / 2.0 - offset, 150.0 + 40.0 * player as f32), skin::text_color()),
+            )?;
+        }
+        scene::draw_errors(ctx, self.error.as_slice())
+    }
+
+    fn window_size(&self) -> (f32, f32) {
+        (WINDOW_WIDTH, WINDOW_HEIGHT)
+    }
+}